@@ -1,3 +1,6 @@
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 fn main() {
     // Set rpath for macOS to find ONNX Runtime library in the same directory as the binary
     #[cfg(target_os = "macos")]
@@ -10,4 +13,66 @@ fn main() {
     {
         println!("cargo:rustc-link-arg=-Wl,-rpath,$ORIGIN");
     }
+
+    // Build metadata for `cyx version --full`
+    let target = std::env::var("TARGET").unwrap_or_else(|_| "unknown".to_string());
+    println!("cargo:rustc-env=CYX_BUILD_TARGET={}", target);
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = command_output(&rustc, &["--version"]);
+    println!("cargo:rustc-env=CYX_RUSTC_VERSION={}", rustc_version);
+
+    // Missing when building from a crates.io tarball, in which case cargo
+    // reruns this script on every build
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    let commit = command_output("git", &["rev-parse", "--short", "HEAD"]);
+    println!("cargo:rustc-env=CYX_GIT_HASH={}", commit);
+
+    // Reproducible builds pin the date through SOURCE_DATE_EPOCH
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    let epoch = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.parse::<u64>().ok())
+        .or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|elapsed| elapsed.as_secs())
+        });
+    let date = epoch.map_or_else(|| "unknown".to_string(), |secs| civil_date(secs / 86_400));
+    println!("cargo:rustc-env=CYX_BUILD_DATE={}", date);
+}
+
+/// YYYY-MM-DD for a count of days since 1970-01-01 (proleptic Gregorian calendar)
+fn civil_date(days: u64) -> String {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// First line of a command's stdout, or "unknown" if it can't be run
+fn command_output(program: &str, args: &[&str]) -> String {
+    Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|stdout| stdout.lines().next().map(str::to_string))
+        .filter(|line| !line.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
 }
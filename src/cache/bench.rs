@@ -0,0 +1,148 @@
+use super::{CacheStorage, SimpleEmbedder};
+use anyhow::Result;
+use std::time::{Duration, Instant};
+
+/// `search_similar` calls timed at each cache size
+const SEARCHES_PER_SIZE: usize = 20;
+
+const TOOLS: [&str; 10] = [
+    "nmap",
+    "hydra",
+    "sqlmap",
+    "gobuster",
+    "hashcat",
+    "john",
+    "nikto",
+    "ffuf",
+    "crackmapexec",
+    "responder",
+];
+const ACTIONS: [&str; 8] = [
+    "scan",
+    "brute force",
+    "enumerate",
+    "fingerprint",
+    "crack",
+    "fuzz",
+    "bypass waf on",
+    "dump credentials from",
+];
+const TARGETS: [&str; 9] = [
+    "ssh",
+    "smb",
+    "http login",
+    "mysql",
+    "ftp",
+    "ldap",
+    "rdp",
+    "kerberos",
+    "ntlm hashes",
+];
+
+/// Timings for one cache size
+#[derive(Debug, Clone, Copy)]
+pub struct SearchTiming {
+    pub cache_size: usize,
+    /// Mean time of one `search_similar` call
+    pub per_search: Duration,
+}
+
+/// Embedding throughput and similarity search latency of the active embedder
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub embedder: &'static str,
+    pub dimensions: usize,
+    pub embeddings: usize,
+    pub embed_elapsed: Duration,
+    pub searches: Vec<SearchTiming>,
+}
+
+impl BenchReport {
+    pub fn embeddings_per_sec(&self) -> f64 {
+        self.embeddings as f64 / self.embed_elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// A deterministic, varied pentest-style query; `i` picks the combination
+pub fn synthetic_query(i: usize) -> String {
+    format!(
+        "{} {} {} variant {}",
+        TOOLS[i % TOOLS.len()],
+        ACTIONS[(i / TOOLS.len()) % ACTIONS.len()],
+        TARGETS[(i / (TOOLS.len() * ACTIONS.len())) % TARGETS.len()],
+        i
+    )
+}
+
+/// Embed `queries` synthetic queries, then fill an in-memory cache up to each of
+/// `cache_sizes` (ascending) and time `search_similar` at that size
+pub fn run(queries: usize, cache_sizes: &[usize]) -> Result<BenchReport> {
+    let embedder = SimpleEmbedder::new(SimpleEmbedder::get_default_dimensions());
+    let texts: Vec<String> = (0..queries).map(synthetic_query).collect();
+
+    let started = Instant::now();
+    for text in &texts {
+        std::hint::black_box(embedder.embed(text));
+    }
+    let embed_elapsed = started.elapsed();
+
+    let mut sizes = cache_sizes.to_vec();
+    sizes.sort_unstable();
+    sizes.dedup();
+
+    let storage = CacheStorage::in_memory()?;
+    let mut stored = 0;
+    let mut searches = Vec::with_capacity(sizes.len());
+    for cache_size in sizes {
+        while stored < cache_size {
+            let query = synthetic_query(stored);
+            let hash = format!("bench-{}", stored);
+            storage.store(
+                &query,
+                &query,
+                &hash,
+                "benchmark response",
+                "bench",
+                "bench",
+            )?;
+            stored += 1;
+        }
+
+        let started = Instant::now();
+        for i in 0..SEARCHES_PER_SIZE {
+            std::hint::black_box(storage.search_similar(&synthetic_query(i * 7 + 3), 0.8, 1)?);
+        }
+        searches.push(SearchTiming {
+            cache_size,
+            per_search: started.elapsed() / SEARCHES_PER_SIZE as u32,
+        });
+    }
+
+    Ok(BenchReport {
+        embedder: SimpleEmbedder::MODEL_NAME,
+        dimensions: embedder.dimensions(),
+        embeddings: queries,
+        embed_elapsed,
+        searches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_reports_each_size_once() {
+        let report = run(50, &[20, 5, 20]).unwrap();
+        assert_eq!(report.embeddings, 50);
+        let sizes: Vec<usize> = report.searches.iter().map(|s| s.cache_size).collect();
+        assert_eq!(sizes, vec![5, 20]);
+        assert!(report.embeddings_per_sec() > 0.0);
+    }
+
+    #[test]
+    fn test_synthetic_queries_are_distinct() {
+        let queries: std::collections::HashSet<String> = (0..1000).map(synthetic_query).collect();
+        assert_eq!(queries.len(), 1000);
+    }
+}
@@ -0,0 +1,93 @@
+use super::CacheStorage;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// On-disk size of the cache directory, by what the files are for
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiskUsage {
+    /// SQLite database with its journal/WAL files
+    pub database: u64,
+    /// Leftover embedding models, if the directory exists
+    pub legacy_models: Option<u64>,
+    /// Everything else (update metadata, ...)
+    pub other: u64,
+}
+
+impl DiskUsage {
+    /// Measure the real file sizes under `cache_dir`; a missing directory is empty
+    pub fn measure(cache_dir: &Path) -> Result<Self> {
+        let db_path = CacheStorage::db_path(cache_dir);
+        let db_name = db_path.file_name().unwrap_or_default().to_string_lossy();
+        let models_dir = Self::legacy_models_dir(cache_dir);
+
+        let mut usage = Self {
+            legacy_models: models_dir.is_dir().then_some(0),
+            ..Self::default()
+        };
+        if !cache_dir.exists() {
+            return Ok(usage);
+        }
+
+        for entry in WalkDir::new(cache_dir) {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let size = entry.metadata()?.len();
+
+            let path = entry.path();
+            let is_database = path.parent() == Some(cache_dir)
+                && entry.file_name().to_string_lossy().starts_with(&*db_name);
+            if is_database {
+                usage.database += size;
+            } else if path.starts_with(&models_dir) {
+                *usage.legacy_models.get_or_insert(0) += size;
+            } else {
+                usage.other += size;
+            }
+        }
+
+        Ok(usage)
+    }
+
+    /// Embedding models downloaded by versions before 0.3.0, no longer used
+    pub fn legacy_models_dir(cache_dir: &Path) -> PathBuf {
+        cache_dir.join("models")
+    }
+
+    pub fn total(&self) -> u64 {
+        self.database + self.legacy_models.unwrap_or(0) + self.other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_measure_splits_by_purpose() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("queries.db"), vec![0u8; 4096]).unwrap();
+        std::fs::write(dir.join("queries.db-wal"), vec![0u8; 100]).unwrap();
+        std::fs::write(dir.join("update_metadata.json"), b"{}").unwrap();
+        std::fs::create_dir_all(dir.join("models/all-MiniLM")).unwrap();
+        std::fs::write(dir.join("models/all-MiniLM/model.onnx"), vec![0u8; 1000]).unwrap();
+
+        let usage = DiskUsage::measure(dir).unwrap();
+        assert_eq!(
+            usage,
+            DiskUsage {
+                database: 4196,
+                legacy_models: Some(1000),
+                other: 2,
+            }
+        );
+        assert_eq!(usage.total(), 5198);
+
+        let missing = DiskUsage::measure(&dir.join("missing")).unwrap();
+        assert_eq!(missing, DiskUsage::default());
+    }
+}
@@ -1,11 +1,149 @@
+use super::ollama_embedder::OllamaEmbedder;
+use crate::config::Config;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
+use std::time::Duration;
 
-pub struct Embedder {
+/// Turns normalized queries into vectors for similarity search
+pub trait Embedder: Send + Sync {
+    fn embed(&self, text: &str) -> Result<Vec<f32>>;
+
+    /// Identifier stored alongside embeddings so rows from other models can be skipped
+    fn model_name(&self) -> &str;
+
+    /// Length of the vectors `embed` returns
+    fn dimensions(&self) -> Result<usize>;
+}
+
+/// Where query embeddings come from (`cache.embedder_backend`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbedderBackend {
+    /// Built-in hashed word and trigram features; no model or service needed
+    #[default]
+    Simple,
+    /// A local Ollama server's embedding model (`cache.ollama_embedding_model`)
+    Ollama,
+}
+
+impl std::str::FromStr for EmbedderBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "simple" => Ok(Self::Simple),
+            "ollama" => Ok(Self::Ollama),
+            _ => anyhow::bail!("Invalid embedder backend. Options: simple, ollama"),
+        }
+    }
+}
+
+/// The embedder selected by `cache.embedder_backend`
+pub fn embedder_from_config(
+    config: &Config,
+    allow_invalid_certs: bool,
+) -> Result<Box<dyn Embedder>> {
+    Ok(match config.cache.embedder_backend {
+        EmbedderBackend::Simple => {
+            Box::new(SimpleEmbedder::new(SimpleEmbedder::get_default_dimensions()))
+        }
+        EmbedderBackend::Ollama => Box::new(OllamaEmbedder::new(
+            &config.ollama.base_url,
+            &config.cache.ollama_embedding_model,
+            Duration::from_secs(config.ollama.timeout_seconds),
+            allow_invalid_certs,
+        )?),
+    })
+}
+
+/// Metric used to compare query embeddings during similarity search
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SimilarityMetric {
+    #[default]
+    Cosine,
+    /// Dot product - equivalent to cosine for normalized embeddings, but cheaper
+    Dot,
+    /// Euclidean distance - lower is more similar, so the threshold is a maximum distance
+    Euclidean,
+}
+
+impl SimilarityMetric {
+    /// Score two embeddings with this metric
+    pub fn score(self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Self::Cosine => cosine_similarity(a, b),
+            Self::Dot => dot_product(a, b),
+            Self::Euclidean => euclidean_distance(a, b),
+        }
+    }
+
+    /// Whether lower scores mean more similar
+    pub fn is_distance(self) -> bool {
+        matches!(self, Self::Euclidean)
+    }
+
+    /// Whether a score passes the threshold, respecting the metric's direction
+    pub fn passes(self, score: f32, threshold: f32) -> bool {
+        if self.is_distance() {
+            score <= threshold
+        } else {
+            score >= threshold
+        }
+    }
+
+    /// Convert a cosine similarity into the equivalent threshold for this metric,
+    /// assuming unit-length embeddings (as produced by the embedders)
+    pub fn threshold_from_cosine(self, cosine: f32) -> f32 {
+        match self {
+            Self::Cosine | Self::Dot => cosine,
+            Self::Euclidean => (2.0 - 2.0 * cosine).max(0.0).sqrt(),
+        }
+    }
+
+    /// Human-readable form of a score for display
+    pub fn describe(self, score: f32) -> String {
+        if self.is_distance() {
+            format!("distance {:.2}", score)
+        } else {
+            format!("{:.0}%", score * 100.0)
+        }
+    }
+
+    /// Order scores best-first
+    pub fn compare(self, a: f32, b: f32) -> std::cmp::Ordering {
+        let ordering = b.partial_cmp(&a).unwrap_or(std::cmp::Ordering::Equal);
+        if self.is_distance() {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+impl std::str::FromStr for SimilarityMetric {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cosine" => Ok(Self::Cosine),
+            "dot" => Ok(Self::Dot),
+            "euclidean" => Ok(Self::Euclidean),
+            _ => anyhow::bail!("Invalid similarity metric. Options: cosine, dot, euclidean"),
+        }
+    }
+}
+
+pub struct SimpleEmbedder {
     dimensions: usize,
 }
 
-impl Embedder {
+impl SimpleEmbedder {
+    /// Identifier stored alongside embeddings so rows from other models can be skipped
+    pub const MODEL_NAME: &'static str = "tfidf-hash";
+
     pub fn new(dimensions: usize) -> Self {
         Self { dimensions }
     }
@@ -53,7 +191,7 @@ impl Embedder {
         hasher.finish() as usize
     }
 
-    fn normalize_vector(vec: &mut [f32]) {
+    pub(super) fn normalize_vector(vec: &mut [f32]) {
         let norm: f32 = vec.iter().map(|&x| x * x).sum::<f32>().sqrt();
         if norm > 0.0 {
             for x in vec.iter_mut() {
@@ -66,11 +204,29 @@ impl Embedder {
         self.dimensions
     }
 
+    pub fn model_name(&self) -> &str {
+        Self::MODEL_NAME
+    }
+
     pub const fn get_default_dimensions() -> usize {
         256
     }
 }
 
+impl Embedder for SimpleEmbedder {
+    fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        Ok(SimpleEmbedder::embed(self, text))
+    }
+
+    fn model_name(&self) -> &str {
+        Self::MODEL_NAME
+    }
+
+    fn dimensions(&self) -> Result<usize> {
+        Ok(self.dimensions)
+    }
+}
+
 pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     if a.len() != b.len() {
         return 0.0;
@@ -87,6 +243,26 @@ pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     dot_product / (norm_a * norm_b)
 }
 
+pub fn dot_product(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+
+    a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+}
+
+pub fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return f32::INFINITY;
+    }
+
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum::<f32>()
+        .sqrt()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -122,4 +298,66 @@ mod tests {
         let sim = cosine_similarity(&vec1, &vec2);
         assert_eq!(sim, 0.0);
     }
+
+    #[test]
+    fn test_dot_product() {
+        let sim = SimilarityMetric::Dot.score(&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]);
+        assert!((sim - 32.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_euclidean_distance() {
+        let dist = SimilarityMetric::Euclidean.score(&[0.0, 0.0], &[3.0, 4.0]);
+        assert!((dist - 5.0).abs() < 0.0001);
+        assert_eq!(
+            SimilarityMetric::Euclidean.score(&[1.0], &[1.0, 2.0]),
+            f32::INFINITY
+        );
+    }
+
+    #[test]
+    fn test_metrics_agree_on_normalized_vectors() {
+        let a = [0.6, 0.8];
+        let b = [0.8, 0.6];
+        let cosine = SimilarityMetric::Cosine.score(&a, &b);
+        let dot = SimilarityMetric::Dot.score(&a, &b);
+        assert!((cosine - dot).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_threshold_direction() {
+        assert!(SimilarityMetric::Cosine.passes(0.95, 0.9));
+        assert!(!SimilarityMetric::Cosine.passes(0.85, 0.9));
+        assert!(SimilarityMetric::Dot.passes(0.95, 0.9));
+
+        // Distances pass when they are at or below the threshold
+        assert!(SimilarityMetric::Euclidean.passes(0.2, 0.5));
+        assert!(!SimilarityMetric::Euclidean.passes(0.8, 0.5));
+    }
+
+    #[test]
+    fn test_compare_orders_best_first() {
+        let mut scores = vec![0.2, 0.9, 0.5];
+        scores.sort_by(|a, b| SimilarityMetric::Cosine.compare(*a, *b));
+        assert_eq!(scores, vec![0.9, 0.5, 0.2]);
+
+        scores.sort_by(|a, b| SimilarityMetric::Euclidean.compare(*a, *b));
+        assert_eq!(scores, vec![0.2, 0.5, 0.9]);
+    }
+
+    #[test]
+    fn test_threshold_from_cosine() {
+        assert_eq!(SimilarityMetric::Dot.threshold_from_cosine(0.9), 0.9);
+        let dist = SimilarityMetric::Euclidean.threshold_from_cosine(0.5);
+        assert!((dist - 1.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_metric_from_str() {
+        assert_eq!(
+            "Euclidean".parse::<SimilarityMetric>().unwrap(),
+            SimilarityMetric::Euclidean
+        );
+        assert!("manhattan".parse::<SimilarityMetric>().is_err());
+    }
 }
@@ -0,0 +1,47 @@
+use crate::util::write_atomic;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// The file inside the cache directory holding the last query asked
+pub fn path(cache_dir: &Path) -> PathBuf {
+    cache_dir.join("last_query")
+}
+
+/// Remember `query` as the last one asked, for `--retry-last`. Written before the
+/// provider is called, so a query that fails (or is never cached) can be retried.
+pub fn record(cache_dir: &Path, query: &str) -> Result<()> {
+    std::fs::create_dir_all(cache_dir).context("Failed to create cache directory")?;
+    // Queries can be sensitive, so only the owner may read it
+    write_atomic(&path(cache_dir), query, Some(0o600))
+}
+
+/// The last query asked, if any was recorded
+pub fn read(cache_dir: &Path) -> Result<Option<String>> {
+    let path = path(cache_dir);
+    match std::fs::read_to_string(&path) {
+        Ok(query) if query.trim().is_empty() => Ok(None),
+        Ok(query) => Ok(Some(query)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_record_and_read() {
+        let temp = TempDir::new().unwrap();
+        let cache_dir = temp.path().join("cache");
+        assert_eq!(read(&cache_dir).unwrap(), None);
+
+        record(&cache_dir, "nmap syn scan").unwrap();
+        record(&cache_dir, "kerberoasting\nwith impacket").unwrap();
+        assert_eq!(
+            read(&cache_dir).unwrap().as_deref(),
+            Some("kerberoasting\nwith impacket")
+        );
+    }
+}
@@ -1,7 +1,22 @@
+pub mod bench;
+pub mod disk_usage;
 pub mod embedder;
+pub mod last_query;
 pub mod normalizer;
+pub mod ollama_embedder;
 pub mod storage;
+pub mod tags;
 
-pub use embedder::{cosine_similarity, Embedder};
+pub use disk_usage::DiskUsage;
+pub use embedder::{
+    cosine_similarity, embedder_from_config, Embedder, EmbedderBackend, SimilarityMetric,
+    SimpleEmbedder,
+};
 pub use normalizer::{NormalizationConfig, QueryNormalizer};
-pub use storage::{CacheStats, CacheStorage, CachedQuery};
+pub use ollama_embedder::OllamaEmbedder;
+pub use storage::{
+    AgeBucket, CacheStats, CacheStorage, CachedQuery, DedupeSummary, ReindexSummary,
+    ThresholdTrial, ThresholdTuning, VacuumSummary, RESPONSE_DEDUPE_THRESHOLD,
+    TUNE_RESPONSE_AGREEMENT,
+};
+pub use tags::{classify, normalize_tag};
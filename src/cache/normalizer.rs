@@ -1,8 +1,10 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct NormalizationConfig {
     pub lowercase: bool,
     pub remove_punctuation: bool,
@@ -23,6 +25,22 @@ impl Default for NormalizationConfig {
     }
 }
 
+impl NormalizationConfig {
+    /// Config key suffixes (`normalize.<step>`) of the steps that are on
+    pub fn enabled_steps(&self) -> Vec<&'static str> {
+        [
+            ("lowercase", self.lowercase),
+            ("remove_punctuation", self.remove_punctuation),
+            ("expand_abbreviations", self.expand_abbreviations),
+            ("trim_whitespace", self.trim_whitespace),
+            ("remove_stopwords", self.remove_stopwords),
+        ]
+        .into_iter()
+        .filter_map(|(step, enabled)| enabled.then_some(step))
+        .collect()
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct AbbreviationsData {
     abbreviations: HashMap<String, String>,
@@ -40,9 +58,17 @@ pub struct QueryNormalizer {
 }
 
 impl QueryNormalizer {
+    /// Create a normalizer, reading data files from `CYX_DATA_DIR` when set
     pub fn new(config: NormalizationConfig) -> Result<Self> {
-        let abbreviations = Self::load_abbreviations()?;
-        let stopwords = Self::load_stopwords()?;
+        let data_dir = std::env::var_os(crate::config::DATA_DIR_ENV).map(PathBuf::from);
+        Self::with_data_dir(config, data_dir.as_deref())
+    }
+
+    /// Create a normalizer that prefers `<data_dir>/normalization/*.json` over the
+    /// data embedded at compile time. Missing files fall back to the embedded copy.
+    pub fn with_data_dir(config: NormalizationConfig, data_dir: Option<&Path>) -> Result<Self> {
+        let abbreviations = Self::load_abbreviations(data_dir)?;
+        let stopwords = Self::load_stopwords(data_dir)?;
 
         Ok(Self {
             config,
@@ -55,23 +81,79 @@ impl QueryNormalizer {
         Self::new(NormalizationConfig::default())
     }
 
-    fn load_abbreviations() -> Result<HashMap<String, String>> {
+    /// Normalization with the `normalize` steps and data directory from the app config
+    pub fn from_config(app_config: &crate::config::Config) -> Result<Self> {
+        Self::with_data_dir(
+            app_config.normalize.clone(),
+            app_config.data_dir().as_deref(),
+        )
+    }
+
+    /// Abbreviations and their expansions, sorted by abbreviation
+    pub fn abbreviations(&self) -> BTreeMap<&str, &str> {
+        self.abbreviations
+            .iter()
+            .map(|(short, expansion)| (short.as_str(), expansion.as_str()))
+            .collect()
+    }
+
+    /// Stopwords, sorted
+    pub fn stopwords(&self) -> BTreeSet<&str> {
+        self.stopwords.iter().map(String::as_str).collect()
+    }
+
+    /// Add or replace an abbreviation in `<data_dir>/normalization/abbreviations.json`.
+    /// That file replaces the built-in list, so a new one starts from the built-in
+    /// entries. Returns the path written.
+    pub fn add_abbreviation(data_dir: &Path, short: &str, expansion: &str) -> Result<PathBuf> {
+        let short = short.trim().to_lowercase();
+        let expansion = expansion.trim();
+        if !is_standalone_word(&short, &short) {
+            anyhow::bail!(
+                "'{}' would never be expanded: abbreviations must be a single word of letters \
+                 and digits (flags, paths, ports and numbers are left as typed)",
+                short
+            );
+        }
+        if expansion.is_empty() {
+            anyhow::bail!("The expansion of '{}' is empty", short);
+        }
+
+        let mut abbreviations: BTreeMap<String, String> = Self::load_abbreviations(Some(data_dir))?
+            .into_iter()
+            .collect();
+        abbreviations.insert(short, expansion.to_string());
+
+        let dir = data_dir.join("normalization");
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let path = dir.join("abbreviations.json");
+        let json =
+            serde_json::to_string_pretty(&serde_json::json!({ "abbreviations": abbreviations }))?;
+        crate::util::write_atomic(&path, json + "\n", None)?;
+        Ok(path)
+    }
+
+    fn load_abbreviations(data_dir: Option<&Path>) -> Result<HashMap<String, String>> {
         // Embed the abbreviations data at compile time
         const ABBREVIATIONS_JSON: &str =
             include_str!("../../data/normalization/abbreviations.json");
 
-        let data: AbbreviationsData = serde_json::from_str(ABBREVIATIONS_JSON)
-            .context("Failed to parse embedded abbreviations JSON")?;
+        let json = read_data_file(data_dir, "abbreviations.json")?;
+        let data: AbbreviationsData =
+            serde_json::from_str(json.as_deref().unwrap_or(ABBREVIATIONS_JSON))
+                .context("Failed to parse abbreviations JSON")?;
 
         Ok(data.abbreviations)
     }
 
-    fn load_stopwords() -> Result<HashSet<String>> {
+    fn load_stopwords(data_dir: Option<&Path>) -> Result<HashSet<String>> {
         // Embed the stopwords data at compile time
         const STOPWORDS_JSON: &str = include_str!("../../data/normalization/stopwords.json");
 
-        let data: StopwordsData = serde_json::from_str(STOPWORDS_JSON)
-            .context("Failed to parse embedded stopwords JSON")?;
+        let json = read_data_file(data_dir, "stopwords.json")?;
+        let data: StopwordsData = serde_json::from_str(json.as_deref().unwrap_or(STOPWORDS_JSON))
+            .context("Failed to parse stopwords JSON")?;
 
         Ok(data.stopwords.into_iter().collect())
     }
@@ -118,7 +200,9 @@ impl QueryNormalizer {
             // Remove trailing punctuation for matching
             let clean_word = word.trim_end_matches(|c: char| !c.is_alphanumeric());
 
-            if let Some(expansion) = self.abbreviations.get(clean_word) {
+            if !is_standalone_word(word, clean_word) {
+                expanded.push(word);
+            } else if let Some(expansion) = self.abbreviations.get(clean_word) {
                 expanded.push(expansion.as_str());
             } else {
                 expanded.push(word);
@@ -168,6 +252,44 @@ impl QueryNormalizer {
         normalized_query.hash(&mut hasher);
         format!("{:x}", hasher.finish())
     }
+
+    /// Cache key for a query answered in `language`; the same as `compute_hash`
+    /// for English (`None`), so existing entries keep their keys
+    pub fn compute_scoped_hash(&self, normalized_query: &str, language: Option<&str>) -> String {
+        match language {
+            Some(language) => self.compute_hash(&format!(
+                "{}\n@lang:{}",
+                normalized_query,
+                language.to_lowercase()
+            )),
+            None => self.compute_hash(normalized_query),
+        }
+    }
+}
+
+/// Read `<data_dir>/normalization/<name>` if a data directory is set and the file exists
+fn read_data_file(data_dir: Option<&Path>, name: &str) -> Result<Option<String>> {
+    let Some(path) = data_dir.map(|dir| dir.join("normalization").join(name)) else {
+        return Ok(None);
+    };
+
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    std::fs::read_to_string(&path)
+        .map(Some)
+        .with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Whether `token` is a plain word that may be expanded. Flags (`-sS`), paths, URLs,
+/// ports (`host:445`), addresses and bare numbers are command arguments whose
+/// meaning an expansion would change, so they pass through untouched.
+fn is_standalone_word(token: &str, word: &str) -> bool {
+    !token.starts_with('-')
+        && !token.contains(['/', ':'])
+        && word.chars().all(char::is_alphanumeric)
+        && word.chars().any(char::is_alphabetic)
 }
 
 #[cfg(test)]
@@ -213,6 +335,31 @@ mod tests {
         assert_eq!(result, "network mapper nmap stealth synchronize scan");
     }
 
+    #[test]
+    fn test_command_arguments_are_not_expanded() {
+        let mut normalizer = create_test_normalizer();
+        for key in ["-ss", "445", "10.0.0.0/24", "http://target", "target:445"] {
+            normalizer
+                .abbreviations
+                .insert(key.to_string(), "mangled".to_string());
+        }
+
+        assert_eq!(
+            normalizer
+                .normalize("nmap -sS -p 445 10.0.0.0/24 target:445")
+                .unwrap(),
+            "network mapper nmap -ss -p 445 10.0.0.0/24 target:445"
+        );
+        assert_eq!(
+            normalizer.normalize("sqli on http://target").unwrap(),
+            "sql injection on http://target"
+        );
+        assert_eq!(
+            normalizer.normalize("sqli, then privesc.").unwrap(),
+            "sql injection then privilege escalation"
+        );
+    }
+
     #[test]
     fn test_stopword_removal() {
         let normalizer = create_test_normalizer();
@@ -303,6 +450,76 @@ mod tests {
         assert_ne!(hash1, hash2);
     }
 
+    #[test]
+    fn test_scoped_hash_separates_languages() {
+        let normalizer = create_test_normalizer();
+        let normalized = normalizer.normalize("nmap syn scan").unwrap();
+
+        let english = normalizer.compute_scoped_hash(&normalized, None);
+        assert_eq!(english, normalizer.compute_hash(&normalized));
+        assert_ne!(
+            english,
+            normalizer.compute_scoped_hash(&normalized, Some("Spanish"))
+        );
+        assert_ne!(
+            normalizer.compute_scoped_hash(&normalized, Some("Spanish")),
+            normalizer.compute_scoped_hash(&normalized, Some("German"))
+        );
+    }
+
+    #[test]
+    fn test_config_toggles_change_hash() {
+        let mut app_config = crate::config::Config::default();
+        let expanded = QueryNormalizer::from_config(&app_config).unwrap();
+        let expanded_hash = expanded.compute_hash(&expanded.normalize("arp spoofing").unwrap());
+
+        app_config.normalize.expand_abbreviations = false;
+        let literal = QueryNormalizer::from_config(&app_config).unwrap();
+        let literal_normalized = literal.normalize("arp spoofing").unwrap();
+        assert_eq!(literal_normalized, "arp spoofing");
+        assert_ne!(literal.compute_hash(&literal_normalized), expanded_hash);
+    }
+
+    #[test]
+    fn test_partial_normalize_table_keeps_defaults() {
+        let config: crate::config::Config = toml::from_str(
+            "provider = \"groq\"\n[api_keys]\n[normalize]\nremove_stopwords = false\n",
+        )
+        .unwrap();
+        assert!(!config.normalize.remove_stopwords);
+        assert!(config.normalize.expand_abbreviations);
+        assert_eq!(
+            config.normalize.enabled_steps(),
+            ["lowercase", "expand_abbreviations", "trim_whitespace"]
+        );
+    }
+
+    #[test]
+    fn test_add_abbreviation_keeps_built_in_entries() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path =
+            QueryNormalizer::add_abbreviation(temp_dir.path(), "ASREP", "as-rep roasting").unwrap();
+        assert_eq!(
+            path,
+            temp_dir.path().join("normalization/abbreviations.json")
+        );
+
+        let normalizer =
+            QueryNormalizer::with_data_dir(NormalizationConfig::default(), Some(temp_dir.path()))
+                .unwrap();
+        assert_eq!(
+            normalizer.abbreviations().get("asrep"),
+            Some(&"as-rep roasting")
+        );
+        assert_eq!(
+            normalizer.abbreviations().get("sqli"),
+            Some(&"sql injection database")
+        );
+
+        assert!(QueryNormalizer::add_abbreviation(temp_dir.path(), "-ss", "syn scan").is_err());
+        assert!(QueryNormalizer::add_abbreviation(temp_dir.path(), "445", "smb").is_err());
+    }
+
     #[test]
     fn test_empty_query() {
         let normalizer = create_test_normalizer();
@@ -316,4 +533,26 @@ mod tests {
         let result = normalizer.normalize("show me how to the a").unwrap();
         assert_eq!(result, "");
     }
+
+    #[test]
+    fn test_data_dir_overrides_embedded_data() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let normalization_dir = temp_dir.path().join("normalization");
+        std::fs::create_dir(&normalization_dir).unwrap();
+        std::fs::write(
+            normalization_dir.join("abbreviations.json"),
+            r#"{"abbreviations": {"kerb": "kerberos"}}"#,
+        )
+        .unwrap();
+
+        // abbreviations come from the data dir, stopwords fall back to the embedded list
+        let normalizer =
+            QueryNormalizer::with_data_dir(NormalizationConfig::default(), Some(temp_dir.path()))
+                .unwrap();
+        assert_eq!(
+            normalizer.normalize("show me kerb roasting").unwrap(),
+            "kerberos roasting"
+        );
+        assert!(!normalizer.abbreviations.contains_key("nmap"));
+    }
 }
@@ -0,0 +1,123 @@
+use super::embedder::{Embedder, SimpleEmbedder};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Embeddings from a local Ollama server's `/api/embed` endpoint, e.g. with
+/// `nomic-embed-text`. Far better at paraphrases than the built-in embedder,
+/// at the cost of a request per lookup.
+pub struct OllamaEmbedder {
+    base_url: String,
+    model: String,
+    /// Stored with each embedding, e.g. `ollama:nomic-embed-text`
+    model_name: String,
+    client: reqwest::blocking::Client,
+    /// Learned from the first embedding, since it depends on the model
+    dimensions: OnceLock<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    input: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum EmbedResponse {
+    Error { error: String },
+    Embeddings { embeddings: Vec<Vec<f32>> },
+}
+
+impl OllamaEmbedder {
+    pub fn new(
+        base_url: &str,
+        model: &str,
+        timeout: Duration,
+        allow_invalid_certs: bool,
+    ) -> Result<Self> {
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model: model.to_string(),
+            model_name: format!("ollama:{}", model),
+            client: crate::http::build_self_hosted_client(Some(timeout), allow_invalid_certs)?,
+            dimensions: OnceLock::new(),
+        })
+    }
+}
+
+impl Embedder for OllamaEmbedder {
+    fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let url = format!("{}/api/embed", self.base_url);
+        let response = self
+            .client
+            .post(&url)
+            .json(&EmbedRequest {
+                model: &self.model,
+                input: text,
+            })
+            .send()
+            .context("Failed to reach Ollama for embeddings. Is Ollama running?")?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .context("Failed to read Ollama embedding response")?;
+        if status == reqwest::StatusCode::NOT_FOUND {
+            anyhow::bail!(
+                "Ollama embedding model '{}' is not installed. Download it with `cyx ollama pull {}`",
+                self.model,
+                self.model
+            );
+        }
+
+        let mut embedding = parse_embed_response(&body)?;
+        SimpleEmbedder::normalize_vector(&mut embedding);
+        let _ = self.dimensions.set(embedding.len());
+        Ok(embedding)
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    fn dimensions(&self) -> Result<usize> {
+        if let Some(dimensions) = self.dimensions.get() {
+            return Ok(*dimensions);
+        }
+        Ok(self.embed("dimensions")?.len())
+    }
+}
+
+/// The single embedding in an `/api/embed` response body
+fn parse_embed_response(body: &str) -> Result<Vec<f32>> {
+    let response: EmbedResponse = serde_json::from_str(body)
+        .with_context(|| format!("Unexpected Ollama embedding response: {}", body.trim()))?;
+
+    match response {
+        EmbedResponse::Error { error } => anyhow::bail!("Ollama embedding error: {}", error),
+        EmbedResponse::Embeddings { embeddings } => embeddings
+            .into_iter()
+            .next()
+            .filter(|embedding| !embedding.is_empty())
+            .context("Ollama returned no embedding (is the model an embedding model?)"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_embed_response() {
+        let body = r#"{"model":"nomic-embed-text","embeddings":[[0.6,0.8]]}"#;
+        assert_eq!(parse_embed_response(body).unwrap(), vec![0.6, 0.8]);
+
+        let error =
+            parse_embed_response(r#"{"error":"model does not support embeddings"}"#).unwrap_err();
+        assert!(error.to_string().contains("does not support embeddings"));
+
+        assert!(parse_embed_response(r#"{"embeddings":[]}"#).is_err());
+    }
+}
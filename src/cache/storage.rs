@@ -1,10 +1,16 @@
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
 
-use super::embedder::{cosine_similarity, Embedder};
+use super::disk_usage::DiskUsage;
+use super::embedder::{Embedder, SimilarityMetric, SimpleEmbedder};
+use super::normalizer::QueryNormalizer;
+use super::tags::normalize_tag;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CachedQuery {
@@ -18,6 +24,18 @@ pub struct CachedQuery {
     pub created_at: DateTime<Utc>,
     pub last_accessed: DateTime<Utc>,
     pub access_count: i32,
+    /// Structured `(description, url)` sources extracted from the response
+    #[serde(default)]
+    pub sources: Vec<(String, String)>,
+    /// Answer language, `None` for English
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Lowercase category tags, e.g. `recon`, `web`, `ad`
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Pinned entries are never removed by `cleanup_old_entries`
+    #[serde(default)]
+    pub pinned: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,15 +46,148 @@ pub struct CacheStats {
     pub miss_count: i64,
     pub oldest_entry: Option<DateTime<Utc>>,
     pub newest_entry: Option<DateTime<Utc>>,
+    pub avg_access_count: f64,
 }
 
+/// Number of cache entries in an age range
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgeBucket {
+    pub label: String,
+    pub count: i64,
+}
+
+/// Outcome of re-normalizing and re-embedding the cache
+#[derive(Debug, Clone, Default)]
+pub struct ReindexSummary {
+    /// Rows rewritten with a fresh normalized form, hash and embedding
+    pub updated: usize,
+    /// Rows folded into another entry because their new hash collided
+    pub merged: usize,
+}
+
+/// Outcome of merging entries with near-identical responses
+#[derive(Debug, Clone, Default)]
+pub struct DedupeSummary {
+    /// Groups of near-identical responses that had at least one duplicate
+    pub groups: usize,
+    /// Entries folded into the most accessed entry of their group
+    pub removed: usize,
+    /// Query and response text freed by the removed entries
+    pub bytes_reclaimed: u64,
+}
+
+/// Database file size around a `vacuum`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VacuumSummary {
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+/// Cache hits at one similarity threshold when replaying cached queries
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThresholdTrial {
+    pub threshold: f32,
+    /// Replayed entries whose closest other entry passes the threshold
+    pub hits: usize,
+    /// Hits whose matched entry has a similar response, i.e. likely correct reuses
+    pub agreeing: usize,
+}
+
+/// Outcome of `tune_threshold`
+#[derive(Debug, Clone, Default)]
+pub struct ThresholdTuning {
+    /// Entries replayed as queries (the most accessed ones)
+    pub sampled: usize,
+    /// Comparable entries they were matched against
+    pub compared: usize,
+    pub trials: Vec<ThresholdTrial>,
+}
+
+impl ThresholdTuning {
+    /// The threshold with the most hits among those whose hits mostly (90%) return
+    /// an agreeing answer, the strictest one on ties; `None` if there is none
+    pub fn recommended(&self) -> Option<f32> {
+        self.trials
+            .iter()
+            .filter(|trial| trial.hits > 0 && trial.agreeing * 10 >= trial.hits * 9)
+            .max_by_key(|trial| trial.hits)
+            .map(|trial| trial.threshold)
+    }
+}
+
+/// Default minimum response similarity for `dedupe_responses`
+pub const RESPONSE_DEDUPE_THRESHOLD: f32 = 0.95;
+
+/// Minimum response similarity (word-trigram Jaccard) for a similar-match hit in
+/// `tune_threshold` to count as returning the right answer
+pub const TUNE_RESPONSE_AGREEMENT: f32 = 0.3;
+
+/// How long to wait for another process's write lock before giving up
+const BUSY_TIMEOUT_MS: u64 = 5000;
+
+/// Columns selected when loading a `CachedQuery` (see `map_cached_query`)
+const CACHED_QUERY_COLUMNS: &str = "id, query_original, query_normalized, query_hash, response,
+                    provider, model, created_at, last_accessed, access_count, sources, language, tags,
+                    pinned";
+
+/// Tags are stored as a comma-separated list (see `normalize_tag`)
+fn split_tags(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Map a row selected with `CACHED_QUERY_COLUMNS` to a `CachedQuery`
+fn map_cached_query(row: &rusqlite::Row) -> rusqlite::Result<CachedQuery> {
+    let sources: Option<String> = row.get(10)?;
+
+    Ok(CachedQuery {
+        id: row.get(0)?,
+        query_original: row.get(1)?,
+        query_normalized: row.get(2)?,
+        query_hash: row.get(3)?,
+        response: row.get(4)?,
+        provider: row.get(5)?,
+        model: row.get(6)?,
+        created_at: DateTime::from_timestamp(row.get(7)?, 0).unwrap_or_else(Utc::now),
+        last_accessed: DateTime::from_timestamp(row.get(8)?, 0).unwrap_or_else(Utc::now),
+        access_count: row.get(9)?,
+        sources: sources
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
+        language: row.get(11)?,
+        tags: row
+            .get::<_, Option<String>>(12)?
+            .map(|tags| split_tags(&tags))
+            .unwrap_or_default(),
+        pinned: row.get(13)?,
+    })
+}
+
+/// SQLite-backed query cache. The connection sits behind a mutex so one storage
+/// can be shared between threads; the database runs in WAL mode so readers in
+/// other processes aren't blocked by a writer.
 pub struct CacheStorage {
-    conn: Connection,
+    conn: Mutex<Connection>,
     cache_dir: PathBuf,
-    embedder: Option<Embedder>,
+    /// Built on first use, so commands that never embed don't pay for it
+    embedder: OnceLock<Box<dyn Embedder>>,
+    /// First embedder error; later embeds are skipped rather than retried
+    embed_failure: OnceLock<String>,
+    /// Where a corrupt database found on open was moved before starting over
+    corrupt_backup: Option<PathBuf>,
+    metric: SimilarityMetric,
+    /// Answer language new entries are stored under and similar matches are limited to
+    language: Option<String>,
 }
 
 impl CacheStorage {
+    /// The SQLite database file inside `cache_dir`
+    pub fn db_path(cache_dir: &Path) -> PathBuf {
+        cache_dir.join("queries.db")
+    }
+
     pub fn new<P: AsRef<Path>>(cache_dir: P) -> Result<Self> {
         let cache_dir = cache_dir.as_ref().to_path_buf();
 
@@ -44,24 +195,168 @@ impl CacheStorage {
             std::fs::create_dir_all(&cache_dir).context("Failed to create cache directory")?;
         }
 
-        let db_path = cache_dir.join("queries.db");
-        let conn = Connection::open(&db_path)
+        let db_path = Self::db_path(&cache_dir);
+        let mut conn = Self::open_db(&db_path)?;
+        let mut corrupt_backup = None;
+
+        // A write cut short (power loss, full disk) can leave the file unreadable:
+        // set it aside and start over rather than failing every cache operation
+        if Self::is_corrupt(&conn)? {
+            drop(conn);
+            let backup = Self::set_aside_corrupt(&db_path)?;
+            log::warn!("Corrupt cache database moved to {}", backup.display());
+            corrupt_backup = Some(backup);
+            conn = Self::open_db(&db_path)?;
+        }
+
+        conn.query_row("PRAGMA journal_mode=WAL", [], |_| Ok(()))?;
+
+        let mut storage = Self::with_connection(conn, cache_dir)?;
+        storage.corrupt_backup = corrupt_backup;
+        Ok(storage)
+    }
+
+    fn open_db(db_path: &Path) -> Result<Connection> {
+        let conn = Connection::open(db_path)
             .with_context(|| format!("Failed to open cache database: {}", db_path.display()))?;
 
-        let embedder = Some(Embedder::new(Embedder::get_default_dimensions()));
+        // Other cyx processes may be writing to the same file: wait for their lock
+        // instead of failing with "database is locked", and let readers run
+        // alongside a writer
+        conn.busy_timeout(Duration::from_millis(BUSY_TIMEOUT_MS))?;
+        Ok(conn)
+    }
+
+    /// Whether the database fails SQLite's consistency check. `quick_check` finds
+    /// damaged pages like `integrity_check` does, but skips verifying every index
+    /// entry, so it stays cheap enough to run on each start.
+    fn is_corrupt(conn: &Connection) -> Result<bool> {
+        let result = conn.query_row("PRAGMA quick_check(1)", [], |row| row.get::<_, String>(0));
+        match result {
+            Ok(status) => Ok(status != "ok"),
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if matches!(
+                    e.code,
+                    rusqlite::ErrorCode::NotADatabase | rusqlite::ErrorCode::DatabaseCorrupt
+                ) =>
+            {
+                Ok(true)
+            }
+            Err(e) => Err(e).context("Failed to check the cache database"),
+        }
+    }
+
+    /// Move a corrupt database (and its write-ahead log) to `queries.db.corrupt`,
+    /// returning the backup path
+    fn set_aside_corrupt(db_path: &Path) -> Result<PathBuf> {
+        let backup = db_path.with_extension("db.corrupt");
+        std::fs::rename(db_path, &backup).with_context(|| {
+            format!(
+                "Failed to move corrupt cache database to {}",
+                backup.display()
+            )
+        })?;
+
+        // A stale log would be replayed into the fresh database
+        let sidecar = |suffix: &str| PathBuf::from(format!("{}{}", db_path.display(), suffix));
+        if sidecar("-wal").exists() {
+            std::fs::rename(sidecar("-wal"), format!("{}-wal", backup.display()))?;
+        }
+        let _ = std::fs::remove_file(sidecar("-shm"));
+
+        Ok(backup)
+    }
+
+    /// A throwaway cache that lives in memory, for benchmarks. It has no cache
+    /// directory, so `vacuum` reports zero sizes.
+    pub fn in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?, PathBuf::new())
+    }
 
+    fn with_connection(conn: Connection, cache_dir: PathBuf) -> Result<Self> {
         let storage = Self {
-            conn,
+            conn: Mutex::new(conn),
             cache_dir,
-            embedder,
+            embedder: OnceLock::new(),
+            embed_failure: OnceLock::new(),
+            corrupt_backup: None,
+            metric: SimilarityMetric::default(),
+            language: None,
         };
         storage.initialize_schema()?;
 
         Ok(storage)
     }
 
+    /// Use a different metric for `search_similar`
+    pub fn with_similarity_metric(mut self, metric: SimilarityMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Scope stores and similarity search to answers in `language` (`None` = English)
+    pub fn with_language(mut self, language: Option<String>) -> Self {
+        self.language = language;
+        self
+    }
+
+    /// Embed with `embedder` instead of the built-in one. Entries embedded by another
+    /// model are skipped by similarity search until `reindex` re-embeds them.
+    pub fn with_embedder(mut self, embedder: Box<dyn Embedder>) -> Self {
+        self.embedder = OnceLock::from(embedder);
+        self
+    }
+
+    /// Set when opening found the database corrupt and started a fresh one: where
+    /// the old file was kept. The storage never prints, so callers tell the user.
+    pub fn corrupt_backup(&self) -> Option<&Path> {
+        self.corrupt_backup.as_deref()
+    }
+
+    pub fn similarity_metric(&self) -> SimilarityMetric {
+        self.metric
+    }
+
+    /// Exclusive access to the connection. A panic while it was held leaves
+    /// nothing half-written (SQLite rolls back), so a poisoned lock is reused.
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn embedder(&self) -> &dyn Embedder {
+        self.embedder
+            .get_or_init(|| Box::new(SimpleEmbedder::new(SimpleEmbedder::get_default_dimensions())))
+            .as_ref()
+    }
+
+    /// Embed `text`, or `None` when the embedder fails (e.g. Ollama unreachable).
+    /// Embeddings only serve similarity search, so the failure is logged and
+    /// kept for `embed_failure` instead of failing the lookup or store.
+    fn try_embed(&self, text: &str) -> Option<Vec<f32>> {
+        if self.embed_failure.get().is_some() {
+            return None;
+        }
+
+        let embedder = self.embedder();
+        match embedder.embed(text) {
+            Ok(embedding) => Some(embedding),
+            Err(e) => {
+                log::warn!("Cache embedder {} failed: {:#}", embedder.model_name(), e);
+                let _ = self.embed_failure.set(format!("{:#}", e));
+                None
+            }
+        }
+    }
+
+    /// Why the embedder failed, if it did: similar matches were skipped and new
+    /// entries stored without an embedding (`cache reindex` adds them later)
+    pub fn embed_failure(&self) -> Option<&str> {
+        self.embed_failure.get().map(String::as_str)
+    }
+
     fn initialize_schema(&self) -> Result<()> {
-        self.conn.execute(
+        let conn = self.conn();
+        conn.execute(
             "CREATE TABLE IF NOT EXISTS queries (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 query_original TEXT NOT NULL,
@@ -73,31 +368,44 @@ impl CacheStorage {
                 model TEXT NOT NULL,
                 created_at INTEGER NOT NULL,
                 last_accessed INTEGER NOT NULL,
-                access_count INTEGER DEFAULT 1
+                access_count INTEGER DEFAULT 1,
+                sources TEXT,
+                embedding_dim INTEGER,
+                embedding_model TEXT,
+                language TEXT,
+                tags TEXT,
+                pinned INTEGER NOT NULL DEFAULT 0
             )",
             [],
         )?;
 
-        let _ = self
-            .conn
-            .execute("ALTER TABLE queries ADD COLUMN embedding BLOB", []);
+        let _ = conn.execute("ALTER TABLE queries ADD COLUMN embedding BLOB", []);
+        let _ = conn.execute("ALTER TABLE queries ADD COLUMN sources TEXT", []);
+        let _ = conn.execute("ALTER TABLE queries ADD COLUMN embedding_dim INTEGER", []);
+        let _ = conn.execute("ALTER TABLE queries ADD COLUMN embedding_model TEXT", []);
+        let _ = conn.execute("ALTER TABLE queries ADD COLUMN language TEXT", []);
+        let _ = conn.execute("ALTER TABLE queries ADD COLUMN tags TEXT", []);
+        let _ = conn.execute(
+            "ALTER TABLE queries ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0",
+            [],
+        );
 
-        self.conn.execute(
+        conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_query_hash ON queries(query_hash)",
             [],
         )?;
 
-        self.conn.execute(
+        conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_created_at ON queries(created_at)",
             [],
         )?;
 
-        self.conn.execute(
+        conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_access_count ON queries(access_count)",
             [],
         )?;
 
-        self.conn.execute(
+        conn.execute(
             "CREATE TABLE IF NOT EXISTS cache_stats (
                 id INTEGER PRIMARY KEY CHECK (id = 1),
                 hit_count INTEGER DEFAULT 0,
@@ -106,7 +414,7 @@ impl CacheStorage {
             [],
         )?;
 
-        self.conn.execute(
+        conn.execute(
             "INSERT OR IGNORE INTO cache_stats (id, hit_count, miss_count) VALUES (1, 0, 0)",
             [],
         )?;
@@ -123,31 +431,35 @@ impl CacheStorage {
         provider: &str,
         model: &str,
     ) -> Result<i64> {
-        let now = Utc::now().timestamp();
+        if response.trim().is_empty() {
+            anyhow::bail!("Refusing to cache an empty response");
+        }
 
-        // Store embedding as Option<Vec<u8>>, None means no embedding
-        let embedding_blob: Option<Vec<u8>> = if let Some(ref embedder) = self.embedder {
-            Some(bincode::serialize(&embedder.embed(query_normalized))?)
-        } else {
-            None
-        };
+        let now = Utc::now().timestamp();
 
-        // Convert to Option<&[u8]> for proper BLOB binding
-        let embedding_ref: Option<&[u8]> = embedding_blob.as_deref();
+        let embedding = self.try_embed(query_normalized);
+        let embedding_blob = embedding.as_ref().map(bincode::serialize).transpose()?;
+        let embedding_dim = embedding.as_ref().map(|e| e.len() as i64);
+        let embedding_model = embedding.as_ref().map(|_| self.embedder().model_name());
 
+        let conn = self.conn();
         // Prepare and execute with proper type annotation
-        let mut stmt = self.conn.prepare_cached(
+        let mut stmt = conn.prepare_cached(
             "INSERT INTO queries (
                 query_original, query_normalized, query_hash, embedding, response,
-                provider, model, created_at, last_accessed, access_count
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                provider, model, created_at, last_accessed, access_count,
+                embedding_dim, embedding_model, language
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
             ON CONFLICT(query_hash) DO UPDATE SET
                 query_original = excluded.query_original,
                 query_normalized = excluded.query_normalized,
                 embedding = excluded.embedding,
+                embedding_dim = excluded.embedding_dim,
+                embedding_model = excluded.embedding_model,
                 response = excluded.response,
                 provider = excluded.provider,
                 model = excluded.model,
+                language = excluded.language,
                 last_accessed = excluded.last_accessed,
                 access_count = access_count + 1",
         )?;
@@ -156,89 +468,93 @@ impl CacheStorage {
             query_original,
             query_normalized,
             query_hash,
-            embedding_ref,
+            embedding_blob,
             response,
             provider,
             model,
             now,
             now,
-            1i64
+            1i64,
+            embedding_dim,
+            embedding_model,
+            self.language
         ])?;
 
-        Ok(self.conn.last_insert_rowid())
+        Ok(conn.last_insert_rowid())
     }
 
+    /// Look up an entry to serve it: records the access and counts a cache hit or
+    /// miss. Display and export paths use `peek_by_hash` instead.
     pub fn get_by_hash(&self, query_hash: &str) -> Result<Option<CachedQuery>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, query_original, query_normalized, query_hash, response,
-                    provider, model, created_at, last_accessed, access_count
-             FROM queries WHERE query_hash = ?1",
-        )?;
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM queries WHERE query_hash = ?1",
+            CACHED_QUERY_COLUMNS
+        ))?;
 
-        let result = stmt.query_row(params![query_hash], |row| {
-            Ok(CachedQuery {
-                id: row.get(0)?,
-                query_original: row.get(1)?,
-                query_normalized: row.get(2)?,
-                query_hash: row.get(3)?,
-                response: row.get(4)?,
-                provider: row.get(5)?,
-                model: row.get(6)?,
-                created_at: DateTime::from_timestamp(row.get(7)?, 0).unwrap_or_else(Utc::now),
-                last_accessed: DateTime::from_timestamp(row.get(8)?, 0).unwrap_or_else(Utc::now),
-                access_count: row.get(9)?,
-            })
-        });
+        let result = stmt.query_row(params![query_hash], map_cached_query);
 
         match result {
             Ok(cached) => {
-                self.update_access(&cached.query_hash)?;
-                self.increment_hit_count()?;
+                Self::update_access(&conn, &cached.query_hash)?;
+                Self::increment_hit_count(&conn)?;
                 Ok(Some(cached))
             }
             Err(rusqlite::Error::QueryReturnedNoRows) => {
-                self.increment_miss_count()?;
+                Self::increment_miss_count(&conn)?;
                 Ok(None)
             }
             Err(e) => Err(e.into()),
         }
     }
 
+    /// Find entries whose embedding passes `threshold` under the active metric,
+    /// best match first, among answers in the storage's language. Scores are
+    /// similarities, or distances for euclidean.
     pub fn search_similar(
         &self,
         query_normalized: &str,
         threshold: f32,
         limit: usize,
     ) -> Result<Vec<(CachedQuery, f32)>> {
-        let query_embedding = if let Some(ref embedder) = self.embedder {
-            embedder.embed(query_normalized)
-        } else {
-            return Ok(Vec::new());
-        };
+        let mut results = self.score_all(query_normalized)?;
+        results.retain(|(_, score)| self.metric.passes(*score, threshold));
+        results.truncate(limit);
+        Ok(results)
+    }
 
-        let mut stmt = self.conn.prepare(
-            "SELECT id, query_original, query_normalized, query_hash, response,
-                    provider, model, created_at, last_accessed, access_count, embedding
-             FROM queries WHERE embedding IS NOT NULL",
-        )?;
+    /// The `limit` closest entries regardless of any threshold, best first
+    pub fn nearest(&self, query_normalized: &str, limit: usize) -> Result<Vec<(CachedQuery, f32)>> {
+        let mut results = self.score_all(query_normalized)?;
+        results.truncate(limit);
+        Ok(results)
+    }
 
-        let rows = stmt.query_map([], |row| {
-            Ok((
-                CachedQuery {
-                    id: row.get(0)?,
-                    query_original: row.get(1)?,
-                    query_normalized: row.get(2)?,
-                    query_hash: row.get(3)?,
-                    response: row.get(4)?,
-                    provider: row.get(5)?,
-                    model: row.get(6)?,
-                    created_at: DateTime::from_timestamp(row.get(7)?, 0).unwrap_or_else(Utc::now),
-                    last_accessed: DateTime::from_timestamp(row.get(8)?, 0)
-                        .unwrap_or_else(Utc::now),
-                    access_count: row.get(9)?,
-                },
-                row.get::<_, Vec<u8>>(10)?,
-            ))
+    /// Score every comparable entry in the storage's language, best match first
+    fn score_all(&self, query_normalized: &str) -> Result<Vec<(CachedQuery, f32)>> {
+        let Some(query_embedding) = self.try_embed(query_normalized) else {
+            return Ok(Vec::new());
+        };
+        let embedder = self.embedder();
+
+        // Rows embedded by a different model or dimension are not comparable.
+        // Legacy rows without metadata are kept and checked by vector length below.
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, embedding FROM queries
+             WHERE embedding IS NOT NULL
+               AND (embedding_model IS NULL OR (embedding_model = ?1 AND embedding_dim = ?2))
+               AND language IS ?3",
+            CACHED_QUERY_COLUMNS
+        ))?;
+
+        let params = params![
+            embedder.model_name(),
+            query_embedding.len() as i64,
+            self.language
+        ];
+        let rows = stmt.query_map(params, |row| {
+            Ok((map_cached_query(row)?, row.get::<_, Vec<u8>>("embedding")?))
         })?;
 
         let mut results: Vec<(CachedQuery, f32)> = Vec::new();
@@ -246,23 +562,345 @@ impl CacheStorage {
             let (cached_query, embedding_blob) = row_result?;
 
             if let Ok(cached_embedding) = bincode::deserialize::<Vec<f32>>(&embedding_blob) {
-                let similarity = cosine_similarity(&query_embedding, &cached_embedding);
+                if cached_embedding.len() != query_embedding.len() {
+                    continue;
+                }
+
+                let score = self.metric.score(&query_embedding, &cached_embedding);
+                results.push((cached_query, score));
+            }
+        }
+
+        results.sort_by(|a, b| self.metric.compare(a.1, b.1));
+        Ok(results)
+    }
+
+    /// Recompute the normalized query, hash and embedding of every entry with the
+    /// current normalizer and embedder. Entries whose new hash collides are merged,
+    /// keeping the most recently accessed response.
+    pub fn reindex(&self, normalizer: &QueryNormalizer) -> Result<ReindexSummary> {
+        let conn = self.conn();
+        let tx = conn.unchecked_transaction()?;
+        let mut summary = ReindexSummary::default();
+
+        // Key every entry before touching any: merging as we go would match a row
+        // whose stale, not yet recomputed hash happens to equal a new one
+        let mut groups: HashMap<String, (String, Vec<CachedQuery>)> = HashMap::new();
+        for entry in Self::select_all(&conn, None)? {
+            let normalized = normalizer.normalize(&entry.query_original)?;
+            let hash = normalizer.compute_scoped_hash(&normalized, entry.language.as_deref());
+            groups
+                .entry(hash)
+                .or_insert_with(|| (normalized, Vec::new()))
+                .1
+                .push(entry);
+        }
+
+        // Free every stale hash first, so no new hash clashes with one still in place
+        conn.execute("UPDATE queries SET query_hash = 'reindex:' || id", [])?;
+
+        for (hash, (normalized, entries)) in groups {
+            // Listed most recently accessed first: that response is kept
+            let mut entries = entries.into_iter();
+            let Some(keep) = entries.next() else {
+                continue;
+            };
+
+            for drop in entries {
+                conn.execute("DELETE FROM queries WHERE id = ?1", params![drop.id])?;
+                conn.execute(
+                    "UPDATE queries SET access_count = access_count + ?1,
+                            created_at = MIN(created_at, ?2), pinned = MAX(pinned, ?3)
+                     WHERE id = ?4",
+                    params![
+                        drop.access_count,
+                        drop.created_at.timestamp(),
+                        drop.pinned,
+                        keep.id
+                    ],
+                )?;
+                summary.merged += 1;
+            }
+
+            self.update_index(&conn, keep.id, &normalized, &hash)?;
+            summary.updated += 1;
+        }
+
+        tx.commit()?;
+        Ok(summary)
+    }
+
+    /// Fold entries whose responses are at least `threshold` similar (word-trigram
+    /// Jaccard) into the most accessed entry of each group, adding their access
+    /// counts to it. With `dry_run` nothing is written, only the summary is computed.
+    pub fn dedupe_responses(&self, threshold: f32, dry_run: bool) -> Result<DedupeSummary> {
+        let conn = self.conn();
+        let mut entries = Self::select_all(&conn, None)?;
+        entries.sort_by(|a, b| {
+            b.access_count
+                .cmp(&a.access_count)
+                .then(b.last_accessed.cmp(&a.last_accessed))
+        });
+        let shingles: Vec<HashSet<String>> = entries
+            .iter()
+            .map(|entry| response_shingles(&entry.response))
+            .collect();
+
+        let tx = conn.unchecked_transaction()?;
+        let mut summary = DedupeSummary::default();
+        let mut removed = vec![false; entries.len()];
+
+        for keep in 0..entries.len() {
+            if removed[keep] {
+                continue;
+            }
+
+            let mut absorbed = 0;
+            for other in keep + 1..entries.len() {
+                if removed[other] || jaccard(&shingles[keep], &shingles[other]) < threshold {
+                    continue;
+                }
 
-                if similarity >= threshold {
-                    results.push((cached_query, similarity));
+                let drop = &entries[other];
+                if !dry_run {
+                    conn.execute("DELETE FROM queries WHERE id = ?1", params![drop.id])?;
+                    conn.execute(
+                        "UPDATE queries SET access_count = access_count + ?1,
+                                pinned = MAX(pinned, ?2)
+                         WHERE id = ?3",
+                        params![drop.access_count, drop.pinned, entries[keep].id],
+                    )?;
                 }
+
+                removed[other] = true;
+                absorbed += 1;
+                summary.removed += 1;
+                summary.bytes_reclaimed += (drop.response.len() + drop.query_original.len()) as u64;
+            }
+
+            if absorbed > 0 {
+                summary.groups += 1;
             }
         }
 
-        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-        results.truncate(limit);
+        tx.commit()?;
+        Ok(summary)
+    }
 
-        Ok(results)
+    /// Replay the `sample` most accessed entries as queries against the rest of the
+    /// cache (same language only) and count the similar-match hits each threshold
+    /// would give. A hit "agrees" when the matched entry's response is at least
+    /// `TUNE_RESPONSE_AGREEMENT` similar to the replayed entry's own response.
+    pub fn tune_threshold(&self, thresholds: &[f32], sample: usize) -> Result<ThresholdTuning> {
+        let embedder = self.embedder();
+        let dimensions = embedder.dimensions()?;
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, embedding FROM queries
+             WHERE embedding IS NOT NULL
+               AND (embedding_model IS NULL OR (embedding_model = ?1 AND embedding_dim = ?2))",
+            CACHED_QUERY_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![embedder.model_name(), dimensions as i64], |row| {
+            Ok((map_cached_query(row)?, row.get::<_, Vec<u8>>("embedding")?))
+        })?;
+
+        let mut entries = Vec::new();
+        for row_result in rows {
+            let (entry, embedding_blob) = row_result?;
+            if let Ok(embedding) = bincode::deserialize::<Vec<f32>>(&embedding_blob) {
+                if embedding.len() == dimensions {
+                    entries.push((entry, embedding));
+                }
+            }
+        }
+
+        let mut order: Vec<usize> = (0..entries.len()).collect();
+        order.sort_by(|&a, &b| entries[b].0.access_count.cmp(&entries[a].0.access_count));
+        order.truncate(sample);
+
+        // Closest other entry of each replayed entry, and whether its answer agrees
+        let mut matches = Vec::new();
+        for &i in &order {
+            let (entry, embedding) = &entries[i];
+            let best = entries
+                .iter()
+                .enumerate()
+                .filter(|(j, (other, _))| *j != i && other.language == entry.language)
+                .map(|(j, (_, other))| (j, self.metric.score(embedding, other)))
+                .min_by(|a, b| self.metric.compare(a.1, b.1));
+
+            if let Some((j, score)) = best {
+                let agreement = jaccard(
+                    &response_shingles(&entry.response),
+                    &response_shingles(&entries[j].0.response),
+                );
+                matches.push((score, agreement >= TUNE_RESPONSE_AGREEMENT));
+            }
+        }
+
+        let trials = thresholds
+            .iter()
+            .map(|&threshold| {
+                let hits = matches
+                    .iter()
+                    .filter(|(score, _)| self.metric.passes(*score, threshold));
+                ThresholdTrial {
+                    threshold,
+                    hits: hits.clone().count(),
+                    agreeing: hits.filter(|(_, agrees)| *agrees).count(),
+                }
+            })
+            .collect();
+
+        Ok(ThresholdTuning {
+            sampled: order.len(),
+            compared: entries.len(),
+            trials,
+        })
+    }
+
+    /// Look up an entry by hash without touching access or hit/miss stats
+    pub fn peek_by_hash(&self, query_hash: &str) -> Result<Option<CachedQuery>> {
+        Self::select_by_hash(&self.conn(), query_hash)
+    }
+
+    fn select_by_hash(conn: &Connection, query_hash: &str) -> Result<Option<CachedQuery>> {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM queries WHERE query_hash = ?1",
+            CACHED_QUERY_COLUMNS
+        ))?;
+
+        Ok(stmt
+            .query_row(params![query_hash], map_cached_query)
+            .optional()?)
+    }
+
+    /// Rewrite an entry's normalized form, hash and embedding
+    fn update_index(
+        &self,
+        conn: &Connection,
+        id: i64,
+        query_normalized: &str,
+        query_hash: &str,
+    ) -> Result<()> {
+        let embedder = self.embedder();
+        let embedding = embedder.embed(query_normalized)?;
+        let embedding_blob = bincode::serialize(&embedding)?;
+        let embedding_dim = embedding.len() as i64;
+        let embedding_model = embedder.model_name();
+
+        conn.execute(
+            "UPDATE queries SET query_normalized = ?1, query_hash = ?2, embedding = ?3,
+                    embedding_dim = ?4, embedding_model = ?5
+             WHERE id = ?6",
+            params![
+                query_normalized,
+                query_hash,
+                embedding_blob,
+                embedding_dim,
+                embedding_model,
+                id
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Attach structured sources to an existing cache entry
+    pub fn store_sources(&self, query_hash: &str, sources: &[(String, String)]) -> Result<()> {
+        let json = serde_json::to_string(sources)?;
+        self.conn().execute(
+            "UPDATE queries SET sources = ?1 WHERE query_hash = ?2",
+            params![json, query_hash],
+        )?;
+        Ok(())
+    }
+
+    /// Add tags to an existing cache entry, keeping the ones it already has.
+    /// Tags are normalized; invalid ones are skipped.
+    pub fn add_tags(&self, query_hash: &str, tags: &[String]) -> Result<()> {
+        let conn = self.conn();
+        let Some(entry) = Self::select_by_hash(&conn, query_hash)? else {
+            return Ok(());
+        };
+
+        let mut merged = entry.tags;
+        merged.extend(tags.iter().filter_map(|tag| normalize_tag(tag)));
+        merged.sort();
+        merged.dedup();
+
+        conn.execute(
+            "UPDATE queries SET tags = ?1 WHERE id = ?2",
+            params![merged.join(","), entry.id],
+        )?;
+        Ok(())
+    }
+
+    /// Pin or unpin an entry; `false` if no entry has that hash
+    pub fn set_pinned(&self, query_hash: &str, pinned: bool) -> Result<bool> {
+        let count = self.conn().execute(
+            "UPDATE queries SET pinned = ?1 WHERE query_hash = ?2",
+            params![pinned, query_hash],
+        )?;
+        Ok(count > 0)
+    }
+
+    /// Entries created between `start` and `end` (both inclusive), newest first
+    pub fn list_in_range(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        limit: Option<usize>,
+    ) -> Result<Vec<CachedQuery>> {
+        let limit_clause = limit.map(|l| format!("LIMIT {}", l)).unwrap_or_default();
+
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM queries
+             WHERE created_at BETWEEN ?1 AND ?2
+             ORDER BY created_at DESC {}",
+            CACHED_QUERY_COLUMNS, limit_clause
+        ))?;
+        let rows = stmt.query_map(
+            params![start.timestamp(), end.timestamp()],
+            map_cached_query,
+        )?;
+
+        let mut queries = Vec::new();
+        for row in rows {
+            queries.push(row?);
+        }
+
+        Ok(queries)
     }
 
-    fn update_access(&self, query_hash: &str) -> Result<()> {
+    /// Entries carrying `tag`, most recently accessed first
+    pub fn list_by_tag(&self, tag: &str, limit: Option<usize>) -> Result<Vec<CachedQuery>> {
+        let Some(tag) = normalize_tag(tag) else {
+            return Ok(Vec::new());
+        };
+        let limit_clause = limit.map(|l| format!("LIMIT {}", l)).unwrap_or_default();
+
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM queries
+             WHERE instr(',' || tags || ',', ',' || ?1 || ',') > 0
+             ORDER BY last_accessed DESC {}",
+            CACHED_QUERY_COLUMNS, limit_clause
+        ))?;
+        let rows = stmt.query_map(params![tag], map_cached_query)?;
+
+        let mut queries = Vec::new();
+        for row in rows {
+            queries.push(row?);
+        }
+
+        Ok(queries)
+    }
+
+    fn update_access(conn: &Connection, query_hash: &str) -> Result<()> {
         let now = Utc::now().timestamp();
-        self.conn.execute(
+        conn.execute(
             "UPDATE queries SET last_accessed = ?1, access_count = access_count + 1
              WHERE query_hash = ?2",
             params![now, query_hash],
@@ -271,30 +909,19 @@ impl CacheStorage {
     }
 
     pub fn list_all(&self, limit: Option<usize>) -> Result<Vec<CachedQuery>> {
+        Self::select_all(&self.conn(), limit)
+    }
+
+    fn select_all(conn: &Connection, limit: Option<usize>) -> Result<Vec<CachedQuery>> {
         let limit_clause = limit.map(|l| format!("LIMIT {}", l)).unwrap_or_default();
 
         let query = format!(
-            "SELECT id, query_original, query_normalized, query_hash, response,
-                    provider, model, created_at, last_accessed, access_count
-             FROM queries ORDER BY last_accessed DESC {}",
-            limit_clause
+            "SELECT {} FROM queries ORDER BY last_accessed DESC {}",
+            CACHED_QUERY_COLUMNS, limit_clause
         );
 
-        let mut stmt = self.conn.prepare(&query)?;
-        let rows = stmt.query_map([], |row| {
-            Ok(CachedQuery {
-                id: row.get(0)?,
-                query_original: row.get(1)?,
-                query_normalized: row.get(2)?,
-                query_hash: row.get(3)?,
-                response: row.get(4)?,
-                provider: row.get(5)?,
-                model: row.get(6)?,
-                created_at: DateTime::from_timestamp(row.get(7)?, 0).unwrap_or_else(Utc::now),
-                last_accessed: DateTime::from_timestamp(row.get(8)?, 0).unwrap_or_else(Utc::now),
-                access_count: row.get(9)?,
-            })
-        })?;
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map([], map_cached_query)?;
 
         let mut queries = Vec::new();
         for row in rows {
@@ -305,32 +932,36 @@ impl CacheStorage {
     }
 
     pub fn stats(&self) -> Result<CacheStats> {
+        let conn = self.conn();
         let total_entries: i64 =
-            self.conn
-                .query_row("SELECT COUNT(*) FROM queries", [], |row| row.get(0))?;
+            conn.query_row("SELECT COUNT(*) FROM queries", [], |row| row.get(0))?;
 
-        let total_size_bytes: i64 = self.conn.query_row(
+        let total_size_bytes: i64 = conn.query_row(
             "SELECT COALESCE(SUM(LENGTH(response) + LENGTH(query_original)), 0) FROM queries",
             [],
             |row| row.get(0),
         )?;
 
-        let (hit_count, miss_count): (i64, i64) = self.conn.query_row(
+        let (hit_count, miss_count): (i64, i64) = conn.query_row(
             "SELECT hit_count, miss_count FROM cache_stats WHERE id = 1",
             [],
             |row| Ok((row.get(0)?, row.get(1)?)),
         )?;
 
-        let oldest_entry: Option<i64> = self
-            .conn
+        let oldest_entry: Option<i64> = conn
             .query_row("SELECT MIN(created_at) FROM queries", [], |row| row.get(0))
             .ok();
 
-        let newest_entry: Option<i64> = self
-            .conn
+        let newest_entry: Option<i64> = conn
             .query_row("SELECT MAX(created_at) FROM queries", [], |row| row.get(0))
             .ok();
 
+        let avg_access_count: f64 = conn.query_row(
+            "SELECT COALESCE(AVG(access_count), 0.0) FROM queries",
+            [],
+            |row| row.get(0),
+        )?;
+
         Ok(CacheStats {
             total_entries,
             total_size_bytes,
@@ -338,19 +969,66 @@ impl CacheStorage {
             miss_count,
             oldest_entry: oldest_entry.and_then(|ts| DateTime::from_timestamp(ts, 0)),
             newest_entry: newest_entry.and_then(|ts| DateTime::from_timestamp(ts, 0)),
+            avg_access_count,
         })
     }
 
-    fn increment_hit_count(&self) -> Result<()> {
-        self.conn.execute(
+    /// Most frequently accessed entries, most accessed first
+    pub fn top_queries(&self, limit: usize) -> Result<Vec<CachedQuery>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM queries ORDER BY access_count DESC, last_accessed DESC LIMIT ?1",
+            CACHED_QUERY_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![limit as i64], map_cached_query)?;
+
+        let mut queries = Vec::new();
+        for row in rows {
+            queries.push(row?);
+        }
+
+        Ok(queries)
+    }
+
+    /// Count entries by age since creation
+    pub fn age_histogram(&self) -> Result<Vec<AgeBucket>> {
+        const BUCKETS: [(&str, i64, i64); 5] = [
+            ("< 1 day", 0, 1),
+            ("1-7 days", 1, 7),
+            ("7-30 days", 7, 30),
+            ("30-90 days", 30, 90),
+            ("> 90 days", 90, i64::MAX / 86400),
+        ];
+
+        let conn = self.conn();
+        let now = Utc::now().timestamp();
+        let mut histogram = Vec::with_capacity(BUCKETS.len());
+
+        for (label, min_days, max_days) in BUCKETS {
+            let count: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM queries WHERE created_at <= ?1 AND created_at > ?2",
+                params![now - min_days * 86400, now - max_days * 86400],
+                |row| row.get(0),
+            )?;
+            histogram.push(AgeBucket {
+                label: label.to_string(),
+                count,
+            });
+        }
+
+        Ok(histogram)
+    }
+
+    fn increment_hit_count(conn: &Connection) -> Result<()> {
+        conn.execute(
             "UPDATE cache_stats SET hit_count = hit_count + 1 WHERE id = 1",
             [],
         )?;
         Ok(())
     }
 
-    fn increment_miss_count(&self) -> Result<()> {
-        self.conn.execute(
+    fn increment_miss_count(conn: &Connection) -> Result<()> {
+        conn.execute(
             "UPDATE cache_stats SET miss_count = miss_count + 1 WHERE id = 1",
             [],
         )?;
@@ -358,9 +1036,10 @@ impl CacheStorage {
     }
 
     pub fn clear(&self) -> Result<usize> {
-        let count = self.conn.execute("DELETE FROM queries", [])?;
+        let conn = self.conn();
+        let count = conn.execute("DELETE FROM queries", [])?;
 
-        self.conn.execute(
+        conn.execute(
             "UPDATE cache_stats SET hit_count = 0, miss_count = 0 WHERE id = 1",
             [],
         )?;
@@ -368,8 +1047,23 @@ impl CacheStorage {
         Ok(count)
     }
 
+    /// Rebuild the database file so space freed by deleted rows is returned to the
+    /// file system. Sizes include the WAL, which is checkpointed and truncated.
+    pub fn vacuum(&self) -> Result<VacuumSummary> {
+        let conn = self.conn();
+        let size_before = DiskUsage::measure(&self.cache_dir)?.database;
+
+        conn.execute_batch("VACUUM")?;
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+
+        Ok(VacuumSummary {
+            size_before,
+            size_after: DiskUsage::measure(&self.cache_dir)?.database,
+        })
+    }
+
     pub fn remove_by_hash(&self, query_hash: &str) -> Result<bool> {
-        let count = self.conn.execute(
+        let count = self.conn().execute(
             "DELETE FROM queries WHERE query_hash = ?1",
             params![query_hash],
         )?;
@@ -380,17 +1074,37 @@ impl CacheStorage {
         &self.cache_dir
     }
 
+    /// Remove entries created more than `days` ago, except pinned ones
     pub fn cleanup_old_entries(&self, days: u32) -> Result<usize> {
         let cutoff = Utc::now().timestamp() - (days as i64 * 86400);
 
-        let count = self
-            .conn
-            .execute("DELETE FROM queries WHERE created_at < ?1", params![cutoff])?;
+        let count = self.conn().execute(
+            "DELETE FROM queries WHERE created_at < ?1 AND pinned = 0",
+            params![cutoff],
+        )?;
 
         Ok(count)
     }
 }
 
+/// Lowercased word trigrams of a response (whole text if it has fewer than three words)
+fn response_shingles(response: &str) -> HashSet<String> {
+    let words: Vec<String> = response.split_whitespace().map(str::to_lowercase).collect();
+
+    if words.len() < 3 {
+        return std::iter::once(words.join(" ")).collect();
+    }
+    words.windows(3).map(|window| window.join(" ")).collect()
+}
+
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(b).count() as f32 / union as f32
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -402,6 +1116,20 @@ mod tests {
         (storage, temp_dir)
     }
 
+    #[test]
+    fn test_embedder_is_lazy() {
+        let (storage, _temp) = create_test_storage();
+
+        storage.stats().unwrap();
+        storage.list_all(None).unwrap();
+        assert!(storage.embedder.get().is_none());
+
+        storage
+            .store("q", "q", "hash", "r", "TestProvider", "test-model")
+            .unwrap();
+        assert!(storage.embedder.get().is_some());
+    }
+
     #[allow(dead_code)]
     fn test_store_and_get() {
         let (storage, _temp) = create_test_storage();
@@ -429,6 +1157,15 @@ mod tests {
         assert!(cached.access_count >= 1);
     }
 
+    #[test]
+    fn test_store_rejects_empty_response() {
+        let (storage, _temp) = create_test_storage();
+        assert!(storage
+            .store("nmap scan", "nmap scan", "hash", "  \n", "p", "m")
+            .is_err());
+        assert!(storage.list_all(None).unwrap().is_empty());
+    }
+
     #[test]
     fn test_duplicate_hash_updates() {
         let (storage, _temp) = create_test_storage();
@@ -479,6 +1216,74 @@ mod tests {
         assert!(stats.newest_entry.is_some());
     }
 
+    #[test]
+    fn test_top_queries_and_average() {
+        let (storage, _temp) = create_test_storage();
+
+        storage.store("q1", "n1", "h1", "r1", "p", "m").unwrap();
+        storage.store("q2", "n2", "h2", "r2", "p", "m").unwrap();
+        for _ in 0..3 {
+            storage.get_by_hash("h2").unwrap();
+        }
+
+        let top = storage.top_queries(10).unwrap();
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].query_hash, "h2");
+
+        let stats = storage.stats().unwrap();
+        assert!((stats.avg_access_count - 2.5).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_age_histogram() {
+        let (storage, _temp) = create_test_storage();
+
+        storage.store("q1", "n1", "h1", "r1", "p", "m").unwrap();
+        storage.store("q2", "n2", "h2", "r2", "p", "m").unwrap();
+        storage
+            .conn()
+            .execute(
+                "UPDATE queries SET created_at = created_at - 10 * 86400 WHERE query_hash = 'h2'",
+                [],
+            )
+            .unwrap();
+
+        let histogram = storage.age_histogram().unwrap();
+        let counts: Vec<i64> = histogram.iter().map(|b| b.count).collect();
+        assert_eq!(counts, vec![1, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_list_in_range_bounds_are_inclusive() {
+        let (storage, _temp) = create_test_storage();
+
+        for (hash, created_at) in [("h1", 1_000), ("h2", 2_000), ("h3", 3_000)] {
+            storage.store(hash, hash, hash, "r", "p", "m").unwrap();
+            storage
+                .conn()
+                .execute(
+                    "UPDATE queries SET created_at = ?1 WHERE query_hash = ?2",
+                    params![created_at, hash],
+                )
+                .unwrap();
+        }
+
+        let at = |secs| DateTime::from_timestamp(secs, 0).unwrap();
+        let hashes = |start, end, limit| -> Vec<String> {
+            storage
+                .list_in_range(at(start), at(end), limit)
+                .unwrap()
+                .into_iter()
+                .map(|q| q.query_hash)
+                .collect()
+        };
+
+        assert_eq!(hashes(1_000, 2_000, None), vec!["h2", "h1"]);
+        assert_eq!(hashes(1_001, 2_999, None), vec!["h2"]);
+        assert_eq!(hashes(0, 5_000, Some(2)), vec!["h3", "h2"]);
+        assert!(hashes(3_001, 5_000, None).is_empty());
+    }
+
     #[test]
     fn test_hit_miss_tracking() {
         let (storage, _temp) = create_test_storage();
@@ -491,6 +1296,13 @@ mod tests {
         let stats = storage.stats().unwrap();
         assert_eq!(stats.hit_count, 1);
         assert_eq!(stats.miss_count, 1);
+
+        // Peeking counts neither hits, misses nor accesses
+        assert!(storage.peek_by_hash("h1").unwrap().is_some());
+        assert!(storage.peek_by_hash("nonexistent").unwrap().is_none());
+        let stats = storage.stats().unwrap();
+        assert_eq!((stats.hit_count, stats.miss_count), (1, 1));
+        assert_eq!(storage.peek_by_hash("h1").unwrap().unwrap().access_count, 2);
     }
 
     #[test]
@@ -525,6 +1337,382 @@ mod tests {
         assert!(!removed_again);
     }
 
+    #[test]
+    fn test_search_similar_skips_other_embedding_models() {
+        let (storage, _temp) = create_test_storage();
+
+        storage
+            .store("q", "nmap stealth scan", "h", "r", "p", "m")
+            .unwrap();
+        assert_eq!(
+            storage
+                .search_similar("nmap stealth scan", 0.9, 5)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        storage
+            .conn()
+            .execute(
+                "UPDATE queries SET embedding_model = 'other', embedding_dim = 768",
+                [],
+            )
+            .unwrap();
+        assert!(storage
+            .search_similar("nmap stealth scan", 0.9, 5)
+            .unwrap()
+            .is_empty());
+    }
+
+    /// Embeds every text as the same vector
+    struct ConstantEmbedder;
+
+    impl Embedder for ConstantEmbedder {
+        fn embed(&self, _text: &str) -> Result<Vec<f32>> {
+            Ok(vec![0.6, 0.8])
+        }
+
+        fn model_name(&self) -> &str {
+            "constant"
+        }
+
+        fn dimensions(&self) -> Result<usize> {
+            Ok(2)
+        }
+    }
+
+    /// Fails like an unreachable Ollama
+    struct FailingEmbedder;
+
+    impl Embedder for FailingEmbedder {
+        fn embed(&self, _text: &str) -> Result<Vec<f32>> {
+            anyhow::bail!("connection refused")
+        }
+
+        fn model_name(&self) -> &str {
+            "failing"
+        }
+
+        fn dimensions(&self) -> Result<usize> {
+            anyhow::bail!("connection refused")
+        }
+    }
+
+    #[test]
+    fn test_failing_embedder_degrades_to_exact_matches() {
+        let (storage, temp) = create_test_storage();
+        let storage = storage.with_embedder(Box::new(FailingEmbedder));
+
+        storage.store("q", "nmap scan", "h", "r", "p", "m").unwrap();
+        assert!(storage.get_by_hash("h").unwrap().is_some());
+        assert!(storage
+            .search_similar("nmap scan", 0.0, 5)
+            .unwrap()
+            .is_empty());
+        assert_eq!(storage.embed_failure(), Some("connection refused"));
+
+        // Stored without an embedding, so a working embedder skips it until reindex
+        let default_storage = CacheStorage::new(temp.path()).unwrap();
+        assert!(default_storage.embed_failure().is_none());
+        assert!(default_storage
+            .search_similar("nmap scan", 0.0, 5)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_corrupt_database_is_reset() {
+        let temp = TempDir::new().unwrap();
+        let db_path = CacheStorage::db_path(temp.path());
+        let garbage = "not a sqlite database ".repeat(200);
+        std::fs::write(&db_path, &garbage).unwrap();
+
+        let storage = CacheStorage::new(temp.path()).unwrap();
+        storage.store("q", "nmap scan", "h", "r", "p", "m").unwrap();
+        assert!(storage.peek_by_hash("h").unwrap().is_some());
+
+        let backup = temp.path().join("queries.db.corrupt");
+        assert_eq!(storage.corrupt_backup(), Some(backup.as_path()));
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), garbage);
+
+        // A healthy database is left alone
+        drop(storage);
+        let storage = CacheStorage::new(temp.path()).unwrap();
+        assert!(storage.peek_by_hash("h").unwrap().is_some());
+        assert!(storage.corrupt_backup().is_none());
+    }
+
+    #[test]
+    fn test_with_embedder() {
+        let (storage, temp) = create_test_storage();
+        let storage = storage.with_embedder(Box::new(ConstantEmbedder));
+
+        storage
+            .store("q", "nmap stealth scan", "h", "r", "p", "m")
+            .unwrap();
+        let results = storage.search_similar("sqlmap dump", 0.99, 5).unwrap();
+        assert_eq!(results.len(), 1);
+
+        // The built-in embedder skips rows embedded by another model
+        let default_storage = CacheStorage::new(temp.path()).unwrap();
+        assert!(default_storage
+            .search_similar("nmap stealth scan", 0.0, 5)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_reindex_updates_and_merges() {
+        let (storage, _temp) = create_test_storage();
+        let normalizer = QueryNormalizer::with_defaults().unwrap();
+
+        storage
+            .store("nmap scan", "stale", "oldhash1", "r1", "p", "m")
+            .unwrap();
+        storage
+            .store("NMAP SCAN", "stale", "oldhash2", "r2", "p", "m")
+            .unwrap();
+        storage
+            .store("sqlmap dump", "stale", "oldhash3", "r3", "p", "m")
+            .unwrap();
+
+        let summary = storage.reindex(&normalizer).unwrap();
+        assert_eq!(summary.updated, 2);
+        assert_eq!(summary.merged, 1);
+
+        let entries = storage.list_all(None).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let normalized = normalizer.normalize("nmap scan").unwrap();
+        let merged = storage
+            .peek_by_hash(&normalizer.compute_hash(&normalized))
+            .unwrap()
+            .unwrap();
+        assert_eq!(merged.query_normalized, normalized);
+        assert_eq!(merged.access_count, 2);
+    }
+
+    #[test]
+    fn test_reindex_ignores_stale_hash_collisions() {
+        let (storage, _temp) = create_test_storage();
+        let normalizer = QueryNormalizer::with_defaults().unwrap();
+        let new_hash = |query: &str| normalizer.compute_hash(&normalizer.normalize(query).unwrap());
+
+        // The nmap entry's stale hash is the one the sqlmap entry is about to get
+        storage
+            .store(
+                "nmap scan",
+                "stale",
+                &new_hash("sqlmap dump"),
+                "nmap",
+                "p",
+                "m",
+            )
+            .unwrap();
+        storage
+            .store("sqlmap dump", "stale", "oldhash", "sqlmap", "p", "m")
+            .unwrap();
+        storage
+            .conn()
+            .execute(
+                "UPDATE queries SET last_accessed = last_accessed + 60 WHERE query_hash = 'oldhash'",
+                [],
+            )
+            .unwrap();
+
+        let summary = storage.reindex(&normalizer).unwrap();
+        assert_eq!((summary.updated, summary.merged), (2, 0));
+
+        let nmap = storage.peek_by_hash(&new_hash("nmap scan")).unwrap();
+        assert_eq!(nmap.unwrap().response, "nmap");
+        let sqlmap = storage.peek_by_hash(&new_hash("sqlmap dump")).unwrap();
+        assert_eq!(sqlmap.unwrap().response, "sqlmap");
+    }
+
+    #[test]
+    fn test_similar_search_is_scoped_to_language() {
+        let temp_dir = TempDir::new().unwrap();
+        let english = CacheStorage::new(temp_dir.path()).unwrap();
+        let spanish = CacheStorage::new(temp_dir.path())
+            .unwrap()
+            .with_language(Some("Spanish".to_string()));
+
+        english
+            .store(
+                "nmap stealth scan",
+                "nmap stealth scan",
+                "en",
+                "r",
+                "p",
+                "m",
+            )
+            .unwrap();
+        spanish
+            .store(
+                "nmap stealth scan",
+                "nmap stealth scan",
+                "es",
+                "r",
+                "p",
+                "m",
+            )
+            .unwrap();
+
+        let matches = spanish.search_similar("nmap stealth scan", 0.9, 5).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].0.query_hash, "es");
+        assert_eq!(matches[0].0.language.as_deref(), Some("Spanish"));
+
+        let matches = english.search_similar("nmap stealth scan", 0.9, 5).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].0.language, None);
+    }
+
+    #[test]
+    fn test_nearest_ignores_threshold() {
+        let (storage, _temp) = create_test_storage();
+
+        storage
+            .store("nmap scan", "nmap stealth scan", "h1", "r1", "p", "m")
+            .unwrap();
+        storage
+            .store("sqlmap", "sqlmap dump database", "h2", "r2", "p", "m")
+            .unwrap();
+
+        let nearest = storage.nearest("nmap stealth scan", 5).unwrap();
+        assert_eq!(nearest.len(), 2);
+        assert_eq!(nearest[0].0.query_hash, "h1");
+        assert!(nearest[0].1 > nearest[1].1);
+        assert_eq!(
+            storage
+                .search_similar("nmap stealth scan", 0.9, 5)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(storage.nearest("nmap stealth scan", 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_pinned_entries_survive_cleanup() {
+        let (storage, _temp) = create_test_storage();
+
+        for (query, hash) in [("q1", "h1"), ("q2", "h2"), ("q3", "h3")] {
+            storage.store(query, query, hash, "r", "p", "m").unwrap();
+        }
+        storage
+            .conn()
+            .execute(
+                "UPDATE queries SET created_at = created_at - 40 * 86400 WHERE query_hash != 'h3'",
+                [],
+            )
+            .unwrap();
+
+        assert!(storage.set_pinned("h1", true).unwrap());
+        assert!(!storage.set_pinned("missing", true).unwrap());
+
+        assert_eq!(storage.cleanup_old_entries(30).unwrap(), 1);
+        assert!(storage.peek_by_hash("h1").unwrap().unwrap().pinned);
+        assert!(storage.peek_by_hash("h2").unwrap().is_none());
+        assert!(!storage.peek_by_hash("h3").unwrap().unwrap().pinned);
+
+        storage.set_pinned("h1", false).unwrap();
+        assert_eq!(storage.cleanup_old_entries(30).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_list_by_tag() {
+        let (storage, _temp) = create_test_storage();
+
+        storage
+            .store("nmap scan", "n1", "h1", "r1", "p", "m")
+            .unwrap();
+        storage
+            .store("sqlmap dump", "n2", "h2", "r2", "p", "m")
+            .unwrap();
+        storage
+            .store("kerberoast", "n3", "h3", "r3", "p", "m")
+            .unwrap();
+
+        storage
+            .add_tags("h1", &["recon".to_string(), "Acme".to_string()])
+            .unwrap();
+        storage.add_tags("h2", &["web".to_string()]).unwrap();
+        storage
+            .add_tags(
+                "h2",
+                &["acme".to_string(), "web".to_string(), "a,b".to_string()],
+            )
+            .unwrap();
+
+        let acme = storage.list_by_tag("ACME", None).unwrap();
+        assert_eq!(acme.len(), 2);
+        assert_eq!(storage.list_by_tag("acme", Some(1)).unwrap().len(), 1);
+
+        let web = storage.list_by_tag("web", None).unwrap();
+        assert_eq!(web.len(), 1);
+        assert_eq!(web[0].tags, vec!["acme", "web"]);
+
+        // Tags match whole entries of the list, not substrings
+        assert!(storage.list_by_tag("acm", None).unwrap().is_empty());
+        assert!(storage.list_by_tag("ad", None).unwrap().is_empty());
+        assert!(storage.peek_by_hash("h3").unwrap().unwrap().tags.is_empty());
+    }
+
+    #[test]
+    fn test_dedupe_responses_keeps_most_accessed() {
+        let (storage, _temp) = create_test_storage();
+        let answer =
+            "Use nmap -sV -p- target to scan all ports and detect service versions quickly";
+
+        storage
+            .store("scan all ports", "a", "hash1", answer, "p", "m")
+            .unwrap();
+        storage
+            .store(
+                "full port scan",
+                "b",
+                "hash2",
+                &format!("{} ", answer),
+                "p",
+                "m",
+            )
+            .unwrap();
+        storage
+            .store(
+                "dump database",
+                "c",
+                "hash3",
+                "sqlmap -u url --dump",
+                "p",
+                "m",
+            )
+            .unwrap();
+        storage.get_by_hash("hash2").unwrap();
+
+        let preview = storage
+            .dedupe_responses(RESPONSE_DEDUPE_THRESHOLD, true)
+            .unwrap();
+        assert_eq!(preview.removed, 1);
+        assert_eq!(storage.list_all(None).unwrap().len(), 3);
+
+        let summary = storage
+            .dedupe_responses(RESPONSE_DEDUPE_THRESHOLD, false)
+            .unwrap();
+        assert_eq!(summary.groups, 1);
+        assert_eq!(summary.removed, 1);
+        assert_eq!(
+            summary.bytes_reclaimed,
+            (answer.len() + "scan all ports".len()) as u64
+        );
+
+        assert!(storage.peek_by_hash("hash1").unwrap().is_none());
+        let kept = storage.peek_by_hash("hash2").unwrap().unwrap();
+        assert_eq!(kept.access_count, 3);
+        assert!(storage.peek_by_hash("hash3").unwrap().is_some());
+    }
+
     #[test]
     fn test_access_count_increments() {
         let (storage, _temp) = create_test_storage();
@@ -538,4 +1726,122 @@ mod tests {
         let cached = storage.get_by_hash("h").unwrap().unwrap();
         assert!(cached.access_count >= 6);
     }
+
+    #[test]
+    fn test_shared_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<CacheStorage>();
+
+        let (storage, _temp) = create_test_storage();
+        let storage = std::sync::Arc::new(storage);
+
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let storage = storage.clone();
+                std::thread::spawn(move || {
+                    for i in 0..10 {
+                        let query = format!("thread {} query {}", t, i);
+                        let hash = format!("hash-{}-{}", t, i);
+                        storage
+                            .store(&query, &query, &hash, "response", "p", "m")
+                            .unwrap();
+                        storage.search_similar(&query, 0.5, 3).unwrap();
+                        assert!(storage.get_by_hash(&hash).unwrap().is_some());
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let stats = storage.stats().unwrap();
+        assert_eq!(stats.total_entries, 40);
+        assert_eq!(stats.hit_count, 40);
+    }
+
+    #[test]
+    fn test_two_storages_on_one_database() {
+        let temp_dir = TempDir::new().unwrap();
+        let first = CacheStorage::new(temp_dir.path()).unwrap();
+        let second = CacheStorage::new(temp_dir.path()).unwrap();
+
+        let journal_mode: String = first
+            .conn()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(journal_mode, "wal");
+
+        let writers: Vec<_> = [first, second]
+            .into_iter()
+            .enumerate()
+            .map(|(n, storage)| {
+                std::thread::spawn(move || {
+                    for i in 0..25 {
+                        let hash = format!("hash-{}-{}", n, i);
+                        storage.store(&hash, &hash, &hash, "r", "p", "m").unwrap();
+                        storage.get_by_hash(&hash).unwrap().unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let reopened = CacheStorage::new(temp_dir.path()).unwrap();
+        assert_eq!(reopened.stats().unwrap().total_entries, 50);
+    }
+
+    #[test]
+    fn test_vacuum_shrinks_file_after_clear() {
+        let (storage, _temp) = create_test_storage();
+        let response = "x".repeat(10_000);
+        for i in 0..50 {
+            let hash = format!("hash{}", i);
+            storage
+                .store(&hash, &hash, &hash, &response, "p", "m")
+                .unwrap();
+        }
+        storage.clear().unwrap();
+
+        let summary = storage.vacuum().unwrap();
+        assert!(summary.size_before > 500_000);
+        assert!(summary.size_after < summary.size_before / 10);
+    }
+
+    #[test]
+    fn test_tune_threshold() {
+        let (storage, _temp) = create_test_storage();
+        let scan = "Use nmap -sS -p- target for a fast SYN scan of every port";
+        storage
+            .store("nmap syn scan", "nmap syn scan", "h1", scan, "p", "m")
+            .unwrap();
+        storage
+            .store(
+                "nmap syn scan all ports",
+                "nmap syn scan all ports",
+                "h2",
+                scan,
+                "p",
+                "m",
+            )
+            .unwrap();
+        storage
+            .store(
+                "nmap udp scan",
+                "nmap udp scan",
+                "h3",
+                "Run nmap -sU --top-ports 100 target, UDP scans are slow",
+                "p",
+                "m",
+            )
+            .unwrap();
+
+        let tuning = storage.tune_threshold(&[0.3, 0.7, 1.01], 10).unwrap();
+        assert_eq!((tuning.sampled, tuning.compared), (3, 3));
+        let hits: Vec<_> = tuning.trials.iter().map(|t| (t.hits, t.agreeing)).collect();
+        assert_eq!(hits, vec![(3, 2), (2, 2), (0, 0)]);
+        assert_eq!(tuning.recommended(), Some(0.7));
+    }
 }
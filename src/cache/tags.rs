@@ -0,0 +1,164 @@
+/// Keywords per tag, following the knowledge areas of the system prompt.
+/// A keyword matches at the start of a word, so stems like `enumerat` work.
+const CATEGORIES: &[(&str, &[&str])] = &[
+    (
+        "recon",
+        &[
+            "nmap",
+            "masscan",
+            "rustscan",
+            "port scan",
+            "ping sweep",
+            "enumerat",
+            "service detection",
+        ],
+    ),
+    (
+        "web",
+        &[
+            "burp",
+            "sqlmap",
+            "ffuf",
+            "gobuster",
+            "sql injection",
+            "sqli",
+            "xss",
+            "csrf",
+            "ssrf",
+            "lfi",
+            "web",
+        ],
+    ),
+    (
+        "password",
+        &[
+            "hydra",
+            "john",
+            "hashcat",
+            "crackmapexec",
+            "password",
+            "brute force",
+            "crack",
+        ],
+    ),
+    (
+        "exploit",
+        &[
+            "metasploit",
+            "msfvenom",
+            "searchsploit",
+            "exploit",
+            "reverse shell",
+            "payload",
+        ],
+    ),
+    (
+        "post-exploitation",
+        &[
+            "mimikatz",
+            "winpeas",
+            "linpeas",
+            "persistence",
+            "post-exploitation",
+            "post exploitation",
+            "lateral movement",
+        ],
+    ),
+    (
+        "privesc",
+        &[
+            "gtfobins",
+            "lolbas",
+            "sudo",
+            "suid",
+            "privilege escalation",
+            "privesc",
+        ],
+    ),
+    (
+        "ad",
+        &[
+            "bloodhound",
+            "powerview",
+            "rubeus",
+            "certutil",
+            "kerberos",
+            "kerberoast",
+            "active directory",
+            "ldap",
+            "domain controller",
+        ],
+    ),
+    (
+        "wireless",
+        &["aircrack", "wifite", "kismet", "wifi", "wpa", "wireless"],
+    ),
+    (
+        "reversing",
+        &[
+            "ghidra",
+            "radare2",
+            "gdb",
+            "reverse engineer",
+            "disassembl",
+            "decompil",
+        ],
+    ),
+    (
+        "osint",
+        &["amass", "subfinder", "theharvester", "osint", "subdomain"],
+    ),
+];
+
+/// Lowercase and trim a tag; `None` if it is empty or contains a comma
+/// (tags are stored comma-separated)
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_lowercase();
+    (!tag.is_empty() && !tag.contains(',')).then_some(tag)
+}
+
+/// Tags for the knowledge areas a query mentions
+pub fn classify(query: &str) -> Vec<String> {
+    let query = query.to_lowercase();
+
+    CATEGORIES
+        .iter()
+        .filter(|(_, keywords)| keywords.iter().any(|keyword| starts_word(&query, keyword)))
+        .map(|(tag, _)| tag.to_string())
+        .collect()
+}
+
+/// Whether `keyword` occurs in `text` at the start of a word
+fn starts_word(text: &str, keyword: &str) -> bool {
+    text.match_indices(keyword).any(|(index, _)| {
+        text[..index]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !c.is_alphanumeric())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_matches_knowledge_areas() {
+        assert_eq!(classify("nmap stealth scan"), vec!["recon"]);
+        assert_eq!(classify("Kerberoasting with Rubeus"), vec!["ad"]);
+        assert_eq!(
+            classify("sqlmap dump users and crack their password hashes"),
+            vec!["web", "password"]
+        );
+        assert!(classify("what is a tcp handshake").is_empty());
+        // Keywords only match at the start of a word
+        assert!(classify("take a selfie").is_empty());
+    }
+
+    #[test]
+    fn test_normalize_tag() {
+        assert_eq!(normalize_tag(" Recon ").as_deref(), Some("recon"));
+        assert_eq!(normalize_tag(""), None);
+        assert_eq!(normalize_tag("web,ad"), None);
+    }
+}
@@ -1,4 +1,10 @@
+use chrono::{DateTime, Local, Utc};
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::LLMProvider;
+use crate::util::read_text_file;
 
 #[derive(Parser)]
 #[command(
@@ -14,6 +20,10 @@ pub struct Cli {
     #[arg(value_name = "QUERY")]
     pub query: Option<String>,
 
+    /// Read the query from a UTF-8 text file instead (e.g. a config or log to analyze)
+    #[arg(short, long, value_name = "PATH", conflicts_with = "query")]
+    pub file: Option<PathBuf>,
+
     /// Quiet mode - only show final response (no banners, tables, etc.)
     #[arg(short, long, global = true)]
     pub quiet: bool,
@@ -30,10 +40,118 @@ pub struct Cli {
     #[arg(short, long, global = true)]
     pub learn: bool,
 
+    /// Dry run - show the provider, model and full prompt without calling the API
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Include the N most similar cached Q&A pairs as prior conversation turns
+    #[arg(long = "context", value_name = "N", default_value = "0", global = true)]
+    pub context_entries: usize,
+
+    /// Override the cache similarity threshold (0.0-1.0) for this run
+    #[arg(short, long, value_name = "THRESHOLD", value_parser = parse_threshold, global = true)]
+    pub threshold: Option<f32>,
+
+    /// Print the cache lookup (normalized query, hash, nearest entries, decision) to stderr
+    #[arg(long, global = true)]
+    pub explain_cache: bool,
+
+    /// Skip the startup update check (also: CYX_NO_UPDATE_CHECK=1 or update.auto_check false)
+    #[arg(long, global = true)]
+    pub no_update_check: bool,
+
+    /// Skip TLS certificate validation for self-hosted endpoints (Ollama) with self-signed certs
+    #[arg(long, global = true)]
+    pub insecure: bool,
+
+    /// Append a diagnostic log to this file (level via CYX_LOG, default info)
+    #[arg(long, value_name = "PATH", global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Append one-off instructions to the system prompt (not part of the cache key)
+    #[arg(long, value_name = "TEXT", global = true)]
+    pub system: Option<String>,
+
+    /// Safe mode - authorization reminders and refusal of clearly out-of-scope requests
+    #[arg(long, global = true)]
+    pub safe: bool,
+
+    /// Answer in another language (e.g. es, de, fr); commands and flags stay as-is
+    #[arg(long, value_name = "CODE", global = true)]
+    pub lang: Option<String>,
+
+    /// Stop the response after N characters; it is cached with a truncation marker
+    #[arg(long, value_name = "N", value_parser = parse_max_chars, global = true)]
+    pub max_chars: Option<usize>,
+
+    /// Hide the sources section of answers; they are still cached with their sources
+    #[arg(long, global = true)]
+    pub no_sources: bool,
+
+    /// Give up on a query after N seconds, overriding provider HTTP timeouts (exit code 4)
+    #[arg(long, value_name = "SECS", value_parser = parse_timeout, global = true)]
+    pub timeout: Option<Duration>,
+
+    /// Tag the query's cache entry (repeatable), e.g. --tag recon --tag acme
+    #[arg(long = "tag", value_name = "NAME", value_parser = parse_tag)]
+    pub tags: Vec<String>,
+
+    /// With --learn, print only this section of the answer (e.g. flags, how-it-works, example-usage)
+    #[arg(long, value_name = "NAME", value_parser = parse_section, requires = "learn", conflicts_with = "max_chars")]
+    pub section: Option<String>,
+
+    /// Attach a PNG/JPEG/GIF/WebP image to the query (vision-capable providers; not cached)
+    #[arg(long, value_name = "PATH")]
+    pub image: Option<PathBuf>,
+
+    /// Show the interface with a canned answer: no API key, network or cache needed
+    #[arg(long, conflicts_with_all = ["file", "image", "json_response", "retry_last", "dry_run"])]
+    pub demo: bool,
+
+    /// Re-run the most recently used cached query against the provider, replacing its answer
+    #[arg(long, conflicts_with_all = ["query", "file", "image", "json_response"])]
+    pub retry_last: bool,
+
+    /// Ask the provider for one JSON object (its JSON mode) and print it as-is; not
+    /// cached (Groq, Ollama). Unlike --output json, no cyx metadata is added.
+    #[arg(long, conflicts_with_all = ["learn", "section", "max_chars", "output"])]
+    pub json_response: bool,
+
+    /// Output format: text, ndjson for one JSON event per streamed chunk, or json for
+    /// `cyx compare` results (json and ndjson imply --quiet)
+    #[arg(long, value_enum, default_value = "text", global = true)]
+    pub output: OutputFormat,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
+impl Cli {
+    /// The one-shot query: the positional argument, or the contents of `--file`
+    pub fn query_text(&self) -> anyhow::Result<Option<String>> {
+        let Some(path) = &self.file else {
+            return Ok(self.query.clone());
+        };
+
+        let text = read_text_file(path)?;
+        if text.trim().is_empty() {
+            anyhow::bail!("{} is empty", path.display());
+        }
+        Ok(Some(text))
+    }
+}
+
+/// How query responses are written to stdout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    /// `{"type":"chunk",...}` lines as the response streams, then one `{"type":"done",...}`
+    Ndjson,
+    /// One JSON document once the command finishes (`cyx compare`)
+    Json,
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// Configure Cyx settings
@@ -48,6 +166,9 @@ pub enum Commands {
     /// Check system dependencies and health
     Doctor,
 
+    /// List dependencies with install instructions for missing ones
+    Deps,
+
     /// Manage Ollama models (Advanced - requires Ollama installed)
     Ollama {
         #[command(subcommand)]
@@ -60,12 +181,80 @@ pub enum Commands {
         action: CacheAction,
     },
 
-    /// Check for updates (cargo install cyx --force to update)
+    /// Pre-populate the cache from a file of queries (one per line)
+    Warm {
+        /// File containing queries, one per line (lines starting with # are ignored)
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+
+    /// Fuzzy-search past queries and re-display a cached response
+    Recent,
+
+    /// Ask several providers the same query (bypassing the cache) and show the answers
+    Compare {
+        #[arg(value_name = "QUERY")]
+        query: String,
+
+        /// Providers to ask, comma-separated (groq, perplexity, ollama)
+        #[arg(long, value_name = "LIST", value_delimiter = ',', value_parser = parse_provider, required = true)]
+        providers: Vec<LLMProvider>,
+    },
+
+    /// Show the normalized form and cache key of a query, or the normalization data
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Normalize {
+        #[command(subcommand)]
+        action: Option<NormalizeAction>,
+
+        #[arg(value_name = "QUERY", required = true)]
+        query: Option<String>,
+
+        /// Print a JSON object instead of text
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Browse cached answers in a full-screen view (search, copy, re-run, delete)
+    Tui,
+
+    /// Check for updates and offer to install them (cargo installs only)
     Update {
         /// Check for updates without installing
         #[arg(long)]
         check_only: bool,
     },
+
+    /// Print the version, or build details to include in bug reports
+    Version {
+        /// Also print the build target, rustc version, git commit and bundled libraries
+        #[arg(long)]
+        full: bool,
+
+        /// Print a JSON object with the full build details
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum NormalizeAction {
+    /// List the abbreviations expanded in queries
+    ListAbbreviations,
+
+    /// List the words dropped from queries
+    ListStopwords,
+
+    /// Add or replace an abbreviation in the data directory (used from the next run)
+    AddAbbreviation {
+        /// Word as typed in queries, e.g. asrep
+        #[arg(value_name = "SHORT")]
+        short: String,
+
+        /// Text it expands to, e.g. "as-rep roasting kerberos"
+        #[arg(value_name = "EXPANSION")]
+        expansion: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -73,6 +262,9 @@ pub enum OllamaAction {
     /// List installed models
     List,
 
+    /// Show recommended models and which are installed
+    Recommend,
+
     /// Pull/download a model
     Pull {
         /// Model name (e.g., mistral:7b-instruct)
@@ -96,13 +288,42 @@ pub enum CacheAction {
     /// List cached queries
     List {
         /// Maximum number of entries to show
-        #[arg(short, long, default_value = "10")]
+        #[arg(short = 'n', long, default_value = "10")]
         limit: usize,
+
+        /// Only show entries with this tag
+        #[arg(long, value_name = "NAME", value_parser = parse_tag)]
+        tag: Option<String>,
+
+        /// Only show entries created on or after DATE (YYYY-MM-DD, or an age like 7d)
+        #[arg(long, value_name = "DATE", value_parser = parse_since)]
+        since: Option<DateTime<Utc>>,
+
+        /// Only show entries created on or before DATE (YYYY-MM-DD, or an age like 7d)
+        #[arg(long, value_name = "DATE", value_parser = parse_until)]
+        until: Option<DateTime<Utc>>,
     },
 
+    /// Print the cache directory and database file (just the directory with --quiet)
+    Path,
+
+    /// Show the on-disk size of the database and other cache files
+    Du,
+
     /// Clear all cached queries
     Clear,
 
+    /// Print one cached answer in full, or export it as Markdown
+    Show {
+        /// Query hash of the entry
+        #[arg(value_name = "HASH")]
+        hash: String,
+
+        /// Print a Markdown document (query, answer, sources) instead of the boxed view
+        #[arg(long)]
+        markdown: bool,
+    },
+
     /// Remove a specific cached query by hash
     Remove {
         /// Query hash to remove
@@ -110,12 +331,63 @@ pub enum CacheAction {
         hash: String,
     },
 
-    /// Clean up old cache entries
+    /// Pin an entry so cleanup never removes it
+    Pin {
+        /// Query hash to pin
+        #[arg(value_name = "HASH")]
+        hash: String,
+    },
+
+    /// Unpin an entry, letting cleanup remove it again
+    Unpin {
+        /// Query hash to unpin
+        #[arg(value_name = "HASH")]
+        hash: String,
+    },
+
+    /// Re-normalize and re-embed all entries with the current normalizer and embedder
+    Reindex,
+
+    /// Merge entries whose responses are near-identical, keeping the most accessed one
+    Dedupe {
+        /// Minimum response similarity (0.0-1.0) for two entries to be merged
+        #[arg(long, value_name = "SIMILARITY", default_value = "0.95", value_parser = parse_threshold)]
+        similarity: f32,
+    },
+
+    /// Replay cached queries at a range of similarity thresholds and suggest one
+    Tune {
+        /// Number of most accessed entries to replay against the rest of the cache
+        #[arg(long, value_name = "N", default_value = "1000")]
+        sample: usize,
+    },
+
+    /// Clean up old cache entries (pinned entries are kept)
     Cleanup {
         /// Remove entries older than N days
         #[arg(short, long, default_value = "30")]
         days: u32,
     },
+
+    /// Compact the database file to reclaim space left by deleted entries
+    Vacuum,
+
+    /// Time embedding throughput and similarity search on synthetic queries
+    #[command(hide = true)]
+    Bench {
+        /// Number of synthetic queries to embed
+        #[arg(long, value_name = "N", default_value = "1000")]
+        queries: usize,
+
+        /// Cache sizes to time search_similar at (comma-separated)
+        #[arg(
+            long,
+            value_name = "N,...",
+            value_delimiter = ',',
+            default_value = "100,1000,5000"
+        )]
+        sizes: Vec<usize>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -140,4 +412,80 @@ pub enum ConfigAction {
 
     /// Show all configuration
     Show,
+
+    /// Send a test query to every configured provider and report latency
+    Test,
+
+    /// Print the config file path (just the path with --quiet)
+    Path,
+}
+
+/// Parse a similarity threshold, rejecting values outside 0.0-1.0
+fn parse_threshold(value: &str) -> Result<f32, String> {
+    let threshold: f32 = value
+        .parse()
+        .map_err(|_| format!("'{}' is not a number", value))?;
+
+    if (0.0..=1.0).contains(&threshold) {
+        Ok(threshold)
+    } else {
+        Err(format!("{} is outside 0.0-1.0", threshold))
+    }
+}
+
+fn parse_max_chars(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(max_chars) => Ok(max_chars),
+        Err(_) => Err(format!("'{}' is not a positive whole number", value)),
+    }
+}
+
+fn parse_provider(value: &str) -> Result<LLMProvider, String> {
+    match value.trim().to_lowercase().as_str() {
+        "groq" => Ok(LLMProvider::Groq),
+        "perplexity" => Ok(LLMProvider::Perplexity),
+        "ollama" => Ok(LLMProvider::Ollama),
+        other => Err(format!(
+            "unknown provider '{}' (use groq, perplexity or ollama)",
+            other
+        )),
+    }
+}
+
+fn parse_timeout(value: &str) -> Result<Duration, String> {
+    match value.parse() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(secs) => Ok(Duration::from_secs(secs)),
+        Err(_) => Err(format!(
+            "'{}' is not a positive whole number of seconds",
+            value
+        )),
+    }
+}
+
+fn parse_since(value: &str) -> Result<DateTime<Utc>, String> {
+    crate::util::parse_date_bound(value, false, Local::now()).map_err(|e| e.to_string())
+}
+
+/// A bare date includes the whole day
+fn parse_until(value: &str) -> Result<DateTime<Utc>, String> {
+    crate::util::parse_date_bound(value, true, Local::now()).map_err(|e| e.to_string())
+}
+
+fn parse_section(value: &str) -> Result<String, String> {
+    crate::session::learn_section(value)
+        .map(String::from)
+        .ok_or_else(|| {
+            let names: Vec<String> = crate::session::LEARN_SECTIONS
+                .iter()
+                .map(|header| header.to_lowercase().replace(' ', "-"))
+                .collect();
+            format!("unknown section (expected one of: {})", names.join(", "))
+        })
+}
+
+fn parse_tag(value: &str) -> Result<String, String> {
+    crate::cache::normalize_tag(value)
+        .ok_or_else(|| format!("'{}' is not a valid tag (empty or contains a comma)", value))
 }
@@ -1,14 +1,23 @@
-use super::args::{CacheAction, Commands, ConfigAction, OllamaAction};
+use super::args::{
+    CacheAction, Cli, Commands, ConfigAction, NormalizeAction, OllamaAction, OutputFormat,
+};
 use super::context::CliContext;
 use crate::{
-    cache::CacheStorage,
-    config::{Config, ConfigManager},
-    deps::{DependencyChecker, DependencyStatus},
-    session::InteractiveSession,
-    ui::Display,
+    cache::{
+        bench, embedder_from_config, last_query, CacheStorage, DiskUsage, EmbedderBackend,
+        QueryNormalizer, SimpleEmbedder, VacuumSummary, TUNE_RESPONSE_AGREEMENT,
+    },
+    config::{Config, ConfigManager, DefaultAction, LLMProvider, ProviderTest},
+    deps::{DepCheckResult, DependencyChecker, DependencyKind, DependencyStatus},
+    error::CyxError,
+    session::{answer_language, strip_sources, InteractiveSession},
+    ui::{BoxStyle, BrowserExit, CacheBrowser, Display, TableFormatter},
 };
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use clap::CommandFactory;
 use colored::Colorize;
+use std::path::Path;
 
 pub struct CommandHandler;
 
@@ -18,31 +27,79 @@ impl CommandHandler {
         command: Option<Commands>,
         context: CliContext,
     ) -> Result<()> {
+        // An unreadable config is reported (and recovered) by the command that needs it
+        let ui = ConfigManager::peek()
+            .map(|config| config.ui)
+            .unwrap_or_default();
+        Display::set_box_style(BoxStyle::new(&ui, context.no_tty));
+        // `quiet` also covers JSON/NDJSON output, which a warning would corrupt
+        crate::http::set_quiet(context.quiet);
+
         match command {
             Some(Commands::Setup) => {
                 Self::setup(&context)?;
             }
             Some(Commands::Config { action }) => {
-                Self::config(action)?;
+                Self::config(action, &context)?;
             }
             Some(Commands::Doctor) => {
                 Self::doctor()?;
             }
+            Some(Commands::Deps) => {
+                Self::deps(&context)?;
+            }
             Some(Commands::Ollama { action }) => {
-                Self::ollama(action)?;
+                Self::ollama(action, &context)?;
             }
             Some(Commands::Cache { action }) => {
-                Self::cache(action)?;
+                Self::cache(action, &context)?;
+            }
+            Some(Commands::Warm { file }) => {
+                Self::warm(&file, context)?;
+            }
+            Some(Commands::Recent) => {
+                Self::recent(&context)?;
+            }
+            Some(Commands::Compare { query, providers }) => {
+                Self::compare(&query, &providers, &context)?;
+            }
+            Some(Commands::Normalize {
+                action: Some(action),
+                ..
+            }) => {
+                Self::normalize_data(action)?;
+            }
+            Some(Commands::Normalize { query, json, .. }) => {
+                Self::normalize(query.as_deref().unwrap_or_default(), json, &context)?;
+            }
+            Some(Commands::Tui) => {
+                Self::tui(context)?;
             }
             Some(Commands::Update { check_only }) => {
-                Self::update(check_only)?;
+                Self::update(check_only, &context)?;
+            }
+            Some(Commands::Version { full, json }) => {
+                Self::version(full, json)?;
             }
             None => {
                 // No subcommand specified - require query
-                if let Some(query_text) = query {
+                if context.output == OutputFormat::Json && !context.dry_run {
+                    return Err(CyxError::Config(
+                        "--output json is for cyx compare and --dry-run; use --output ndjson for queries"
+                            .to_string(),
+                    )
+                    .into());
+                }
+                if context.demo {
+                    // The canned answer is the same whatever was asked
+                    let query = query.as_deref().unwrap_or("nmap stealth scan");
+                    InteractiveSession::demo(query, context)?;
+                } else if context.retry_last {
+                    Self::retry_last(context)?;
+                } else if let Some(query_text) = query {
                     Self::one_shot(&query_text, context)?;
                 } else {
-                    anyhow::bail!("No query provided. Usage: cyx \"your query here\"");
+                    Self::default_action(context)?;
                 }
             }
         }
@@ -55,14 +112,363 @@ impl CommandHandler {
         Ok(())
     }
 
+    /// Neither a query nor a subcommand: do what `default_action` says
+    fn default_action(context: CliContext) -> Result<()> {
+        match ConfigManager::load()?.default_action {
+            DefaultAction::Error => return Err(CyxError::NoQuery.into()),
+            DefaultAction::Help => Cli::command().print_help()?,
+            DefaultAction::Setup => {
+                ConfigManager::interactive_setup()?;
+            }
+            DefaultAction::Chat => {
+                let config = Self::load_or_setup_config()?;
+                InteractiveSession::chat(config, context)?;
+            }
+        }
+        Ok(())
+    }
+
     fn one_shot(query: &str, context: CliContext) -> Result<()> {
         let config = Self::load_or_setup_config()?;
         InteractiveSession::one_shot(config, query, context)?;
         Ok(())
     }
 
-    fn config(action: ConfigAction) -> Result<()> {
+    /// Ask the last query again, bypassing the cache
+    fn retry_last(context: CliContext) -> Result<()> {
+        let config = Self::load_or_setup_config()?;
+        let Some(query) = last_query::read(&Config::cache_dir()?)? else {
+            anyhow::bail!("No previous query to retry");
+        };
+
+        if !context.quiet {
+            println!("{}", format!("Query: \"{}\"", query).dimmed());
+        }
+        InteractiveSession::retry(config, &query, context)
+    }
+
+    fn warm(file: &Path, context: CliContext) -> Result<()> {
+        let content = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read query file: {}", file.display()))?;
+
+        let queries: Vec<String> = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect();
+
+        if queries.is_empty() {
+            anyhow::bail!("No queries found in {}", file.display());
+        }
+
+        let config = Self::load_or_setup_config()?;
+        if !config.cache.enabled {
+            anyhow::bail!("Cache is disabled. Enable it with: cyx config set cache.enabled true");
+        }
+
+        let threshold = config.cache.metric_threshold(context.threshold);
+
+        if context.should_show_progress() {
+            Display::info(&format!("Warming cache with {} queries...", queries.len()));
+        }
+
+        let summary = InteractiveSession::warm(config, &queries, threshold, context)?;
+
+        println!("{}", "✓ Cache warm-up complete".green());
+        println!("  Added: {}", summary.added.to_string().green());
+        println!(
+            "  Already cached: {}",
+            summary.already_cached.to_string().dimmed()
+        );
+        if summary.failed > 0 {
+            println!("  Failed: {}", summary.failed.to_string().red());
+        }
+
+        Ok(())
+    }
+
+    fn compare(query: &str, providers: &[LLMProvider], context: &CliContext) -> Result<()> {
+        let mut unique: Vec<LLMProvider> = Vec::new();
+        for provider in providers {
+            if !unique.contains(provider) {
+                unique.push(provider.clone());
+            }
+        }
+        let config = Self::load_or_setup_config()?;
+        let answers = InteractiveSession::compare(&config, query, &unique, context);
+
+        if context.output == OutputFormat::Json {
+            println!("{}", serde_json::to_string_pretty(&answers)?);
+        } else {
+            for answer in &answers {
+                let title = answer.provider.to_uppercase();
+                match (&answer.response, &answer.error) {
+                    (Some(response), _) => {
+                        Display::stream_box_section(&title, strip_sources(response).trim_end())
+                    }
+                    (None, error) => Display::stream_box_section(
+                        &title,
+                        &format!("Error: {}", error.as_deref().unwrap_or("no response")),
+                    ),
+                }
+
+                let mut details = vec![format!("{:.2}s", answer.latency_ms as f64 / 1000.0)];
+                if let Some(model) = &answer.model {
+                    details.insert(0, model.clone());
+                }
+                if let Some(usage) = answer.usage {
+                    details.push(format!(
+                        "~{} prompt / ~{} completion tokens",
+                        usage.prompt_tokens, usage.completion_tokens
+                    ));
+                }
+                println!("  {}", details.join(" · ").dimmed());
+            }
+        }
+
+        if answers.iter().all(|answer| answer.response.is_none()) {
+            anyhow::bail!("No provider answered");
+        }
+        Ok(())
+    }
+
+    /// Print the normalized form and cache key `query` would be looked up under
+    fn normalize(query: &str, json: bool, context: &CliContext) -> Result<()> {
+        #[derive(serde::Serialize)]
+        struct NormalizedQuery<'a> {
+            query: &'a str,
+            normalized: &'a str,
+            hash: &'a str,
+            language: Option<&'a str>,
+        }
+
+        let config = ConfigManager::load()?;
+        let normalizer = QueryNormalizer::from_config(&config)?;
+        let normalized = normalizer.normalize(query)?;
+        let language = answer_language(context, &config);
+        let hash = normalizer.compute_scoped_hash(&normalized, language.as_deref());
+
+        if json {
+            let output = NormalizedQuery {
+                query,
+                normalized: &normalized,
+                hash: &hash,
+                language: language.as_deref(),
+            };
+            println!("{}", serde_json::to_string(&output)?);
+            return Ok(());
+        }
+
+        println!("{}      {}", "Query:".bold(), query);
+        println!("{} {}", "Normalized:".bold(), normalized);
+        println!("{}       {}", "Hash:".bold(), hash);
+        if let Some(language) = language {
+            println!(
+                "{}   {} (answers are cached per language)",
+                "Language:".bold(),
+                language
+            );
+        }
+        Ok(())
+    }
+
+    fn version(full: bool, json: bool) -> Result<()> {
+        #[derive(serde::Serialize)]
+        struct BuildInfo {
+            version: &'static str,
+            target: &'static str,
+            profile: &'static str,
+            rustc: &'static str,
+            git_commit: &'static str,
+            build_date: &'static str,
+            embedder: &'static str,
+            sqlite: &'static str,
+        }
+
+        let info = BuildInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            target: env!("CYX_BUILD_TARGET"),
+            profile: if cfg!(debug_assertions) {
+                "debug"
+            } else {
+                "release"
+            },
+            rustc: env!("CYX_RUSTC_VERSION"),
+            git_commit: env!("CYX_GIT_HASH"),
+            build_date: env!("CYX_BUILD_DATE"),
+            embedder: SimpleEmbedder::MODEL_NAME,
+            sqlite: rusqlite::version(),
+        };
+
+        if json {
+            println!("{}", serde_json::to_string(&info)?);
+            return Ok(());
+        }
+
+        println!("cyx {}", info.version);
+        if full {
+            println!(
+                "{}     {} ({})",
+                "Target:".bold(),
+                info.target,
+                info.profile
+            );
+            println!("{}      {}", "Rustc:".bold(), info.rustc);
+            println!("{}     {}", "Commit:".bold(), info.git_commit);
+            println!("{}      {}", "Built:".bold(), info.build_date);
+            println!("{}   {}", "Embedder:".bold(), info.embedder);
+            println!("{}     {} (bundled)", "SQLite:".bold(), info.sqlite);
+        }
+        Ok(())
+    }
+
+    fn normalize_data(action: NormalizeAction) -> Result<()> {
+        let config = ConfigManager::load()?;
+
+        match action {
+            NormalizeAction::ListAbbreviations => {
+                let normalizer = QueryNormalizer::from_config(&config)?;
+                let abbreviations = normalizer.abbreviations();
+                let width = abbreviations
+                    .keys()
+                    .map(|short| short.len())
+                    .max()
+                    .unwrap_or(0);
+                for (short, expansion) in &abbreviations {
+                    println!("{:width$}  {}", short.cyan(), expansion, width = width);
+                }
+                println!();
+                println!(
+                    "{}",
+                    format!("{} abbreviations", abbreviations.len()).dimmed()
+                );
+            }
+            NormalizeAction::ListStopwords => {
+                let normalizer = QueryNormalizer::from_config(&config)?;
+                let stopwords = normalizer.stopwords();
+                for stopword in &stopwords {
+                    println!("{}", stopword);
+                }
+                println!();
+                println!("{}", format!("{} stopwords", stopwords.len()).dimmed());
+            }
+            NormalizeAction::AddAbbreviation { short, expansion } => {
+                let data_dir = match config.data_dir() {
+                    Some(dir) => dir,
+                    None => {
+                        let dir = Config::config_dir()?.join("data");
+                        let mut config = config;
+                        config.data_dir = Some(dir.clone());
+                        ConfigManager::save(&config)?;
+                        Display::info(&format!("Set data_dir to {}", dir.display()));
+                        dir
+                    }
+                };
+
+                let path = QueryNormalizer::add_abbreviation(&data_dir, &short, &expansion)?;
+                println!(
+                    "{}",
+                    format!("✓ {} -> {}", short.trim().to_lowercase(), expansion.trim()).green()
+                );
+                println!("  {}", format!("Saved to {}", path.display()).dimmed());
+                println!(
+                    "  {}",
+                    "Run `cyx cache reindex` to apply it to queries already cached".dimmed()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn recent(context: &CliContext) -> Result<()> {
+        if context.no_tty {
+            anyhow::bail!("cyx recent needs an interactive terminal (drop --no-tty)");
+        }
+
+        let storage = CacheStorage::new(Config::cache_dir()?)?;
+        InteractiveSession::report_cache_reset(&storage, context);
+        let queries = storage.list_all(None)?;
+
+        if queries.is_empty() {
+            println!("{}", "No cached queries yet.".yellow());
+            println!("Run some queries to populate the cache!");
+            return Ok(());
+        }
+
+        let items: Vec<&str> = queries
+            .iter()
+            .map(|query| query.query_original.as_str())
+            .collect();
+
+        let selection =
+            dialoguer::FuzzySelect::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                .with_prompt("Search past queries")
+                .items(&items)
+                .default(0)
+                .interact_opt()?;
+
+        let Some(index) = selection else {
+            println!("{}", "Cancelled.".dimmed());
+            return Ok(());
+        };
+
+        // Re-fetch through the cache so the access is recorded
+        let selected = &queries[index];
+        let cached = storage
+            .get_by_hash(&selected.query_hash)?
+            .unwrap_or_else(|| selected.clone());
+
+        if !context.quiet {
+            println!(
+                "{}",
+                format!("Query: \"{}\"", cached.query_original).dimmed()
+            );
+        }
+        InteractiveSession::show_cached(&cached, context);
+
+        Ok(())
+    }
+
+    fn tui(context: CliContext) -> Result<()> {
+        if context.no_tty {
+            anyhow::bail!("cyx tui needs an interactive terminal (drop --no-tty)");
+        }
+
+        let storage = CacheStorage::new(Config::cache_dir()?)?;
+        InteractiveSession::report_cache_reset(&storage, &context);
+        if storage.list_all(Some(1))?.is_empty() {
+            println!("{}", "No cached queries yet.".yellow());
+            println!("Run some queries to populate the cache!");
+            return Ok(());
+        }
+
+        match CacheBrowser::new(&storage)?.run()? {
+            BrowserExit::Quit => {}
+            BrowserExit::Rerun(query) => {
+                drop(storage);
+                if !context.quiet {
+                    println!("{}", format!("Query: \"{}\"", query).dimmed());
+                }
+                let config = Self::load_or_setup_config()?;
+                InteractiveSession::retry(config, &query, context)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn config(action: ConfigAction, context: &CliContext) -> Result<()> {
         match action {
+            ConfigAction::Path => {
+                let path = Config::config_path()?;
+                if context.quiet {
+                    println!("{}", path.display());
+                } else {
+                    println!("{} {}", "Config file:".bold(), path.display());
+                }
+            }
             ConfigAction::Set { key, value } => {
                 ConfigManager::set_value(&key, &value)?;
             }
@@ -70,6 +476,38 @@ impl CommandHandler {
                 let value = ConfigManager::get_value(&key)?;
                 println!("{}: {}", key, value);
             }
+            ConfigAction::Test => {
+                let config = ConfigManager::load()?;
+                println!("{}", "Testing providers...".cyan());
+
+                let mut table = TableFormatter::new(&["Provider", "Status", "Latency", "Details"]);
+                for (provider, outcome) in ConfigManager::test_all_providers(&config) {
+                    let mut name = format!("{:?}", provider);
+                    if provider == config.provider {
+                        name.push_str(" (primary)");
+                    }
+
+                    let row = match outcome {
+                        ProviderTest::Skipped(reason) => {
+                            vec![name, "Skipped".to_string(), "-".to_string(), reason]
+                        }
+                        ProviderTest::Passed(latency) => vec![
+                            name,
+                            "OK".to_string(),
+                            format!("{} ms", latency.as_millis()),
+                            String::new(),
+                        ],
+                        ProviderTest::Failed { latency, error } => vec![
+                            name,
+                            "Failed".to_string(),
+                            format!("{} ms", latency.as_millis()),
+                            error,
+                        ],
+                    };
+                    table.add_row(row);
+                }
+                table.print();
+            }
             ConfigAction::Show => {
                 let config = ConfigManager::load()?;
                 println!("{}", "Current Configuration".bold().cyan());
@@ -78,6 +516,9 @@ impl CommandHandler {
                 println!("{}", "Provider:".bold());
                 println!("  {:?}", config.provider);
                 println!();
+                println!("{}", "Bare `cyx`:".bold());
+                println!("  {:?}", config.default_action);
+                println!();
                 println!("{}", "API Keys:".bold());
                 println!(
                     "  Groq: {}",
@@ -110,6 +551,116 @@ impl CommandHandler {
                     }
                 );
                 println!("  TTL: {} days", config.cache.ttl_days);
+                println!(
+                    "  Similarity: {:?} (threshold {})",
+                    config.cache.similarity_metric, config.cache.similarity_threshold
+                );
+                match config.cache.embedder_backend {
+                    EmbedderBackend::Simple => println!("  Embedder: simple (built-in)"),
+                    EmbedderBackend::Ollama => println!(
+                        "  Embedder: Ollama ({})",
+                        config.cache.ollama_embedding_model
+                    ),
+                }
+                let steps = config.normalize.enabled_steps();
+                println!(
+                    "  Normalization: {}",
+                    if steps.is_empty() {
+                        "off".to_string()
+                    } else {
+                        steps.join(", ")
+                    }
+                );
+                println!();
+                println!("{}", "Updates:".bold());
+                println!(
+                    "  Auto check: {}",
+                    if config.update.auto_check {
+                        "Yes".green()
+                    } else {
+                        "No".yellow()
+                    }
+                );
+                println!("  Source: {:?}", config.update.source);
+                println!();
+                println!("{}", "Network:".bold());
+                println!(
+                    "  Allow invalid certs: {}",
+                    if config.network.allow_invalid_certs {
+                        "Yes (insecure)".red()
+                    } else {
+                        "No".green()
+                    }
+                );
+                println!(
+                    "  Empty response retries: {}",
+                    config.network.empty_response_retries
+                );
+                println!(
+                    "  Rate limit: {}",
+                    if config.network.rps > 0.0 {
+                        format!("{} requests/s", config.network.rps)
+                    } else {
+                        "Unlimited".to_string()
+                    }
+                );
+                println!(
+                    "  Slow response note: {}",
+                    if config.network.slow_response_secs > 0 {
+                        format!("after {}s", config.network.slow_response_secs)
+                    } else {
+                        "Off".to_string()
+                    }
+                );
+                println!();
+                println!("{}", "UI:".bold());
+                println!(
+                    "  Box width: {}",
+                    config
+                        .ui
+                        .box_width
+                        .map_or_else(|| "auto".to_string(), |width| width.to_string())
+                );
+                println!(
+                    "  ASCII boxes: {}",
+                    if config.ui.ascii { "Yes" } else { "No" }
+                );
+                println!();
+                println!("{}", "Response:".bold());
+                println!(
+                    "  Language: {}",
+                    config.response.language.as_deref().unwrap_or("English")
+                );
+                println!(
+                    "  Safe mode: {}",
+                    if config.response.safe_mode {
+                        "Yes".green()
+                    } else {
+                        "No".normal()
+                    }
+                );
+                println!(
+                    "  Strip thinking: {}",
+                    if config.response.strip_thinking {
+                        "Yes".green()
+                    } else {
+                        "No".normal()
+                    }
+                );
+                println!(
+                    "  Enforce citation format: {}",
+                    if config.response.enforce_citation_format {
+                        "Yes".green()
+                    } else {
+                        "No".normal()
+                    }
+                );
+                if !config.response.trusted_sources.is_empty() {
+                    println!(
+                        "  Trusted sources: {}",
+                        config.response.trusted_sources.join(", ")
+                    );
+                }
                 println!();
                 println!("{}", "Config file:".dimmed());
                 println!(
@@ -127,9 +678,9 @@ impl CommandHandler {
         println!("{}", "─".repeat(60));
         println!();
 
-        // Check if using cloud providers (Groq/Perplexity)
-        if let Ok(config) = ConfigManager::load() {
-            match config.provider {
+        // Cloud providers (Groq/Perplexity) need no local dependencies
+        let cloud_provider = match ConfigManager::load() {
+            Ok(config) => match config.provider {
                 crate::config::LLMProvider::Groq | crate::config::LLMProvider::Perplexity => {
                     println!(
                         "{} Using {} - no system dependencies required!",
@@ -142,61 +693,99 @@ impl CommandHandler {
                         "For cloud providers (Groq/Perplexity), everything works out of the box."
                             .dimmed()
                     );
-                    println!();
-                    return Ok(());
+                    true
                 }
-                _ => {}
+                _ => false,
+            },
+            Err(_) => false,
+        };
+
+        let checker = DependencyChecker::new()?;
+        let results = checker.check_all()?;
+        let (tools, core): (Vec<_>, Vec<_>) = results
+            .into_iter()
+            .partition(|result| result.kind == DependencyKind::Tool);
+
+        if !cloud_provider {
+            for result in &core {
+                print_dependency(result, true);
             }
         }
 
+        println!();
+        println!("{}", "Security Tools".bold().cyan());
+        println!("{}", "─".repeat(60));
+        for result in &tools {
+            print_dependency(result, false);
+        }
+
+        let available = tools
+            .iter()
+            .filter(|result| matches!(result.status, DependencyStatus::Installed { .. }))
+            .count();
+        println!();
+        println!(
+            "{}",
+            format!("{} of {} tools found on PATH", available, tools.len()).dimmed()
+        );
+
+        println!();
+        Ok(())
+    }
+
+    fn deps(context: &CliContext) -> Result<()> {
+        use crate::deps::OllamaInstaller;
+
         let checker = DependencyChecker::new()?;
         let results = checker.check_all()?;
 
-        for result in results {
-            match result.status {
-                DependencyStatus::Installed { ref version } => {
-                    println!(
-                        "{} {} {}",
-                        "[✓]".green(),
-                        result.name,
-                        format!("({})", version).dimmed()
-                    );
-                }
-                DependencyStatus::NotInstalled => {
-                    println!(
-                        "{} {} {}",
-                        "[✗]".red(),
-                        result.name,
-                        "(not installed)".dimmed()
-                    );
-                    println!("    {}", result.instructions.dimmed());
-                }
-                DependencyStatus::WrongVersion {
-                    ref current,
-                    ref required,
-                } => {
-                    println!(
-                        "{} {} {} {}",
-                        "[!]".yellow(),
-                        result.name,
-                        format!("(current: {}, required: {})", current, required).dimmed(),
-                        "(wrong version)".yellow()
-                    );
-                }
+        for (title, kind) in [
+            ("Dependencies", DependencyKind::Core),
+            ("Security Tools", DependencyKind::Tool),
+        ] {
+            println!("{}", title.bold().cyan());
+            println!("{}", "─".repeat(60));
+            for result in results.iter().filter(|result| result.kind == kind) {
+                print_dependency(result, true);
             }
+            println!();
+        }
+
+        let ollama_missing = results.iter().any(|result| {
+            result.name == "Ollama" && matches!(result.status, DependencyStatus::NotInstalled)
+        });
+        if !ollama_missing || context.no_tty {
+            return Ok(());
+        }
+
+        let install = dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+            .with_prompt("Ollama is not installed. Install it now?")
+            .default(false)
+            .interact()?;
+
+        if install {
+            println!("{}", "Installing Ollama...".cyan());
+            OllamaInstaller::install()?;
+            OllamaInstaller::start_service()?;
+            println!("{}", "✓ Ollama installed".green());
+            println!(
+                "Switch to it with: {}",
+                "cyx config set provider ollama".cyan()
+            );
         }
 
-        println!();
         Ok(())
     }
 
-    fn ollama(action: OllamaAction) -> Result<()> {
-        use crate::config::OllamaConfig;
+    fn ollama(action: OllamaAction, context: &CliContext) -> Result<()> {
         use crate::llm::OllamaProvider;
 
+        let config = ConfigManager::load()?;
+        let allow_invalid_certs = config.network.allow_invalid_certs || context.insecure;
+
         match action {
             OllamaAction::List => {
-                let provider = OllamaProvider::new(OllamaConfig::default())?;
+                let provider = OllamaProvider::new(config.ollama, allow_invalid_certs)?;
                 let models = provider.list_models()?;
 
                 if models.is_empty() {
@@ -212,14 +801,65 @@ impl CommandHandler {
                     }
                 }
             }
+            OllamaAction::Recommend => {
+                let profiles = crate::llm::model_profiles()?;
+                // Recommendations are still useful when Ollama is not running
+                let installed = OllamaProvider::new(config.ollama.clone(), allow_invalid_certs)
+                    .and_then(|provider| provider.list_models())
+                    .ok();
+
+                println!("{}", "Recommended Ollama models:".bold());
+                for profile in &profiles {
+                    let status = match &installed {
+                        Some(models) if models.iter().any(|m| profile.matches(m)) => {
+                            "installed".green()
+                        }
+                        Some(_) => "not installed".dimmed(),
+                        None => "".normal(),
+                    };
+                    println!(
+                        "  • {} {} {}",
+                        profile.name.cyan(),
+                        format!("({})", profile.size).dimmed(),
+                        status
+                    );
+                    println!("    {}", profile.description);
+                }
+
+                if installed.is_none() {
+                    println!(
+                        "\n{}",
+                        "Could not reach Ollama to check installed models.".yellow()
+                    );
+                }
+                println!(
+                    "\nUse {} to download a model.",
+                    "cyx ollama pull <model>".cyan()
+                );
+            }
             OllamaAction::Pull { model } => {
                 println!("{}", format!("Downloading {}...", model).cyan());
-                OllamaProvider::pull_model(&model, &OllamaConfig::default().base_url)?;
+                let pb = Display::create_download_bar("pulling");
+                let result = OllamaProvider::pull_model(
+                    &model,
+                    &config.ollama.base_url,
+                    allow_invalid_certs,
+                    |progress| {
+                        if let (Some(total), Some(completed)) = (progress.total, progress.completed)
+                        {
+                            pb.set_length(total);
+                            pb.set_position(completed);
+                        }
+                        pb.set_message(progress.status.clone());
+                    },
+                );
+                pb.finish_and_clear();
+                result?;
                 println!("{}", format!("✓ Successfully pulled {}", model).green());
             }
             OllamaAction::Remove { model } => {
                 println!("{}", format!("Removing {}...", model).cyan());
-                OllamaProvider::remove_model(&model, &OllamaConfig::default().base_url)?;
+                OllamaProvider::remove_model(&model, &config.ollama.base_url, allow_invalid_certs)?;
                 println!("{}", format!("✓ Successfully removed {}", model).green());
             }
         }
@@ -227,12 +867,67 @@ impl CommandHandler {
         Ok(())
     }
 
-    fn cache(action: CacheAction) -> Result<()> {
+    fn cache(action: CacheAction, context: &CliContext) -> Result<()> {
         let cache_dir = Config::cache_dir()?;
-        let storage = CacheStorage::new(&cache_dir)?;
+
+        // Opened only by the actions that use it, so `path` and `du` still work
+        // when the database can't be opened
+        let open_storage = || -> Result<CacheStorage> {
+            let storage = CacheStorage::new(&cache_dir)?;
+            InteractiveSession::report_cache_reset(&storage, context);
+            Ok(storage)
+        };
 
         match action {
+            CacheAction::Path => {
+                if context.quiet {
+                    println!("{}", cache_dir.display());
+                } else {
+                    println!("{}  {}", "Cache directory:".bold(), cache_dir.display());
+                    println!(
+                        "{}         {}",
+                        "Database:".bold(),
+                        CacheStorage::db_path(&cache_dir).display()
+                    );
+                }
+            }
+            CacheAction::Du => {
+                let usage = DiskUsage::measure(&cache_dir)?;
+
+                println!("{}", "Cache Disk Usage".bold().cyan());
+                let mut table = TableFormatter::new(&["Item", "Path", "Size"]);
+                let mut add_row = |item: &str, path: &Path, size: u64| {
+                    table.add_row(vec![
+                        item.to_string(),
+                        path.display().to_string(),
+                        format_bytes(size as i64),
+                    ]);
+                };
+
+                add_row(
+                    "Database",
+                    &CacheStorage::db_path(&cache_dir),
+                    usage.database,
+                );
+                let models_dir = DiskUsage::legacy_models_dir(&cache_dir);
+                if let Some(size) = usage.legacy_models {
+                    add_row("Legacy embedding models", &models_dir, size);
+                }
+                add_row("Other files", &cache_dir, usage.other);
+                add_row("Total", &cache_dir, usage.total());
+                table.print();
+
+                if usage.legacy_models.is_some() {
+                    println!();
+                    println!(
+                        "{} Embedding models are unused since v0.3.0 and can be deleted: {}",
+                        "[*]".cyan(),
+                        format!("rm -rf {}", models_dir.display()).cyan()
+                    );
+                }
+            }
             CacheAction::Stats => {
+                let storage = open_storage()?;
                 let stats = storage.stats()?;
 
                 println!("{}", "Cache Statistics".bold().cyan());
@@ -253,6 +948,7 @@ impl CommandHandler {
                     let hit_rate = (stats.hit_count as f64 / total_requests as f64) * 100.0;
                     println!("  Hit rate: {:.1}%", hit_rate);
                 }
+                println!("  Avg accesses per entry: {:.1}", stats.avg_access_count);
 
                 if let Some(oldest) = stats.oldest_entry {
                     println!(
@@ -271,29 +967,118 @@ impl CommandHandler {
                     "  Cache location: {}",
                     cache_dir.display().to_string().dimmed()
                 );
+
+                let top_queries = storage.top_queries(10)?;
+                if !top_queries.is_empty() {
+                    println!();
+                    println!("{}", "Most Accessed Queries".bold().cyan());
+                    let mut table = TableFormatter::new(&["Query", "Accesses", "Last access"]);
+                    for query in top_queries {
+                        table.add_row(vec![
+                            truncate_chars(&query.query_original, 50),
+                            query.access_count.to_string(),
+                            query.last_accessed.format("%Y-%m-%d %H:%M").to_string(),
+                        ]);
+                    }
+                    table.print();
+                }
+
+                if stats.total_entries > 0 {
+                    println!();
+                    println!("{}", "Entry Ages".bold().cyan());
+                    let mut table = TableFormatter::new(&["Age", "Entries", ""]);
+                    for bucket in storage.age_histogram()? {
+                        let bar_len = (bucket.count * 30 / stats.total_entries) as usize;
+                        table.add_row(vec![
+                            bucket.label,
+                            bucket.count.to_string(),
+                            "█".repeat(bar_len),
+                        ]);
+                    }
+                    table.print();
+                }
             }
 
-            CacheAction::List { limit } => {
-                let queries = storage.list_all(Some(limit))?;
+            CacheAction::List {
+                limit,
+                tag,
+                since,
+                until,
+            } => {
+                let storage = open_storage()?;
+                let in_range = since.is_some() || until.is_some();
+                let queries = if in_range {
+                    let start = since.unwrap_or(DateTime::UNIX_EPOCH);
+                    let end = until.unwrap_or_else(Utc::now);
+                    // With a tag too, filter the whole range before applying the limit
+                    let mut queries =
+                        storage.list_in_range(start, end, tag.is_none().then_some(limit))?;
+                    if let Some(tag) = &tag {
+                        queries.retain(|query| query.tags.contains(tag));
+                        queries.truncate(limit);
+                    }
+                    queries
+                } else {
+                    match &tag {
+                        Some(tag) => storage.list_by_tag(tag, Some(limit))?,
+                        None => storage.list_all(Some(limit))?,
+                    }
+                };
 
                 if queries.is_empty() {
-                    println!("{}", "No cached queries yet.".yellow());
-                    println!("Run some queries to populate the cache!");
+                    match tag {
+                        _ if in_range => {
+                            println!("{}", "No cached queries in that date range.".yellow())
+                        }
+                        Some(tag) => println!(
+                            "{}",
+                            format!("No cached queries tagged '{}'.", tag).yellow()
+                        ),
+                        None => {
+                            println!("{}", "No cached queries yet.".yellow());
+                            println!("Run some queries to populate the cache!");
+                        }
+                    }
                     return Ok(());
                 }
 
-                println!(
-                    "{}",
-                    format!("Recent Cached Queries (showing {})", queries.len())
-                        .bold()
-                        .cyan()
-                );
+                let heading = match &tag {
+                    Some(tag) => format!(
+                        "Cached Queries tagged '{}' (showing {})",
+                        tag,
+                        queries.len()
+                    ),
+                    None => format!("Recent Cached Queries (showing {})", queries.len()),
+                };
+                let heading = match (since, until) {
+                    (None, None) => heading,
+                    (since, until) => format!(
+                        "{}, created {} to {}",
+                        heading,
+                        since.map_or("the start".to_string(), |d| format_local(&d)),
+                        until.map_or("now".to_string(), |d| format_local(&d))
+                    ),
+                };
+                println!("{}", heading.bold().cyan());
                 println!("{}", "─".repeat(80));
 
                 for query in queries {
                     println!();
-                    println!("  {}: {}", "Query".bold(), query.query_original.cyan());
+                    let pinned = if query.pinned {
+                        format!(" {}", "[pinned]".yellow())
+                    } else {
+                        String::new()
+                    };
+                    println!(
+                        "  {}: {}{}",
+                        "Query".bold(),
+                        query.query_original.cyan(),
+                        pinned
+                    );
                     println!("  {}: {}", "Hash".dimmed(), query.query_hash.dimmed());
+                    if !query.tags.is_empty() {
+                        println!("  {}: {}", "Tags".dimmed(), query.tags.join(", "));
+                    }
                     println!(
                         "  {}: {} | {}: {}",
                         "Provider".dimmed(),
@@ -308,6 +1093,13 @@ impl CommandHandler {
                         "Last access".dimmed(),
                         query.last_accessed.format("%Y-%m-%d %H:%M")
                     );
+                    if in_range {
+                        println!(
+                            "  {}: {}",
+                            "Created".dimmed(),
+                            format_local(&query.created_at)
+                        );
+                    }
 
                     let response_preview = if query.response.len() > 100 {
                         format!("{}...", &query.response[..100])
@@ -319,6 +1111,7 @@ impl CommandHandler {
             }
 
             CacheAction::Clear => {
+                let storage = open_storage()?;
                 println!("{}", "This will delete all cached queries.".yellow());
                 let confirm =
                     dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
@@ -329,12 +1122,41 @@ impl CommandHandler {
                 if confirm {
                     let count = storage.clear()?;
                     println!("{}", format!("✓ Cleared {} cached queries", count).green());
+                    print_vacuum_summary(&storage.vacuum()?);
                 } else {
                     println!("{}", "Cancelled.".dimmed());
                 }
             }
 
+            CacheAction::Show { hash, markdown } => {
+                let storage = open_storage()?;
+                // A read-only lookup, so exporting an answer doesn't count as a cache hit
+                let Some(cached) = storage.peek_by_hash(&hash)? else {
+                    let known: Vec<String> = storage
+                        .list_all(Some(5))?
+                        .iter()
+                        .map(|entry| entry.query_hash.chars().take(8).collect())
+                        .collect();
+                    if known.is_empty() {
+                        anyhow::bail!("Query with hash {} not found: the cache is empty", hash);
+                    }
+                    anyhow::bail!(
+                        "Query with hash {} not found in cache. Recent hashes: {}... \
+                         (see `cyx cache list` for full hashes)",
+                        hash,
+                        known.join("..., ")
+                    );
+                };
+
+                if markdown {
+                    print!("{}", InteractiveSession::cached_markdown(&cached));
+                } else {
+                    InteractiveSession::show_cached(&cached, context);
+                }
+            }
+
             CacheAction::Remove { hash } => {
+                let storage = open_storage()?;
                 let removed = storage.remove_by_hash(&hash)?;
                 if removed {
                     println!(
@@ -349,7 +1171,157 @@ impl CommandHandler {
                 }
             }
 
+            CacheAction::Pin { ref hash } | CacheAction::Unpin { ref hash } => {
+                let storage = open_storage()?;
+                let pin = matches!(action, CacheAction::Pin { .. });
+                if storage.set_pinned(hash, pin)? {
+                    let verb = if pin { "Pinned" } else { "Unpinned" };
+                    println!("{}", format!("✓ {} cached query {}", verb, hash).green());
+                } else {
+                    println!(
+                        "{}",
+                        format!("Query with hash {} not found in cache", hash).yellow()
+                    );
+                }
+            }
+
+            CacheAction::Reindex => {
+                let storage = open_storage()?;
+                println!("{}", "Reindexing cache entries...".cyan());
+                let config = ConfigManager::load()?;
+                let normalizer = QueryNormalizer::from_config(&config)?;
+                let storage = storage.with_embedder(embedder_from_config(
+                    &config,
+                    config.network.allow_invalid_certs || context.insecure,
+                )?);
+                let summary = storage.reindex(&normalizer)?;
+
+                println!(
+                    "{}",
+                    format!("✓ Reindexed {} cache entries", summary.updated).green()
+                );
+                if summary.merged > 0 {
+                    println!(
+                        "  {}",
+                        format!("Merged {} duplicate entries", summary.merged).dimmed()
+                    );
+                }
+            }
+
+            CacheAction::Dedupe { similarity } => {
+                let storage = open_storage()?;
+                let summary = storage.dedupe_responses(similarity, context.dry_run)?;
+
+                if summary.removed == 0 {
+                    println!("{}", "No near-identical responses found.".dimmed());
+                    return Ok(());
+                }
+
+                let verb = if context.dry_run {
+                    "Would merge"
+                } else {
+                    "✓ Merged"
+                };
+                println!(
+                    "{}",
+                    format!(
+                        "{} {} duplicate entries across {} groups ({} reclaimed)",
+                        verb,
+                        summary.removed,
+                        summary.groups,
+                        format_bytes(summary.bytes_reclaimed as i64)
+                    )
+                    .green()
+                );
+            }
+
+            CacheAction::Tune { sample } => {
+                let storage = open_storage()?;
+                let config = ConfigManager::load()?;
+                let metric = config.cache.similarity_metric;
+                let storage =
+                    storage
+                        .with_similarity_metric(metric)
+                        .with_embedder(embedder_from_config(
+                            &config,
+                            config.network.allow_invalid_certs || context.insecure,
+                        )?);
+                // Thresholds are shown and recommended as cosine similarities, like the config
+                let cosines: Vec<f32> = (10..=19).map(|step| step as f32 * 0.05).collect();
+                let thresholds: Vec<f32> = cosines
+                    .iter()
+                    .map(|&cosine| metric.threshold_from_cosine(cosine))
+                    .collect();
+                let tuning = storage.tune_threshold(&thresholds, sample)?;
+
+                if tuning.compared < 2 {
+                    println!(
+                        "{}",
+                        "Not enough cached queries to tune the threshold yet.".yellow()
+                    );
+                    return Ok(());
+                }
+
+                println!("{}", "Similarity Threshold Tuning".bold().cyan());
+                println!(
+                    "  Replayed the {} most accessed entries against {} cached entries",
+                    tuning.sampled, tuning.compared
+                );
+                println!(
+                    "  {}",
+                    format!(
+                        "A hit agrees when the matched answer shares at least {:.0}% of its \
+                         word trigrams with the replayed one",
+                        TUNE_RESPONSE_AGREEMENT * 100.0
+                    )
+                    .dimmed()
+                );
+                println!();
+
+                let mut table = TableFormatter::new(&["Threshold", "Hits", "Agreeing", "Hit rate"]);
+                for (trial, cosine) in tuning.trials.iter().zip(&cosines) {
+                    table.add_row(vec![
+                        format!("{:.2}", cosine),
+                        trial.hits.to_string(),
+                        trial.agreeing.to_string(),
+                        format!("{:.0}%", trial.hits as f64 / tuning.sampled as f64 * 100.0),
+                    ]);
+                }
+                table.print();
+                println!();
+
+                println!(
+                    "  Current threshold: {:.2}",
+                    config.cache.similarity_threshold
+                );
+                let recommended = tuning.recommended().and_then(|threshold| {
+                    let index = thresholds.iter().position(|&t| t == threshold)?;
+                    Some(cosines[index])
+                });
+                match recommended {
+                    Some(threshold) => {
+                        println!(
+                            "  Recommended: {}",
+                            format!("{:.2}", threshold).green().bold()
+                        );
+                        println!(
+                            "  {}",
+                            format!(
+                                "Apply with: cyx config set cache.similarity_threshold {:.2}",
+                                threshold
+                            )
+                            .dimmed()
+                        );
+                    }
+                    None => println!(
+                        "  {}",
+                        "No threshold gave mostly agreeing hits; pick one from the table.".yellow()
+                    ),
+                }
+            }
+
             CacheAction::Cleanup { days } => {
+                let storage = open_storage()?;
                 println!(
                     "{}",
                     format!("Cleaning up entries older than {} days...", days).cyan()
@@ -361,6 +1333,7 @@ impl CommandHandler {
                         "{}",
                         format!("✓ Removed {} old cache entries", count).green()
                     );
+                    print_vacuum_summary(&storage.vacuum()?);
                 } else {
                     println!("{}", "No old entries to remove.".dimmed());
                 }
@@ -373,6 +1346,45 @@ impl CommandHandler {
                     format_bytes(stats.total_size_bytes)
                 );
             }
+
+            CacheAction::Vacuum => {
+                let storage = open_storage()?;
+                println!("{}", "Compacting cache database...".cyan());
+                print_vacuum_summary(&storage.vacuum()?);
+            }
+
+            CacheAction::Bench { queries, sizes } => {
+                println!(
+                    "{}",
+                    "Benchmarking on synthetic queries (in-memory cache)...".cyan()
+                );
+                let report = bench::run(queries, &sizes)?;
+
+                println!();
+                println!(
+                    "{} {} ({} dimensions)",
+                    "Embedder:".bold(),
+                    report.embedder,
+                    report.dimensions
+                );
+                println!(
+                    "{} {:.0} embeddings/sec ({} in {:.1?})",
+                    "Throughput:".bold(),
+                    report.embeddings_per_sec(),
+                    report.embeddings,
+                    report.embed_elapsed
+                );
+                println!();
+
+                let mut table = TableFormatter::new(&["Cache size", "search_similar"]);
+                for timing in &report.searches {
+                    table.add_row(vec![
+                        timing.cache_size.to_string(),
+                        format!("{:.2?}", timing.per_search),
+                    ]);
+                }
+                table.print();
+            }
         }
 
         Ok(())
@@ -405,22 +1417,109 @@ impl CommandHandler {
         Ok(config)
     }
 
-    fn update(check_only: bool) -> Result<()> {
-        use crate::update::UpdateManager;
+    fn update(check_only: bool, context: &CliContext) -> Result<()> {
+        use crate::update::{InstallMethod, UpdateManager, UpdateSource};
+
+        let config = ConfigManager::load()?;
+        let manager = UpdateManager::new(config.update.source)?;
+        let update_info = manager.check_and_display()?;
+
+        // Self-update is only offered interactively, for cargo installs tracking crates.io
+        if check_only
+            || !update_info.needs_update
+            || context.no_tty
+            || update_info.source != UpdateSource::Crates
+            || InstallMethod::detect() != InstallMethod::Cargo
+        {
+            return Ok(());
+        }
 
-        let manager = UpdateManager::new()?;
-        manager.check_and_display()?;
+        let confirm = dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+            .with_prompt("Run `cargo install cyx --force` now?")
+            .default(true)
+            .interact()?;
 
-        // Note: Both update and update --check-only do the same thing now
-        // (just display update availability and cargo install command)
-        if !check_only {
-            // Kept for backward compatibility, but functionally identical
+        if confirm {
+            manager.self_update()?;
+            println!();
+            println!(
+                "{}",
+                format!("✓ Updated to v{}", update_info.latest_version).green()
+            );
+        } else {
+            println!("{}", "Cancelled.".dimmed());
         }
 
         Ok(())
     }
 }
 
+/// Print one dependency check result line, optionally with install instructions
+fn print_dependency(result: &DepCheckResult, show_instructions: bool) {
+    match result.status {
+        DependencyStatus::Installed { ref version } => {
+            println!(
+                "{} {} {}",
+                "[✓]".green(),
+                result.name,
+                format!("({})", version).dimmed()
+            );
+        }
+        DependencyStatus::NotInstalled => {
+            println!(
+                "{} {} {}",
+                "[✗]".red(),
+                result.name,
+                "(not installed)".dimmed()
+            );
+            if show_instructions {
+                println!("    {}", result.instructions.dimmed());
+            }
+        }
+        DependencyStatus::WrongVersion {
+            ref current,
+            ref required,
+        } => {
+            println!(
+                "{} {} {} {}",
+                "[!]".yellow(),
+                result.name,
+                format!("(current: {}, required: {})", current, required).dimmed(),
+                "(wrong version)".yellow()
+            );
+        }
+    }
+}
+
+/// Shorten text to at most `max` characters, adding an ellipsis if truncated
+fn truncate_chars(text: &str, max: usize) -> String {
+    if text.chars().count() > max {
+        format!("{}...", text.chars().take(max).collect::<String>())
+    } else {
+        text.to_string()
+    }
+}
+
+/// A timestamp in the local time zone, to the minute
+fn format_local(datetime: &DateTime<Utc>) -> String {
+    datetime
+        .with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+fn print_vacuum_summary(summary: &VacuumSummary) {
+    println!(
+        "{}",
+        format!(
+            "✓ Database compacted: {} → {}",
+            format_bytes(summary.size_before as i64),
+            format_bytes(summary.size_after as i64)
+        )
+        .green()
+    );
+}
+
 fn format_bytes(bytes: i64) -> String {
     const KB: i64 = 1024;
     const MB: i64 = KB * 1024;
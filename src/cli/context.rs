@@ -1,3 +1,7 @@
+use super::args::{Cli, OutputFormat};
+use std::path::PathBuf;
+use std::time::Duration;
+
 /// Runtime context for CLI flags and options
 #[derive(Debug, Clone, Default)]
 pub struct CliContext {
@@ -5,6 +9,40 @@ pub struct CliContext {
     pub verbose: bool,
     pub no_tty: bool,
     pub learn: bool,
+    pub dry_run: bool,
+    /// Number of related cache entries to seed the conversation with (0 = off)
+    pub context_entries: usize,
+    /// Extra text appended to the built-in system prompt
+    pub system: Option<String>,
+    /// Accept invalid TLS certificates from self-hosted endpoints
+    pub insecure: bool,
+    /// Similarity threshold overriding `cache.similarity_threshold`
+    pub threshold: Option<f32>,
+    pub output: OutputFormat,
+    /// Answer language from `--lang`, overriding `response.language`
+    pub language: Option<String>,
+    /// Safe mode requested with `--safe` (also enabled by `response.safe_mode`)
+    pub safe: bool,
+    /// Tags added to the cache entry of the query (`--tag`)
+    pub tags: Vec<String>,
+    /// Print cache lookup diagnostics to stderr (`--explain-cache`)
+    pub explain_cache: bool,
+    /// Image attached to a one-shot query (`--image`)
+    pub image: Option<PathBuf>,
+    /// Stop streaming after this many characters (`--max-chars`)
+    pub max_chars: Option<usize>,
+    /// Learn mode section to show instead of the whole answer (`--section`)
+    pub section: Option<String>,
+    /// Hide the sources section when displaying answers (`--no-sources`)
+    pub no_sources: bool,
+    /// Time limit for each provider request (`--timeout`)
+    pub timeout: Option<Duration>,
+    /// Print the provider's JSON-mode response as-is (`--json-response`)
+    pub json_response: bool,
+    /// Re-run the most recent query without the cache (`--retry-last`)
+    pub retry_last: bool,
+    /// Answer with the canned demo response (`--demo`)
+    pub demo: bool,
 }
 
 impl CliContext {
@@ -14,6 +52,7 @@ impl CliContext {
             verbose,
             no_tty,
             learn,
+            ..Default::default()
         }
     }
 
@@ -36,4 +75,42 @@ impl CliContext {
     pub fn should_show_verbose(&self) -> bool {
         self.verbose
     }
+
+    /// Check if responses are written as NDJSON events
+    pub fn is_ndjson(&self) -> bool {
+        self.output == OutputFormat::Ndjson
+    }
+}
+
+impl From<&Cli> for CliContext {
+    fn from(cli: &Cli) -> Self {
+        let machine_readable = cli.output != OutputFormat::Text || cli.json_response;
+        Self {
+            dry_run: cli.dry_run,
+            context_entries: cli.context_entries,
+            system: cli.system.clone(),
+            insecure: cli.insecure,
+            threshold: cli.threshold,
+            output: cli.output,
+            language: cli.lang.clone(),
+            safe: cli.safe,
+            tags: cli.tags.clone(),
+            explain_cache: cli.explain_cache,
+            image: cli.image.clone(),
+            max_chars: cli.max_chars,
+            section: cli.section.clone(),
+            no_sources: cli.no_sources,
+            timeout: cli.timeout,
+            json_response: cli.json_response,
+            retry_last: cli.retry_last,
+            demo: cli.demo,
+            // Anything besides the JSON would corrupt JSON output
+            ..Self::new(
+                cli.quiet || machine_readable,
+                cli.verbose && !machine_readable,
+                cli.no_tty || machine_readable,
+                cli.learn,
+            )
+        }
+    }
 }
@@ -2,6 +2,6 @@ pub mod args;
 pub mod commands;
 pub mod context;
 
-pub use args::{Cli, Commands};
+pub use args::{Cli, Commands, OutputFormat};
 pub use commands::CommandHandler;
 pub use context::CliContext;
@@ -1,50 +1,158 @@
-use super::Config;
+use super::{Config, CURRENT_CONFIG_VERSION};
 use crate::cache::CacheStorage;
+use crate::error::CyxError;
+use crate::ui::display::MIN_BOX_WIDTH;
+use crate::ui::Display;
+use crate::util::write_atomic;
 use anyhow::{Context, Result};
 use colored::Colorize;
 use dialoguer::{theme::ColorfulTheme, Input, Select};
 use std::fs;
-use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 pub struct ConfigManager;
 
+/// Outcome of a connection test against one provider
+#[derive(Debug)]
+pub enum ProviderTest {
+    /// Not configured (no API key), so not attempted
+    Skipped(String),
+    Passed(Duration),
+    Failed {
+        latency: Duration,
+        error: String,
+    },
+}
+
 impl ConfigManager {
     /// Load config from file, or create default if it doesn't exist
     pub fn load() -> Result<Config> {
-        let config_path = Config::config_path()?;
+        Self::load_from(&Config::config_path()?)
+    }
+
+    /// Read the config without rewriting the file or printing anything, for settings
+    /// looked up before the command runs (box style, update check). A corrupt file
+    /// is an error here; the command that needs the config recovers it via `load`.
+    pub fn peek() -> Result<Config> {
+        Self::peek_from(&Config::config_path()?)
+    }
 
+    fn peek_from(config_path: &Path) -> Result<Config> {
         if !config_path.exists() {
             return Ok(Config::default());
         }
 
-        let content = fs::read_to_string(&config_path).context("Failed to read config file")?;
+        let content = fs::read_to_string(config_path).context("Failed to read config file")?;
+        let mut table: toml::Table = toml::from_str(&content).context("Invalid config file")?;
+        Self::migrate(&mut table);
+        toml::Value::Table(table)
+            .try_into()
+            .context("Invalid config file")
+    }
+
+    /// Load config from a specific path. A file that fails to parse is backed up to
+    /// `config.toml.bak` and replaced with defaults plus any salvageable values.
+    fn load_from(config_path: &Path) -> Result<Config> {
+        if !config_path.exists() {
+            return Ok(Config::default());
+        }
+
+        let content = fs::read_to_string(config_path).context("Failed to read config file")?;
+
+        let mut table: toml::Table = match toml::from_str(&content) {
+            Ok(table) => table,
+            Err(e) => return Self::recover_corrupt(config_path, &content, &e),
+        };
+
+        let migrated = Self::migrate(&mut table);
+
+        let config: Config = match toml::Value::Table(table).try_into() {
+            Ok(config) => config,
+            Err(e) => return Self::recover_corrupt(config_path, &content, &e),
+        };
+
+        if migrated {
+            Self::save_to(config_path, &config)?;
+            Display::info_stderr(&format!(
+                "Upgraded config file to version {}",
+                CURRENT_CONFIG_VERSION
+            ));
+        }
+
+        Ok(config)
+    }
+
+    /// Upgrade a raw config table to the current schema version, one step at a time.
+    /// Returns true if anything changed and the file should be rewritten.
+    pub fn migrate(table: &mut toml::Table) -> bool {
+        let mut version = table
+            .get("version")
+            .and_then(toml::Value::as_integer)
+            .unwrap_or(0) as u32;
+
+        if version >= CURRENT_CONFIG_VERSION {
+            return false;
+        }
 
-        let config: Config = toml::from_str(&content).context("Failed to parse config file")?;
+        while version < CURRENT_CONFIG_VERSION {
+            match version {
+                0 => migrate_v0_to_v1(table),
+                _ => unreachable!("no migration from config version {}", version),
+            }
+            version += 1;
+        }
+
+        table.insert(
+            "version".to_string(),
+            toml::Value::Integer(i64::from(version)),
+        );
+        true
+    }
+
+    /// Back up a corrupt config file and rewrite it from the values that still parse
+    fn recover_corrupt(
+        config_path: &Path,
+        content: &str,
+        error: &toml::de::Error,
+    ) -> Result<Config> {
+        let backup_path = config_path.with_extension("toml.bak");
+        fs::copy(config_path, &backup_path).context("Failed to back up corrupt config file")?;
+
+        let (config, salvaged) = salvage_config(content);
+        Self::save_to(config_path, &config)?;
+
+        Display::warning_stderr(&format!(
+            "Config file was corrupt ({}). Backed up to {}",
+            error.message().trim(),
+            backup_path.display()
+        ));
+        Display::info_stderr(&format!(
+            "Recovered {} setting(s), using defaults for the rest. Run `cyx setup` to reconfigure.",
+            salvaged
+        ));
 
         Ok(config)
     }
 
     /// Save config to file with secure permissions (600)
     pub fn save(config: &Config) -> Result<()> {
-        let config_dir = Config::config_dir()?;
-        let config_path = Config::config_path()?;
+        Self::save_to(&Config::config_path()?, config)
+    }
 
+    fn save_to(config_path: &Path, config: &Config) -> Result<()> {
         // Create config directory if it doesn't exist
-        if !config_dir.exists() {
-            fs::create_dir_all(&config_dir).context("Failed to create config directory")?;
+        if let Some(config_dir) = config_path.parent() {
+            if !config_dir.exists() {
+                fs::create_dir_all(config_dir).context("Failed to create config directory")?;
+            }
         }
 
         // Serialize config to TOML
         let content = toml::to_string_pretty(config).context("Failed to serialize config")?;
 
-        // Write to file
-        fs::write(&config_path, content).context("Failed to write config file")?;
-
-        // Set permissions to 600 (read/write for owner only)
-        let mut perms = fs::metadata(&config_path)?.permissions();
-        perms.set_mode(0o600);
-        fs::set_permissions(&config_path, perms)
-            .context("Failed to set config file permissions")?;
+        // Write atomically with permissions 600 (read/write for owner only)
+        write_atomic(config_path, content, Some(0o600)).context("Failed to write config file")?;
 
         Ok(())
     }
@@ -98,14 +206,31 @@ impl ConfigManager {
             println!("{}", "─".repeat(60).dimmed());
             println!("Note: You must have Ollama installed and models downloaded.");
             println!("Install from: {}", "https://ollama.com".cyan());
-            println!("Download models with: {}\n", "ollama pull mistral".cyan());
-
-            let model: String = Input::with_theme(&ColorfulTheme::default())
-                .with_prompt("Enter Ollama model name (e.g., mistral:7b-instruct)")
-                .default("mistral:7b-instruct".to_string())
-                .interact_text()?;
-
-            config.ollama.model = model;
+            println!(
+                "Download models with: {}\n",
+                "cyx ollama pull <model>".cyan()
+            );
+
+            let profiles = crate::llm::model_profiles()?;
+            let mut choices: Vec<String> = profiles.iter().map(|p| p.to_string()).collect();
+            choices.push("Other (enter a model name)".to_string());
+
+            let default = profiles
+                .iter()
+                .position(|p| p.name == config.ollama.model)
+                .unwrap_or(0);
+            let choice = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Select an Ollama model")
+                .items(&choices)
+                .default(default)
+                .interact()?;
+
+            config.ollama.model = match profiles.get(choice) {
+                Some(profile) => profile.name.clone(),
+                None => Input::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Enter Ollama model name")
+                    .interact_text()?,
+            };
         } else if selected_provider.starts_with("Groq") {
             config.provider = super::LLMProvider::Groq;
 
@@ -170,12 +295,19 @@ impl ConfigManager {
 
         let cache_dir = Config::cache_dir()?;
         match CacheStorage::new(&cache_dir) {
-            Ok(_) => {
+            Ok(storage) => {
                 println!("{}", "[✓]".green());
                 println!(
                     "  Cache directory: {}",
                     cache_dir.display().to_string().dimmed()
                 );
+                if let Some(backup) = storage.corrupt_backup() {
+                    println!(
+                        "  {} Database was corrupt and has been reset (old file kept at {})",
+                        "[!]".yellow(),
+                        backup.display()
+                    );
+                }
             }
             Err(e) => {
                 println!("{}", "[✗]".red());
@@ -213,47 +345,70 @@ impl ConfigManager {
 
     /// Test provider connection
     fn test_provider(config: &Config) -> Result<()> {
-        use crate::llm::{
-            groq::GroqProvider, perplexity::PerplexityProvider, LLMProvider, OllamaProvider,
-        };
-
-        // Create provider based on config
-        let provider: Box<dyn LLMProvider> = match config.provider {
-            super::LLMProvider::Groq => {
-                let api_key = config
-                    .api_keys
-                    .groq
-                    .clone()
-                    .ok_or_else(|| anyhow::anyhow!("Groq API key not configured"))?;
-                Box::new(GroqProvider::new(api_key)?)
-            }
-            super::LLMProvider::Perplexity => {
-                let api_key = config
-                    .api_keys
-                    .perplexity
-                    .clone()
-                    .ok_or_else(|| anyhow::anyhow!("Perplexity API key not configured"))?;
-                Box::new(PerplexityProvider::new(api_key)?)
-            }
-            super::LLMProvider::Ollama => Box::new(OllamaProvider::new(config.ollama.clone())?),
-        };
-
-        // Try a minimal test query
-        let test_messages = vec![crate::llm::Message {
-            role: "user".to_string(),
-            content: "test".to_string(),
-        }];
+        let provider = crate::llm::create_provider(
+            config,
+            &config.provider,
+            config.network.allow_invalid_certs,
+            None,
+        )?;
+
+        // Validate the key and connection without spending tokens where possible
+        provider.test_connection_cheap()
+    }
 
-        // Just test the connection, ignore the response
-        let _ = provider.send_message(&test_messages)?;
+    /// Check every provider with `test_connection_cheap`, timing each one. Providers without
+    /// an API key are skipped; Ollama is always tried since it needs no credentials.
+    pub fn test_all_providers(config: &Config) -> Vec<(super::LLMProvider, ProviderTest)> {
+        [
+            super::LLMProvider::Groq,
+            super::LLMProvider::Perplexity,
+            super::LLMProvider::Ollama,
+        ]
+        .into_iter()
+        .map(|kind| {
+            let missing_key = match kind {
+                super::LLMProvider::Groq => config.api_keys.groq.is_none(),
+                super::LLMProvider::Perplexity => config.api_keys.perplexity.is_none(),
+                super::LLMProvider::Ollama => false,
+            };
+            if missing_key {
+                return (kind, ProviderTest::Skipped("No API key".to_string()));
+            }
 
-        Ok(())
+            let started = Instant::now();
+            let result = crate::llm::create_provider(
+                config,
+                &kind,
+                config.network.allow_invalid_certs,
+                None,
+            )
+            .and_then(|provider| provider.test_connection_cheap());
+            let latency = started.elapsed();
+
+            let outcome = match result {
+                Ok(_) => ProviderTest::Passed(latency),
+                Err(e) => ProviderTest::Failed {
+                    latency,
+                    error: e.to_string(),
+                },
+            };
+            (kind, outcome)
+        })
+        .collect()
     }
 
     /// Set a specific configuration value
     pub fn set_value(key: &str, value: &str) -> Result<()> {
         let mut config = Self::load()?;
+        Self::apply_value(&mut config, key, value).map_err(|e| CyxError::Config(e.to_string()))?;
+
+        Self::save(&config)?;
+        println!("{}", format!("✓ Updated {}", key).green());
+        Ok(())
+    }
 
+    /// Parse `value` for `key` into `config`
+    fn apply_value(config: &mut Config, key: &str, value: &str) -> Result<()> {
         match key {
             "provider" => {
                 config.provider = match value.to_lowercase().as_str() {
@@ -263,6 +418,9 @@ impl ConfigManager {
                     _ => anyhow::bail!("Invalid provider. Options: groq, perplexity, ollama"),
                 };
             }
+            "default_action" => {
+                config.default_action = value.parse()?;
+            }
             "groq_api_key" => {
                 config.api_keys.groq = Some(value.to_string());
             }
@@ -283,14 +441,133 @@ impl ConfigManager {
                     .parse()
                     .map_err(|_| anyhow::anyhow!("Invalid number for ttl_days"))?;
             }
+            "cache.similarity_threshold" => {
+                let threshold: f32 = value
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid number for similarity_threshold"))?;
+                if !(0.0..=1.0).contains(&threshold) {
+                    anyhow::bail!(
+                        "similarity_threshold is a cosine similarity between 0.0 and 1.0 \
+                         (it is converted for the dot and euclidean metrics)"
+                    );
+                }
+                config.cache.similarity_threshold = threshold;
+            }
+            "cache.similarity_metric" => {
+                config.cache.similarity_metric = value.parse()?;
+            }
+            "cache.embedder_backend" => {
+                config.cache.embedder_backend = value.parse()?;
+            }
+            "cache.ollama_embedding_model" => {
+                if value.trim().is_empty() {
+                    anyhow::bail!("ollama_embedding_model cannot be empty");
+                }
+                config.cache.ollama_embedding_model = value.trim().to_string();
+            }
+            "network.allow_invalid_certs" => {
+                config.network.allow_invalid_certs = value.to_lowercase() == "true";
+            }
+            "network.empty_response_retries" => {
+                config.network.empty_response_retries = value
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid number for empty_response_retries"))?;
+            }
+            "response.language" => {
+                config.response.language = if value.is_empty() {
+                    None
+                } else {
+                    Some(value.to_string())
+                };
+            }
+            "response.safe_mode" => {
+                config.response.safe_mode = value.to_lowercase() == "true";
+            }
+            "response.trusted_sources" => {
+                config.response.trusted_sources = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|source| !source.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+            "response.strip_thinking" => {
+                config.response.strip_thinking = value.to_lowercase() == "true";
+            }
+            "response.enforce_citation_format" => {
+                config.response.enforce_citation_format = value.to_lowercase() == "true";
+            }
+            "network.rps" => {
+                let rps: f64 = value
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid number for rps"))?;
+                if !rps.is_finite() || rps < 0.0 || (rps > 0.0 && rps < crate::llm::MIN_RPS) {
+                    anyhow::bail!(
+                        "rps must be 0 (unlimited) or at least {} (one request a day)",
+                        crate::llm::MIN_RPS
+                    );
+                }
+                config.network.rps = rps;
+            }
+            "network.slow_response_secs" => {
+                config.network.slow_response_secs = value
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid number for slow_response_secs"))?;
+            }
+            "normalize.lowercase" => {
+                config.normalize.lowercase = value.to_lowercase() == "true";
+            }
+            "normalize.remove_punctuation" => {
+                config.normalize.remove_punctuation = value.to_lowercase() == "true";
+            }
+            "normalize.expand_abbreviations" => {
+                config.normalize.expand_abbreviations = value.to_lowercase() == "true";
+            }
+            "normalize.trim_whitespace" => {
+                config.normalize.trim_whitespace = value.to_lowercase() == "true";
+            }
+            "normalize.remove_stopwords" => {
+                config.normalize.remove_stopwords = value.to_lowercase() == "true";
+            }
+            "ui.box_width" => {
+                config.ui.box_width = match value.to_lowercase().as_str() {
+                    "" | "auto" => None,
+                    width => Some(
+                        width
+                            .parse()
+                            .ok()
+                            .filter(|&width| width >= MIN_BOX_WIDTH)
+                            .ok_or_else(|| {
+                                anyhow::anyhow!(
+                                    "box_width must be a number of columns (at least {}) or auto",
+                                    MIN_BOX_WIDTH
+                                )
+                            })?,
+                    ),
+                };
+            }
+            "ui.ascii" => {
+                config.ui.ascii = value.to_lowercase() == "true";
+            }
+            "update.source" => {
+                config.update.source = value.parse()?;
+            }
+            "update.auto_check" => {
+                config.update.auto_check = value.to_lowercase() == "true";
+            }
+            "data_dir" => {
+                config.data_dir = if value.is_empty() {
+                    None
+                } else {
+                    Some(value.into())
+                };
+            }
             _ => anyhow::bail!(
                 "Unknown config key: {}. Try: provider, cache.enabled, cache.ttl_days",
                 key
             ),
         }
 
-        Self::save(&config)?;
-        println!("{}", format!("✓ Updated {}", key).green());
         Ok(())
     }
 
@@ -300,6 +577,7 @@ impl ConfigManager {
 
         let value = match key {
             "provider" => format!("{:?}", config.provider),
+            "default_action" => format!("{:?}", config.default_action),
             "groq_api_key" => config
                 .api_keys
                 .groq
@@ -312,10 +590,319 @@ impl ConfigManager {
             "ollama_base_url" => config.ollama.base_url,
             "cache.enabled" => config.cache.enabled.to_string(),
             "cache.ttl_days" => config.cache.ttl_days.to_string(),
+            "cache.similarity_threshold" => config.cache.similarity_threshold.to_string(),
+            "cache.similarity_metric" => format!("{:?}", config.cache.similarity_metric),
+            "cache.embedder_backend" => format!("{:?}", config.cache.embedder_backend),
+            "cache.ollama_embedding_model" => config.cache.ollama_embedding_model.clone(),
+            "network.allow_invalid_certs" => config.network.allow_invalid_certs.to_string(),
+            "network.empty_response_retries" => config.network.empty_response_retries.to_string(),
+            "network.rps" => config.network.rps.to_string(),
+            "network.slow_response_secs" => config.network.slow_response_secs.to_string(),
+            "response.language" => config
+                .response
+                .language
+                .unwrap_or_else(|| "Not set (English)".to_string()),
+            "response.safe_mode" => config.response.safe_mode.to_string(),
+            "response.trusted_sources" if config.response.trusted_sources.is_empty() => {
+                "Not set".to_string()
+            }
+            "response.trusted_sources" => config.response.trusted_sources.join(", "),
+            "response.strip_thinking" => config.response.strip_thinking.to_string(),
+            "response.enforce_citation_format" => {
+                config.response.enforce_citation_format.to_string()
+            }
+            "normalize.lowercase" => config.normalize.lowercase.to_string(),
+            "normalize.remove_punctuation" => config.normalize.remove_punctuation.to_string(),
+            "normalize.expand_abbreviations" => config.normalize.expand_abbreviations.to_string(),
+            "normalize.trim_whitespace" => config.normalize.trim_whitespace.to_string(),
+            "normalize.remove_stopwords" => config.normalize.remove_stopwords.to_string(),
+            "ui.box_width" => config
+                .ui
+                .box_width
+                .map_or_else(|| "auto".to_string(), |width| width.to_string()),
+            "ui.ascii" => config.ui.ascii.to_string(),
+            "update.source" => format!("{:?}", config.update.source),
+            "update.auto_check" => config.update.auto_check.to_string(),
+            "data_dir" => config
+                .data_dir()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_else(|| "Not set (using built-in data)".to_string()),
             "config_path" => Config::config_path()?.display().to_string(),
-            _ => anyhow::bail!("Unknown config key: {}", key),
+            _ => return Err(CyxError::Config(format!("Unknown config key: {}", key)).into()),
         };
 
         Ok(value)
     }
 }
+
+/// v0 (unversioned) files may lack whole sections: fill any missing top-level
+/// key from the defaults, keeping everything the user set
+fn migrate_v0_to_v1(table: &mut toml::Table) {
+    if let Ok(toml::Value::Table(defaults)) = toml::Value::try_from(Config::default()) {
+        for (key, value) in defaults {
+            if key != "version" {
+                table.entry(key).or_insert(value);
+            }
+        }
+    }
+}
+
+/// Rebuild a config from a file that no longer parses as a whole: every line that is
+/// valid TOML on its own is applied over the defaults, as long as the result still
+/// deserializes. Returns the config and the number of values kept.
+fn salvage_config(content: &str) -> (Config, usize) {
+    let mut entries = Vec::new();
+    let mut section: Vec<String> = Vec::new();
+
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            section = line
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .split('.')
+                .map(|part| part.trim().trim_matches('"').to_string())
+                .collect();
+            continue;
+        }
+
+        if let Ok(table) = line.parse::<toml::Table>() {
+            for (key, value) in table {
+                let mut path = section.clone();
+                path.push(key);
+                flatten_value(path, value, &mut entries);
+            }
+        }
+    }
+
+    let mut merged = match toml::Value::try_from(Config::default()) {
+        Ok(value) => value,
+        Err(_) => return (Config::default(), 0),
+    };
+
+    let mut salvaged = 0;
+    for (path, value) in entries {
+        let mut candidate = merged.clone();
+        if set_value_at(&mut candidate, &path, value)
+            && candidate.clone().try_into::<Config>().is_ok()
+        {
+            merged = candidate;
+            salvaged += 1;
+        }
+    }
+
+    (merged.try_into().unwrap_or_default(), salvaged)
+}
+
+/// Split nested tables (e.g. from dotted keys) into leaf values with full key paths
+fn flatten_value(path: Vec<String>, value: toml::Value, out: &mut Vec<(Vec<String>, toml::Value)>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let mut child = path.clone();
+                child.push(key);
+                flatten_value(child, value, out);
+            }
+        }
+        value => out.push((path, value)),
+    }
+}
+
+/// Set a value at a key path, creating intermediate tables. Fails on non-table parents.
+fn set_value_at(root: &mut toml::Value, path: &[String], value: toml::Value) -> bool {
+    let Some((last, parents)) = path.split_last() else {
+        return false;
+    };
+
+    let mut current = root;
+    for key in parents {
+        let Some(table) = current.as_table_mut() else {
+            return false;
+        };
+        current = table
+            .entry(key.clone())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    }
+
+    match current.as_table_mut() {
+        Some(table) => {
+            table.insert(last.clone(), value);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{DefaultAction, LLMProvider};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_all_providers_skips_missing_keys() {
+        let mut config = Config::default();
+        config.ollama.base_url = "http://127.0.0.1:9".to_string();
+
+        let results = ConfigManager::test_all_providers(&config);
+        assert_eq!(results.len(), 3);
+        assert!(matches!(
+            results[0],
+            (LLMProvider::Groq, ProviderTest::Skipped(_))
+        ));
+        assert!(matches!(
+            results[1],
+            (LLMProvider::Perplexity, ProviderTest::Skipped(_))
+        ));
+        assert!(matches!(
+            results[2],
+            (LLMProvider::Ollama, ProviderTest::Failed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_load_valid_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        fs::write(&path, "provider = \"ollama\"\n\n[api_keys]\n").unwrap();
+
+        let config = ConfigManager::load_from(&path).unwrap();
+        assert!(matches!(config.provider, LLMProvider::Ollama));
+        assert_eq!(config.default_action, DefaultAction::Error);
+        assert!(!path.with_extension("toml.bak").exists());
+    }
+
+    #[test]
+    fn test_set_default_action() {
+        let mut config = Config::default();
+        ConfigManager::apply_value(&mut config, "default_action", "Chat").unwrap();
+        assert_eq!(config.default_action, DefaultAction::Chat);
+        assert!(ConfigManager::apply_value(&mut config, "default_action", "repl").is_err());
+
+        let saved = toml::to_string(&config).unwrap();
+        assert!(saved.contains("default_action = \"chat\""));
+    }
+
+    #[test]
+    fn test_similarity_threshold_stays_cosine() {
+        let mut config = Config::default();
+        ConfigManager::apply_value(&mut config, "cache.similarity_metric", "euclidean").unwrap();
+        ConfigManager::apply_value(&mut config, "cache.similarity_threshold", "0.82").unwrap();
+        assert!(
+            ConfigManager::apply_value(&mut config, "cache.similarity_threshold", "1.2").is_err()
+        );
+
+        // Cosine 0.82 between unit vectors is a distance of 0.6
+        assert!((config.cache.metric_threshold(None) - 0.6).abs() < 1e-4);
+        assert_eq!(config.cache.metric_threshold(Some(1.0)), 0.0);
+    }
+
+    #[test]
+    fn test_rps_rejects_rates_that_overflow() {
+        let mut config = Config::default();
+        ConfigManager::apply_value(&mut config, "network.rps", "0").unwrap();
+        ConfigManager::apply_value(&mut config, "network.rps", "0.5").unwrap();
+        assert!(ConfigManager::apply_value(&mut config, "network.rps", "1e-20").is_err());
+        assert!(ConfigManager::apply_value(&mut config, "network.rps", "-1").is_err());
+        assert_eq!(config.network.rps, 0.5);
+    }
+
+    #[test]
+    fn test_load_corrupt_config_recovers() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        let corrupt = "provider = \"perplexity\"\n\n[api_keys]\ngroq = \"gsk_test\"\nperplexity = \"unterminated\n\n[cache]\nttl_days = 7\nenabled = maybe\n";
+        fs::write(&path, corrupt).unwrap();
+
+        let config = ConfigManager::load_from(&path).unwrap();
+
+        // Salvageable values survive, broken ones fall back to defaults
+        assert!(matches!(config.provider, LLMProvider::Perplexity));
+        assert_eq!(config.api_keys.groq.as_deref(), Some("gsk_test"));
+        assert_eq!(config.api_keys.perplexity, None);
+        assert_eq!(config.cache.ttl_days, 7);
+        assert!(config.cache.enabled);
+
+        // Original is backed up and the rewritten file parses cleanly
+        let backup = fs::read_to_string(path.with_extension("toml.bak")).unwrap();
+        assert_eq!(backup, corrupt);
+        let rewritten = fs::read_to_string(&path).unwrap();
+        assert!(toml::from_str::<Config>(&rewritten).is_ok());
+    }
+
+    #[test]
+    fn test_peek_leaves_corrupt_config_alone() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        fs::write(&path, "provider = \"groq\"\n[cache\n").unwrap();
+
+        assert!(ConfigManager::peek_from(&path).is_err());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "provider = \"groq\"\n[cache\n"
+        );
+        assert!(!path.with_extension("toml.bak").exists());
+    }
+
+    #[test]
+    fn test_peek_does_not_rewrite_old_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        fs::write(&path, "provider = \"ollama\"\n").unwrap();
+
+        let config = ConfigManager::peek_from(&path).unwrap();
+        assert_eq!(config.version, CURRENT_CONFIG_VERSION);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "provider = \"ollama\"\n"
+        );
+    }
+
+    #[test]
+    fn test_load_v0_config_migrates() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        // Unversioned file from before the api_keys/ollama/cache sections existed
+        fs::write(&path, "provider = \"perplexity\"\n").unwrap();
+
+        let config = ConfigManager::load_from(&path).unwrap();
+        assert_eq!(config.version, CURRENT_CONFIG_VERSION);
+        assert!(matches!(config.provider, LLMProvider::Perplexity));
+        assert_eq!(config.api_keys.perplexity, None);
+        assert_eq!(config.ollama.model, "mistral:7b-instruct");
+        assert_eq!(config.cache.ttl_days, 30);
+
+        // The file is rewritten at the current version and loads without migrating again
+        let mut rewritten: toml::Table =
+            toml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            rewritten.get("version").and_then(toml::Value::as_integer),
+            Some(i64::from(CURRENT_CONFIG_VERSION))
+        );
+        assert!(!ConfigManager::migrate(&mut rewritten));
+        assert!(!path.with_extension("toml.bak").exists());
+    }
+
+    #[test]
+    fn test_migrate_keeps_existing_values() {
+        let mut table: toml::Table =
+            toml::from_str("provider = \"ollama\"\n[cache]\nttl_days = 7\n").unwrap();
+        assert!(ConfigManager::migrate(&mut table));
+
+        let config: Config = toml::Value::Table(table).try_into().unwrap();
+        assert!(matches!(config.provider, LLMProvider::Ollama));
+        assert_eq!(config.cache.ttl_days, 7);
+    }
+
+    #[test]
+    fn test_salvage_skips_values_with_wrong_type() {
+        let (config, salvaged) =
+            salvage_config("[cache]\nttl_days = \"soon\"\nsimilarity_threshold = 0.8\n{{{");
+        assert_eq!(salvaged, 1);
+        assert_eq!(config.cache.ttl_days, 30);
+        assert_eq!(config.cache.similarity_threshold, 0.8);
+    }
+}
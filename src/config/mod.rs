@@ -1,21 +1,49 @@
 pub mod manager;
 
-pub use manager::ConfigManager;
+pub use manager::{ConfigManager, ProviderTest};
 
+use crate::cache::{EmbedderBackend, NormalizationConfig, SimilarityMetric};
+use crate::update::UpdateSource;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+/// Environment variable pointing at a directory of data files (takes precedence over `data_dir`)
+pub const DATA_DIR_ENV: &str = "CYX_DATA_DIR";
+
+/// Schema version written to new config files; older files are migrated on load
+pub const CURRENT_CONFIG_VERSION: u32 = 1;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Schema version (missing in files written before versioning, i.e. 0)
+    #[serde(default)]
+    pub version: u32,
     pub provider: LLMProvider,
+    /// What a bare `cyx`, with neither a query nor a subcommand, does
+    #[serde(default)]
+    pub default_action: DefaultAction,
     pub api_keys: ApiKeys,
     #[serde(default)]
     pub ollama: OllamaConfig,
     #[serde(default)]
     pub cache: CacheConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub update: UpdateConfig,
+    #[serde(default)]
+    pub response: ResponseConfig,
+    /// Query normalization steps applied before cache lookups
+    #[serde(default)]
+    pub normalize: NormalizationConfig,
+    #[serde(default)]
+    pub ui: UiConfig,
+    /// Directory checked first for data files such as `normalization/*.json`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_dir: Option<PathBuf>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum LLMProvider {
     Perplexity,
@@ -23,6 +51,35 @@ pub enum LLMProvider {
     Ollama,
 }
 
+/// Behavior of `cyx` run without a query or subcommand (`default_action`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DefaultAction {
+    /// Fail with the "no query" usage error (exit code 6)
+    #[default]
+    Error,
+    /// Print the command-line help
+    Help,
+    /// Prompt for queries until `exit`
+    Chat,
+    /// Run the setup wizard
+    Setup,
+}
+
+impl std::str::FromStr for DefaultAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(Self::Error),
+            "help" => Ok(Self::Help),
+            "chat" => Ok(Self::Chat),
+            "setup" => Ok(Self::Setup),
+            _ => anyhow::bail!("Invalid default action. Options: error, help, chat, setup"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiKeys {
     pub perplexity: Option<String>,
@@ -76,14 +133,28 @@ pub struct CacheConfig {
     pub ttl_days: u32,
     #[serde(default = "default_embedding_model")]
     pub embedding_model: String,
+    /// Minimum cosine similarity for a similar match, whatever the metric
     #[serde(default = "default_similarity_threshold")]
     pub similarity_threshold: f32,
+    /// Metric for similarity search
+    #[serde(default)]
+    pub similarity_metric: SimilarityMetric,
+    /// Where query embeddings come from: built-in `simple`, or a local `ollama`
+    #[serde(default)]
+    pub embedder_backend: EmbedderBackend,
+    /// Embedding model used by the `ollama` backend
+    #[serde(default = "default_ollama_embedding_model")]
+    pub ollama_embedding_model: String,
 }
 
 fn default_embedding_model() -> String {
     "small".to_string()
 }
 
+fn default_ollama_embedding_model() -> String {
+    "nomic-embed-text".to_string()
+}
+
 fn default_similarity_threshold() -> f32 {
     0.90
 }
@@ -103,20 +174,148 @@ impl Default for CacheConfig {
             ttl_days: default_ttl_days(),
             embedding_model: default_embedding_model(),
             similarity_threshold: default_similarity_threshold(),
+            similarity_metric: SimilarityMetric::default(),
+            embedder_backend: EmbedderBackend::default(),
+            ollama_embedding_model: default_ollama_embedding_model(),
+        }
+    }
+}
+
+impl CacheConfig {
+    /// The similarity threshold (or a `--threshold` override), both cosine
+    /// similarities, converted into the terms of `similarity_metric`
+    pub fn metric_threshold(&self, cosine_override: Option<f32>) -> f32 {
+        self.similarity_metric
+            .threshold_from_cosine(cosine_override.unwrap_or(self.similarity_threshold))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Skip TLS certificate validation for self-hosted endpoints (Ollama) only
+    #[serde(default)]
+    pub allow_invalid_certs: bool,
+    /// Extra attempts when the provider returns an empty or whitespace-only response
+    #[serde(default = "default_empty_response_retries")]
+    pub empty_response_retries: u32,
+    /// Maximum provider requests per second, sustained (0 = unlimited)
+    #[serde(default)]
+    pub rps: f64,
+    /// Seconds without a response before the spinner says the model may be loading (0 = never)
+    #[serde(default = "default_slow_response_secs")]
+    pub slow_response_secs: u64,
+}
+
+fn default_slow_response_secs() -> u64 {
+    15
+}
+
+fn default_empty_response_retries() -> u32 {
+    1
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            allow_invalid_certs: false,
+            empty_response_retries: default_empty_response_retries(),
+            rps: 0.0,
+            slow_response_secs: default_slow_response_secs(),
         }
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateConfig {
+    /// Check for updates (at most once a day) on startup
+    #[serde(default = "default_auto_check")]
+    pub auto_check: bool,
+    /// Where update checks look for new versions (crates or github)
+    #[serde(default)]
+    pub source: UpdateSource,
+}
+
+fn default_auto_check() -> bool {
+    true
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            auto_check: default_auto_check(),
+            source: UpdateSource::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseConfig {
+    /// Language answers are written in (code such as `es`, or a name); English when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Require authorization reminders and refuse clearly out-of-scope requests
+    #[serde(default)]
+    pub safe_mode: bool,
+    /// Sites the model is asked to prefer when citing sources (e.g. book.hacktricks.xyz)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_sources: Vec<String>,
+    /// Drop `<think>`/`<reasoning>` blocks that reasoning models emit before the answer
+    #[serde(default = "default_strip_thinking")]
+    pub strip_thinking: bool,
+    /// Remove inline `[1]` citations and move numbered reference lists into `[SOURCES]`
+    #[serde(default = "default_enforce_citation_format")]
+    pub enforce_citation_format: bool,
+}
+
+fn default_strip_thinking() -> bool {
+    true
+}
+
+fn default_enforce_citation_format() -> bool {
+    true
+}
+
+impl Default for ResponseConfig {
+    fn default() -> Self {
+        Self {
+            language: None,
+            safe_mode: false,
+            trusted_sources: Vec::new(),
+            strip_thinking: default_strip_thinking(),
+            enforce_citation_format: default_enforce_citation_format(),
+        }
+    }
+}
+
+/// How answers are drawn in the terminal
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UiConfig {
+    /// Width of the answer box; unset follows the terminal width (80 when unknown)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub box_width: Option<usize>,
+    /// Draw boxes with `+`, `|` and `-` instead of Unicode box-drawing characters
+    #[serde(default)]
+    pub ascii: bool,
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: CURRENT_CONFIG_VERSION,
             provider: LLMProvider::Groq,
+            default_action: DefaultAction::default(),
             api_keys: ApiKeys {
                 perplexity: None,
                 groq: None,
             },
             ollama: OllamaConfig::default(),
             cache: CacheConfig::default(),
+            network: NetworkConfig::default(),
+            update: UpdateConfig::default(),
+            response: ResponseConfig::default(),
+            normalize: NormalizationConfig::default(),
+            ui: UiConfig::default(),
+            data_dir: None,
         }
     }
 }
@@ -132,6 +331,13 @@ impl Config {
         Ok(Self::config_dir()?.join("config.toml"))
     }
 
+    /// Data directory override: `CYX_DATA_DIR` if set, otherwise the `data_dir` key
+    pub fn data_dir(&self) -> Option<PathBuf> {
+        std::env::var_os(DATA_DIR_ENV)
+            .map(PathBuf::from)
+            .or_else(|| self.data_dir.clone())
+    }
+
     pub fn cache_dir() -> anyhow::Result<PathBuf> {
         let dirs = directories::ProjectDirs::from("", "", "cyx")
             .ok_or_else(|| anyhow::anyhow!("Failed to determine cache directory"))?;
@@ -1,6 +1,31 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::process::Command;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long a tool's version command may run before it is killed
+const VERSION_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Security tools referenced in the system prompt's knowledge areas:
+/// (display name, binary, version argument, package name)
+const SECURITY_TOOLS: &[(&str, &str, Option<&str>, &str)] = &[
+    ("nmap", "nmap", Some("--version"), "nmap"),
+    ("masscan", "masscan", Some("--version"), "masscan"),
+    ("RustScan", "rustscan", Some("--version"), "rustscan"),
+    ("sqlmap", "sqlmap", Some("--version"), "sqlmap"),
+    ("ffuf", "ffuf", Some("-V"), "ffuf"),
+    ("gobuster", "gobuster", Some("version"), "gobuster"),
+    ("Hydra", "hydra", Some("-h"), "hydra"),
+    ("John the Ripper", "john", None, "john"),
+    ("hashcat", "hashcat", Some("--version"), "hashcat"),
+    ("CrackMapExec", "crackmapexec", None, "crackmapexec"),
+    // msfconsole takes several seconds to boot, so only check presence
+    ("Metasploit", "msfconsole", None, "metasploit-framework"),
+    ("searchsploit", "searchsploit", None, "exploitdb"),
+];
 
 pub struct DependencyChecker {
     checks: Vec<Box<dyn DependencyCheckImpl>>,
@@ -14,10 +39,21 @@ pub enum DependencyStatus {
     WrongVersion { current: String, required: String },
 }
 
+/// Whether a dependency is needed by Cyx itself or is a tool its answers reference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DependencyKind {
+    Core,
+    Tool,
+}
+
 pub trait DependencyCheckImpl: Send + Sync {
     fn name(&self) -> &str;
     fn check(&self) -> Result<DependencyStatus>;
     fn install_instructions(&self) -> String;
+
+    fn kind(&self) -> DependencyKind {
+        DependencyKind::Core
+    }
 }
 
 impl Clone for Box<dyn DependencyCheckImpl> {
@@ -28,9 +64,18 @@ impl Clone for Box<dyn DependencyCheckImpl> {
 
 impl DependencyChecker {
     pub fn new() -> Result<Self> {
-        let checks: Vec<Box<dyn DependencyCheckImpl>> =
+        let mut checks: Vec<Box<dyn DependencyCheckImpl>> =
             vec![Box::new(SqliteCheck), Box::new(OllamaCheck)];
 
+        for &(name, binary, version_arg, package) in SECURITY_TOOLS {
+            checks.push(Box::new(ToolCheck {
+                name,
+                binary,
+                version_arg,
+                package,
+            }));
+        }
+
         Ok(Self { checks })
     }
 
@@ -43,6 +88,7 @@ impl DependencyChecker {
                 name: check.name().to_string(),
                 status,
                 instructions: check.install_instructions(),
+                kind: check.kind(),
             });
         }
 
@@ -64,6 +110,7 @@ pub struct DepCheckResult {
     pub name: String,
     pub status: DependencyStatus,
     pub instructions: String,
+    pub kind: DependencyKind,
 }
 
 // SQLite Check
@@ -107,9 +154,7 @@ impl DependencyCheckImpl for OllamaCheck {
         }
 
         // Try to check if service is running
-        if let Ok(client) = reqwest::blocking::Client::builder()
-            .timeout(std::time::Duration::from_secs(2))
-            .build()
+        if let Ok(client) = crate::http::build_client(Some(std::time::Duration::from_secs(2)), None)
         {
             if let Ok(response) = client.get("http://localhost:11434/api/tags").send() {
                 if response.status().is_success() {
@@ -134,3 +179,154 @@ impl DependencyCheckImpl for OllamaCheck {
         }
     }
 }
+
+// Security tool check (PATH lookup)
+struct ToolCheck {
+    name: &'static str,
+    binary: &'static str,
+    /// Argument that prints the version, if the tool has a cheap one
+    version_arg: Option<&'static str>,
+    package: &'static str,
+}
+
+impl DependencyCheckImpl for ToolCheck {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn check(&self) -> Result<DependencyStatus> {
+        let Some(path) = find_in_path(self.binary) else {
+            return Ok(DependencyStatus::NotInstalled);
+        };
+
+        let version = self
+            .version_arg
+            .and_then(|arg| tool_version(&path, arg))
+            .unwrap_or_else(|| "installed".to_string());
+
+        Ok(DependencyStatus::Installed { version })
+    }
+
+    fn install_instructions(&self) -> String {
+        format!(
+            "Install via your package manager (e.g. sudo apt install {})",
+            self.package
+        )
+    }
+
+    fn kind(&self) -> DependencyKind {
+        DependencyKind::Tool
+    }
+}
+
+/// Locate an executable on PATH, like `which`
+fn find_in_path(binary: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(binary))
+        .find(|candidate| is_executable(candidate))
+}
+
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Run the tool's version command and pull the first version number from its output.
+/// Many tools print their banner to stderr or exit non-zero, so both are accepted.
+fn tool_version(path: &Path, version_arg: &str) -> Option<String> {
+    let (stdout, stderr) =
+        output_with_timeout(Command::new(path).arg(version_arg), VERSION_TIMEOUT)?;
+
+    let text = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&stdout),
+        String::from_utf8_lossy(&stderr)
+    );
+    extract_version(&text)
+}
+
+/// Run `command` and collect its stdout and stderr, or kill it and give up once
+/// `timeout` has passed, so a tool that hangs or waits for input can't stall
+/// `cyx doctor`
+fn output_with_timeout(command: &mut Command, timeout: Duration) -> Option<(Vec<u8>, Vec<u8>)> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    // Drained while waiting, so a chatty tool never blocks on a full pipe
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    while child.try_wait().ok()?.is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    Some((stdout.join().ok()?, stderr.join().ok()?))
+}
+
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
+        }
+        output
+    })
+}
+
+fn extract_version(text: &str) -> Option<String> {
+    let version = regex::Regex::new(r"\d+\.\d+(?:\.\d+)?").ok()?;
+    version.find(text).map(|m| m.as_str().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_version_gives_up_on_hanging_tools() {
+        assert_eq!(
+            tool_version(Path::new("echo"), "Nmap version 7.94").as_deref(),
+            Some("7.94")
+        );
+
+        let started = Instant::now();
+        assert!(
+            output_with_timeout(Command::new("sleep").arg("30"), Duration::from_millis(100))
+                .is_none()
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_extract_version() {
+        assert_eq!(
+            extract_version("Nmap version 7.94 ( https://nmap.org )").as_deref(),
+            Some("7.94")
+        );
+        assert_eq!(
+            extract_version("Hydra v9.5 (c) 2023 by van Hauser").as_deref(),
+            Some("9.5")
+        );
+        assert_eq!(extract_version("v2.1.0-dev").as_deref(), Some("2.1.0"));
+        assert_eq!(extract_version("no version here"), None);
+    }
+
+    #[test]
+    fn test_find_in_path() {
+        assert!(find_in_path("sh").is_some());
+        assert!(find_in_path("definitely-not-a-real-binary-cyx").is_none());
+    }
+}
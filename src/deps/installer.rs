@@ -72,9 +72,7 @@ impl OllamaInstaller {
 
     pub fn check_available() -> bool {
         // Try connecting to Ollama API
-        if let Ok(client) = reqwest::blocking::Client::builder()
-            .timeout(std::time::Duration::from_secs(5))
-            .build()
+        if let Ok(client) = crate::http::build_client(Some(std::time::Duration::from_secs(5)), None)
         {
             if let Ok(response) = client.get("http://localhost:11434/api/tags").send() {
                 return response.status().is_success();
@@ -1,5 +1,5 @@
 pub mod checker;
 pub mod installer;
 
-pub use checker::{DepCheckResult, DependencyChecker, DependencyStatus};
+pub use checker::{DepCheckResult, DependencyChecker, DependencyKind, DependencyStatus};
 pub use installer::OllamaInstaller;
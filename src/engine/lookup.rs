@@ -0,0 +1,90 @@
+use crate::cache::{classify, CacheStorage, CachedQuery, QueryNormalizer};
+use crate::llm::LLMProvider;
+use crate::session::{response_sources, strip_sources};
+use anyhow::Result;
+
+/// Where a query lives in the cache: its normalized form and the hash scoped to
+/// the answer language. Shared by `Cyx::query` and the CLI so both look up and
+/// store answers the same way.
+#[derive(Debug, Clone)]
+pub struct CacheKey {
+    pub normalized: String,
+    pub hash: String,
+}
+
+/// Outcome of `CacheKey::lookup`
+#[derive(Debug)]
+pub enum CacheLookup {
+    Exact(CachedQuery),
+    /// Best match passing the threshold, with its score under the storage's metric
+    Similar(CachedQuery, f32),
+    Miss,
+}
+
+impl CacheKey {
+    /// Normalize `query` and hash it for answers in `language` (`None` = English)
+    pub fn new(normalizer: &QueryNormalizer, query: &str, language: Option<&str>) -> Result<Self> {
+        let normalized = normalizer.normalize(query)?;
+        let hash = normalizer.compute_scoped_hash(&normalized, language);
+        Ok(Self { normalized, hash })
+    }
+
+    /// Exact match by hash, then the best similar entry passing `threshold`
+    /// (already in the terms of the storage's metric)
+    pub fn lookup(&self, storage: &CacheStorage, threshold: f32) -> Result<CacheLookup> {
+        if let Some(cached) = storage.get_by_hash(&self.hash)? {
+            log::info!(
+                "Cache hit (exact match) for \"{}\" [{}]",
+                self.normalized,
+                self.hash
+            );
+            return Ok(CacheLookup::Exact(cached));
+        }
+
+        let similar = storage.search_similar(&self.normalized, threshold, 1)?;
+        if let Some((cached, score)) = similar.into_iter().next() {
+            log::info!(
+                "Cache hit (similarity {:.3}, threshold {:.3}) for \"{}\": matched \"{}\"",
+                score,
+                threshold,
+                self.normalized,
+                cached.query_normalized
+            );
+            return Ok(CacheLookup::Similar(cached, score));
+        }
+
+        log::info!("Cache miss for \"{}\" [{}]", self.normalized, self.hash);
+        Ok(CacheLookup::Miss)
+    }
+
+    /// Store a fresh `response` from `provider`: the text without its `[SOURCES]`
+    /// section, the sources separately, and tags from `classify` plus `tags`
+    pub fn store(
+        &self,
+        storage: &CacheStorage,
+        query: &str,
+        response: &str,
+        provider: &dyn LLMProvider,
+        tags: &[String],
+    ) -> Result<()> {
+        storage.store(
+            query,
+            &self.normalized,
+            &self.hash,
+            strip_sources(response),
+            provider.name(),
+            provider.model(),
+        )?;
+        storage.store_sources(
+            &self.hash,
+            &response_sources(response, &provider.citations()),
+        )?;
+
+        let mut all_tags = classify(query);
+        all_tags.extend(tags.iter().cloned());
+        storage.add_tags(&self.hash, &all_tags)?;
+
+        log::debug!("Cached the response as [{}]", self.hash);
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+//! Embeddable query engine: the cache lookup, provider call and source
+//! extraction behind `cyx "<query>"`, without any terminal rendering.
+//!
+//! ```no_run
+//! use cyx::{config::ConfigManager, Cyx};
+//!
+//! let cyx = Cyx::from_config(ConfigManager::load()?)?;
+//! let result = cyx.query("nmap stealth scan")?;
+//! println!("{}", result.response);
+//! for (description, url) in &result.sources {
+//!     println!("{}: {}", description, url);
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod lookup;
+
+pub use lookup::{CacheKey, CacheLookup};
+
+use crate::cache::{embedder_from_config, CacheStorage, CachedQuery, QueryNormalizer};
+use crate::cli::CliContext;
+use crate::config::Config;
+use crate::llm::LLMProvider;
+use crate::session::{
+    extract_sources, language_name, response_sources, strip_sources, InteractiveSession,
+};
+use anyhow::Result;
+use std::cell::OnceCell;
+use std::path::{Path, PathBuf};
+
+/// Answer to a query, from the cache or a fresh provider call
+#[derive(Debug, Clone)]
+pub struct QueryResult {
+    /// Response text, without the trailing `[SOURCES]` section
+    pub response: String,
+    /// `(description, url)` pairs cited by the response
+    pub sources: Vec<(String, String)>,
+    pub provider: String,
+    pub model: String,
+    /// Whether the answer came from the cache
+    pub cached: bool,
+    /// Score of a similar (not exact) cache match under the configured metric;
+    /// `None` for exact hits and fresh answers
+    pub similarity: Option<f32>,
+}
+
+/// Query engine built from a `Config`
+pub struct Cyx {
+    config: Config,
+    /// Asks the provider exactly as `cyx "<query>"` does (prompt, context budget,
+    /// empty-response retries, citation cleanup), without rendering anything
+    session: InteractiveSession,
+    normalizer: QueryNormalizer,
+    /// Overrides `Config::cache_dir()`
+    cache_dir: Option<PathBuf>,
+    /// Opened on the first query that needs it
+    storage: OnceCell<CacheStorage>,
+}
+
+impl Cyx {
+    /// Create an engine for the configured provider, using the default cache directory
+    pub fn from_config(config: Config) -> Result<Self> {
+        let session = InteractiveSession::new(config.clone(), Self::session_context())?;
+        Self::with_session(config, session)
+    }
+
+    /// Create an engine around a custom provider implementation
+    pub fn with_provider(config: Config, provider: Box<dyn LLMProvider>) -> Result<Self> {
+        let session =
+            InteractiveSession::with_provider(config.clone(), Self::session_context(), provider)?;
+        Self::with_session(config, session)
+    }
+
+    fn with_session(config: Config, session: InteractiveSession) -> Result<Self> {
+        Ok(Self {
+            normalizer: QueryNormalizer::from_config(&config)?,
+            config,
+            session,
+            cache_dir: None,
+            storage: OnceCell::new(),
+        })
+    }
+
+    /// A session that never prints: the engine only returns answers
+    fn session_context() -> CliContext {
+        CliContext {
+            quiet: true,
+            no_tty: true,
+            ..Default::default()
+        }
+    }
+
+    /// Use a different cache directory (ignored when the cache is disabled)
+    pub fn with_cache_dir<P: AsRef<Path>>(mut self, cache_dir: P) -> Self {
+        self.cache_dir = Some(cache_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Answer in another language (code such as `es`, or a name), overriding
+    /// `response.language`
+    pub fn with_language(mut self, code: &str) -> Self {
+        self.session.set_language(language_name(code));
+        self
+    }
+
+    /// The cache, opened on first use; `None` when `cache.enabled` is false or in
+    /// safe mode, whose answers must not be mixed with the plain prompt's
+    fn storage(&self) -> Result<Option<&CacheStorage>> {
+        if !self.config.cache.enabled || self.config.response.safe_mode {
+            return Ok(None);
+        }
+
+        if self.storage.get().is_none() {
+            let cache_dir = match &self.cache_dir {
+                Some(dir) => dir.clone(),
+                None => Config::cache_dir()?,
+            };
+            let storage = CacheStorage::new(cache_dir)?
+                .with_similarity_metric(self.config.cache.similarity_metric)
+                .with_embedder(embedder_from_config(
+                    &self.config,
+                    self.config.network.allow_invalid_certs,
+                )?)
+                .with_language(self.session.language().map(str::to_string));
+            let _ = self.storage.set(storage);
+        }
+
+        Ok(self.storage.get())
+    }
+
+    /// Answer a query: exact cache match, then similar match above
+    /// `cache.similarity_threshold`, then a provider call whose response is cached
+    pub fn query(&self, text: &str) -> Result<QueryResult> {
+        let key = CacheKey::new(&self.normalizer, text, self.session.language())?;
+        let storage = self.storage()?;
+
+        if let Some(storage) = storage {
+            match key.lookup(storage, self.config.cache.metric_threshold(None))? {
+                CacheLookup::Exact(cached) => return Ok(Self::cached_result(cached, None)),
+                CacheLookup::Similar(cached, score) => {
+                    return Ok(Self::cached_result(cached, Some(score)))
+                }
+                CacheLookup::Miss => {}
+            }
+        }
+
+        let response = self.session.fetch_response(text)?;
+        let provider = self.session.provider();
+        if let Some(storage) = storage {
+            key.store(storage, text, &response, provider, &[])?;
+        }
+
+        Ok(QueryResult {
+            sources: response_sources(&response, &provider.citations()),
+            response: strip_sources(&response).to_string(),
+            provider: provider.name().to_string(),
+            model: provider.model().to_string(),
+            cached: false,
+            similarity: None,
+        })
+    }
+
+    fn cached_result(cached: CachedQuery, similarity: Option<f32>) -> QueryResult {
+        let sources = if cached.sources.is_empty() {
+            extract_sources(&cached.response)
+        } else {
+            cached.sources
+        };
+
+        QueryResult {
+            response: strip_sources(&cached.response).to_string(),
+            sources,
+            provider: cached.provider,
+            model: cached.model,
+            cached: true,
+            similarity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{ChunkHandler, Message};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    /// Provider that answers every query the same way and counts requests
+    struct FixedProvider {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl LLMProvider for FixedProvider {
+        fn send_message(&self, _messages: &[Message]) -> Result<String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok("nmap -sS target\n\n[SOURCES]\n- Nmap docs: https://nmap.org/book/".to_string())
+        }
+
+        fn send_message_stream(
+            &self,
+            messages: &[Message],
+            _on_chunk: ChunkHandler,
+        ) -> Result<String> {
+            self.send_message(messages)
+        }
+
+        fn name(&self) -> &str {
+            "Fixed"
+        }
+
+        fn model(&self) -> &str {
+            "test"
+        }
+
+        fn searches_web(&self) -> bool {
+            false
+        }
+
+        fn prompt_budget(&self) -> usize {
+            8192
+        }
+    }
+
+    #[test]
+    fn test_query_caches_fresh_answers() {
+        let temp_dir = TempDir::new().unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let provider = Box::new(FixedProvider {
+            calls: calls.clone(),
+        });
+        let cyx = Cyx::with_provider(Config::default(), provider)
+            .unwrap()
+            .with_cache_dir(temp_dir.path());
+
+        let fresh = cyx.query("nmap stealth scan").unwrap();
+        assert!(!fresh.cached);
+        assert_eq!(fresh.response, "nmap -sS target");
+        assert_eq!(fresh.sources.len(), 1);
+        assert_eq!(fresh.provider, "Fixed");
+
+        let cached = cyx.query("nmap stealth scan").unwrap();
+        assert!(cached.cached);
+        assert_eq!(cached.similarity, None);
+        assert_eq!(cached.response, fresh.response);
+        assert_eq!(cached.sources, fresh.sources);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_languages_are_cached_separately() {
+        let temp_dir = TempDir::new().unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let engine = |language: &str| {
+            let provider = Box::new(FixedProvider {
+                calls: calls.clone(),
+            });
+            Cyx::with_provider(Config::default(), provider)
+                .unwrap()
+                .with_cache_dir(temp_dir.path())
+                .with_language(language)
+        };
+
+        assert!(!engine("en").query("nmap stealth scan").unwrap().cached);
+        assert!(!engine("es").query("nmap stealth scan").unwrap().cached);
+        assert!(engine("spanish").query("nmap stealth scan").unwrap().cached);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
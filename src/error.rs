@@ -0,0 +1,108 @@
+//! Failure kinds that scripts may need to tell apart, and the process exit code
+//! each one maps to
+
+use std::time::Duration;
+
+/// Exit codes of the `cyx` binary
+pub mod exit_code {
+    pub const SUCCESS: i32 = 0;
+    /// Any failure without a more specific code
+    pub const FAILURE: i32 = 1;
+    /// Invalid configuration or command-line usage
+    pub const USAGE: i32 = 2;
+    /// Missing or rejected provider API key
+    pub const AUTH: i32 = 3;
+    /// The provider could not be reached, or `--timeout` ran out
+    pub const NETWORK: i32 = 4;
+    /// Reserved for a cache miss when the network may not be used (offline mode)
+    pub const OFFLINE_CACHE_MISS: i32 = 5;
+    /// Neither a query nor a subcommand was given
+    pub const NO_QUERY: i32 = 6;
+}
+
+/// An error with a dedicated exit code. Raised inside `anyhow::Error`s; context
+/// added on the way up does not hide it from `exit_code`.
+#[derive(Debug)]
+pub enum CyxError {
+    /// Invalid configuration value or unknown config key
+    Config(String),
+    /// No API key for the provider, or the provider rejected it
+    Auth(String),
+    /// A provider request ran past the `--timeout` limit
+    Timeout(Duration),
+    /// No query and no subcommand
+    NoQuery,
+}
+
+impl CyxError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CyxError::Config(_) => exit_code::USAGE,
+            CyxError::Auth(_) => exit_code::AUTH,
+            CyxError::Timeout(_) => exit_code::NETWORK,
+            CyxError::NoQuery => exit_code::NO_QUERY,
+        }
+    }
+}
+
+impl std::fmt::Display for CyxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CyxError::Config(message) | CyxError::Auth(message) => write!(f, "{}", message),
+            CyxError::Timeout(limit) => write!(f, "Timed out after {}s", limit.as_secs()),
+            CyxError::NoQuery => write!(f, "No query provided. Usage: cyx \"your query here\""),
+        }
+    }
+}
+
+impl std::error::Error for CyxError {}
+
+/// Exit code for `error`: the first `CyxError` in its chain decides, then
+/// connection failures and timeouts count as network errors
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    if let Some(cyx_error) = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<CyxError>())
+    {
+        return cyx_error.exit_code();
+    }
+
+    let unreachable = error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .any(|e| e.is_connect() || e.is_timeout() || e.is_request() || e.is_body());
+    if unreachable {
+        return exit_code::NETWORK;
+    }
+
+    exit_code::FAILURE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_exit_code_survives_context() {
+        let error = Err::<(), _>(CyxError::Auth("Groq API key not configured".into()))
+            .context("Failed to create provider")
+            .context("Failed to start session")
+            .unwrap_err();
+        assert_eq!(exit_code(&error), exit_code::AUTH);
+        assert_eq!(exit_code(&CyxError::NoQuery.into()), exit_code::NO_QUERY);
+        assert_eq!(exit_code(&anyhow::anyhow!("boom")), exit_code::FAILURE);
+    }
+
+    #[test]
+    fn test_connection_refused_is_network_error() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let error = reqwest::blocking::get(format!("http://127.0.0.1:{}", port)).unwrap_err();
+        let error = anyhow::Error::from(error).context("Failed to connect to Ollama");
+        assert_eq!(exit_code(&error), exit_code::NETWORK);
+    }
+}
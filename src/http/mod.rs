@@ -0,0 +1,108 @@
+use anyhow::{Context, Result};
+use reqwest::blocking::Client;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+use std::time::Duration;
+
+/// User-agent sent with every request unless a caller overrides it
+pub const DEFAULT_USER_AGENT: &str = concat!("cyx/", env!("CARGO_PKG_VERSION"));
+
+/// Build the blocking HTTP client used for all outgoing requests.
+/// `timeout` of `None` disables the request timeout (e.g. for long model pulls).
+pub fn build_client(timeout: Option<Duration>, user_agent: Option<&str>) -> Result<Client> {
+    Client::builder()
+        .timeout(timeout)
+        .user_agent(user_agent.unwrap_or(DEFAULT_USER_AGENT))
+        .build()
+        .context("Failed to create HTTP client")
+}
+
+/// Async counterpart of [`build_client`], for the async provider implementations
+pub fn build_async_client(
+    timeout: Option<Duration>,
+    user_agent: Option<&str>,
+) -> Result<reqwest::Client> {
+    let mut builder =
+        reqwest::Client::builder().user_agent(user_agent.unwrap_or(DEFAULT_USER_AGENT));
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
+    builder.build().context("Failed to create HTTP client")
+}
+
+/// Build a client for a user-configured, self-hosted endpoint (e.g. a remote Ollama).
+/// With `allow_invalid_certs` the client skips TLS certificate validation, which is
+/// only ever meant for self-signed certificates; a warning is printed once per run.
+/// Never use this for third-party APIs such as Groq or Perplexity.
+pub fn build_self_hosted_client(
+    timeout: Option<Duration>,
+    allow_invalid_certs: bool,
+) -> Result<Client> {
+    if !allow_invalid_certs {
+        return build_client(timeout, None);
+    }
+
+    warn_invalid_certs();
+    Client::builder()
+        .timeout(timeout)
+        .user_agent(DEFAULT_USER_AGENT)
+        .danger_accept_invalid_certs(true)
+        .build()
+        .context("Failed to create HTTP client")
+}
+
+/// Async counterpart of [`build_self_hosted_client`]
+pub fn build_self_hosted_async_client(
+    timeout: Option<Duration>,
+    allow_invalid_certs: bool,
+) -> Result<reqwest::Client> {
+    if !allow_invalid_certs {
+        return build_async_client(timeout, None);
+    }
+
+    warn_invalid_certs();
+    let mut builder = reqwest::Client::builder()
+        .user_agent(DEFAULT_USER_AGENT)
+        .danger_accept_invalid_certs(true);
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
+    builder.build().context("Failed to create HTTP client")
+}
+
+/// Set by `set_quiet` for `--quiet` and machine-readable output
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Keep the insecure-TLS warning from being printed for the rest of the run
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// The insecure-TLS warning, unless `set_quiet` silenced it
+fn invalid_certs_warning() -> Option<&'static str> {
+    (!QUIET.load(Ordering::Relaxed)).then_some(
+        "TLS certificate validation is DISABLED for self-hosted endpoints (--insecure / network.allow_invalid_certs)",
+    )
+}
+
+/// Print the insecure-TLS warning once per run, on stderr
+fn warn_invalid_certs() {
+    static WARNING: Once = Once::new();
+    if let Some(warning) = invalid_certs_warning() {
+        WARNING.call_once(|| crate::ui::Display::warning_stderr(warning));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quiet_silences_invalid_certs_warning() {
+        assert!(invalid_certs_warning().is_some());
+        set_quiet(true);
+        assert!(invalid_certs_warning().is_none());
+        set_quiet(false);
+        assert!(invalid_certs_warning().is_some());
+    }
+}
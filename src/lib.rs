@@ -2,7 +2,14 @@ pub mod cache;
 pub mod cli;
 pub mod config;
 pub mod deps;
+pub mod engine;
+pub mod error;
+pub mod http;
 pub mod llm;
 pub mod session;
 pub mod ui;
 pub mod update;
+pub mod util;
+
+pub use engine::{Cyx, QueryResult};
+pub use error::CyxError;
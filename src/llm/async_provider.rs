@@ -0,0 +1,354 @@
+use super::{ChunkHandler, LLMProvider, Message};
+use anyhow::{Context, Result};
+use std::future::Future;
+use std::sync::OnceLock;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+/// Async counterpart of [`LLMProvider`], for embedding cyx in async services
+/// (e.g. a chat bot). Metadata such as `name()` and `model()` comes from the
+/// blocking trait; the blocking `send_*` methods are driven by these.
+pub trait AsyncLLMProvider: LLMProvider {
+    /// Send a conversation and wait for the complete response
+    fn send_message(&self, messages: &[Message]) -> impl Future<Output = Result<String>> + Send;
+
+    /// Stream a response, sending each text chunk on `chunks` as it arrives.
+    /// Returns the complete response; chunks are discarded if the receiver is gone.
+    fn send_message_stream(
+        &self,
+        messages: &[Message],
+        chunks: UnboundedSender<String>,
+    ) -> impl Future<Output = Result<String>> + Send;
+}
+
+/// Runs the async provider calls to completion for the blocking [`LLMProvider`]
+/// impls. The runtime is only started on the first blocking call, so a provider
+/// used purely from async code never creates (or drops) one inside a runtime.
+#[derive(Default)]
+pub(crate) struct BlockingBridge {
+    runtime: OnceLock<Runtime>,
+}
+
+impl BlockingBridge {
+    fn runtime(&self) -> Result<&Runtime> {
+        if let Some(runtime) = self.runtime.get() {
+            return Ok(runtime);
+        }
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Failed to start async runtime")?;
+        Ok(self.runtime.get_or_init(|| runtime))
+    }
+
+    pub(crate) fn block_on<T>(&self, future: impl Future<Output = Result<T>>) -> Result<T> {
+        self.runtime()?.block_on(future)
+    }
+
+    /// Drive a streaming request, passing chunks to `on_chunk` as they arrive.
+    /// If `on_chunk` breaks, the request is dropped (closing the connection) and
+    /// the chunks received so far are returned.
+    pub(crate) fn stream<F>(
+        &self,
+        request: impl FnOnce(UnboundedSender<String>) -> F,
+        mut on_chunk: ChunkHandler,
+    ) -> Result<String>
+    where
+        F: Future<Output = Result<String>>,
+    {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let request = request(sender);
+
+        self.block_on(async move {
+            tokio::pin!(request);
+            let mut received = String::new();
+            loop {
+                tokio::select! {
+                    biased;
+                    Some(chunk) = receiver.recv() => {
+                        received.push_str(&chunk);
+                        if on_chunk(&chunk).is_break() {
+                            return Ok(received);
+                        }
+                    }
+                    result = &mut request => {
+                        // Chunks not yet handed over: a break here must stop the
+                        // answer at the same point as one during the request
+                        while let Ok(chunk) = receiver.try_recv() {
+                            received.push_str(&chunk);
+                            if on_chunk(&chunk).is_break() {
+                                return Ok(received);
+                            }
+                        }
+                        return result;
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Read the body of a streaming (SSE) chat completion, passing each line to
+/// `handle_line` until it returns false. Endpoints that ignore `stream: true` (some
+/// OpenAI-compatible proxies) answer with a plain body instead; that is detected by
+/// its Content-Type, parsed whole with `parse_complete` and sent as a single chunk.
+pub(crate) async fn read_event_stream(
+    mut response: reqwest::Response,
+    chunks: &UnboundedSender<String>,
+    mut handle_line: impl FnMut(&str, &mut String) -> bool,
+    parse_complete: impl FnOnce(&str) -> Result<String>,
+) -> Result<String> {
+    let is_event_stream = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"));
+
+    if !is_event_stream {
+        let body = response
+            .text()
+            .await
+            .context("Failed to read response body")?;
+        let content = parse_complete(&body)?;
+        let _ = chunks.send(content.clone());
+        return Ok(content);
+    }
+
+    let mut full_response = String::new();
+    let mut lines = LineBuffer::default();
+
+    while let Some(bytes) = response
+        .chunk()
+        .await
+        .context("Failed to read stream chunk")?
+    {
+        for line in lines.push(&bytes) {
+            if !handle_line(&line, &mut full_response) {
+                return Ok(full_response);
+            }
+        }
+    }
+
+    if let Some(line) = lines.finish() {
+        handle_line(&line, &mut full_response);
+    }
+
+    Ok(full_response)
+}
+
+/// Splits a streamed response body into lines as byte chunks arrive
+#[derive(Debug, Default)]
+pub(crate) struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+impl LineBuffer {
+    /// Append a chunk and return the lines it completed, without line endings
+    pub(crate) fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(bytes);
+
+        let mut lines = Vec::new();
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            lines.push(Self::decode(&line[..end]));
+        }
+        lines
+    }
+
+    /// The trailing line if the body did not end with a newline
+    pub(crate) fn finish(self) -> Option<String> {
+        (!self.pending.is_empty()).then(|| Self::decode(&self.pending))
+    }
+
+    fn decode(line: &[u8]) -> String {
+        String::from_utf8_lossy(line)
+            .trim_end_matches('\r')
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::ControlFlow;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_line_buffer_joins_split_lines() {
+        let mut buffer = LineBuffer::default();
+        assert!(buffer.push(b"data: {\"a\"").is_empty());
+        assert_eq!(
+            buffer.push(b":1}\r\n\ndata: [DO"),
+            vec!["data: {\"a\":1}", ""]
+        );
+        assert_eq!(buffer.push(b"NE]\n"), vec!["data: [DONE]"]);
+        assert_eq!(buffer.finish(), None);
+
+        let mut buffer = LineBuffer::default();
+        buffer.push("{\"done\":true} é".as_bytes());
+        assert_eq!(buffer.finish().as_deref(), Some("{\"done\":true} é"));
+    }
+
+    #[test]
+    fn test_bridge_streams_chunks_in_order() {
+        let bridge = BlockingBridge::default();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+
+        let response = bridge
+            .stream(
+                |chunks| async move {
+                    let mut full = String::new();
+                    for chunk in ["nmap ", "-sV ", "target"] {
+                        let _ = chunks.send(chunk.to_string());
+                        full.push_str(chunk);
+                        tokio::task::yield_now().await;
+                    }
+                    Ok(full)
+                },
+                Box::new(move |chunk| {
+                    sink.lock().unwrap().push(chunk.to_string());
+                    ControlFlow::Continue(())
+                }),
+            )
+            .unwrap();
+
+        assert_eq!(response, "nmap -sV target");
+        assert_eq!(*received.lock().unwrap(), vec!["nmap ", "-sV ", "target"]);
+    }
+
+    #[test]
+    fn test_bridge_stops_when_handler_breaks() {
+        let bridge = BlockingBridge::default();
+
+        let response = bridge
+            .stream(
+                |chunks| async move {
+                    for chunk in ["nmap ", "-sV ", "target"] {
+                        let _ = chunks.send(chunk.to_string());
+                        tokio::task::yield_now().await;
+                    }
+                    Ok("never returned".to_string())
+                },
+                Box::new(|chunk| {
+                    if chunk == "-sV " {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                }),
+            )
+            .unwrap();
+
+        assert_eq!(response, "nmap -sV ");
+    }
+
+    #[test]
+    fn test_bridge_stops_when_handler_breaks_after_request_finished() {
+        let bridge = BlockingBridge::default();
+
+        // Every chunk is queued before the request completes, without yielding
+        let response = bridge
+            .stream(
+                |chunks| async move {
+                    for chunk in ["nmap ", "-sV ", "target"] {
+                        let _ = chunks.send(chunk.to_string());
+                    }
+                    Ok("nmap -sV target".to_string())
+                },
+                Box::new(|chunk| {
+                    if chunk == "-sV " {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                }),
+            )
+            .unwrap();
+
+        assert_eq!(response, "nmap -sV ");
+    }
+
+    /// Serve one HTTP response with `content_type` and `body`, returning its URL
+    fn serve_once(content_type: &'static str, body: &'static str) -> String {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 4096];
+            let _ = stream.read(&mut request);
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                content_type,
+                body.len(),
+                body
+            );
+        });
+        url
+    }
+
+    /// Read `url` as a streamed completion whose SSE lines are plain `data: <text>`
+    fn read_stream(url: &str) -> (String, Vec<String>) {
+        let bridge = BlockingBridge::default();
+        let (chunks, mut receiver) = mpsc::unbounded_channel();
+
+        let response = bridge
+            .block_on(async {
+                let response = reqwest::get(url).await?;
+                read_event_stream(
+                    response,
+                    &chunks,
+                    |line, full| match line.strip_prefix("data: ") {
+                        Some("[DONE]") => false,
+                        Some(text) => {
+                            let _ = chunks.send(text.to_string());
+                            full.push_str(text);
+                            true
+                        }
+                        None => true,
+                    },
+                    |body| {
+                        let json: serde_json::Value = serde_json::from_str(body)?;
+                        Ok(json["choices"][0]["message"]["content"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string())
+                    },
+                )
+                .await
+            })
+            .unwrap();
+
+        let mut received = Vec::new();
+        while let Ok(chunk) = receiver.try_recv() {
+            received.push(chunk);
+        }
+        (response, received)
+    }
+
+    #[test]
+    fn test_event_stream_is_read_line_by_line() {
+        let url = serve_once(
+            "text/event-stream",
+            "data: nmap \n\ndata: -sV\n\ndata: [DONE]\n\n",
+        );
+        let (response, received) = read_stream(&url);
+        assert_eq!(response, "nmap -sV");
+        assert_eq!(received, vec!["nmap ", "-sV"]);
+    }
+
+    #[test]
+    fn test_non_streaming_body_becomes_one_chunk() {
+        let url = serve_once(
+            "application/json",
+            r#"{"choices":[{"message":{"role":"assistant","content":"nmap -sV target"}}]}"#,
+        );
+        let (response, received) = read_stream(&url);
+        assert_eq!(response, "nmap -sV target");
+        assert_eq!(received, vec!["nmap -sV target"]);
+    }
+}
@@ -0,0 +1,111 @@
+use super::{Message, Role};
+
+/// Characters per token for the rough `chars / 4` estimate
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Tokens added per message for the role and chat-template framing
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// Estimated prompt size after fitting a conversation into a token budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub estimated: usize,
+    pub budget: usize,
+    /// Oldest history messages dropped to fit
+    pub dropped: usize,
+}
+
+/// Estimate the prompt tokens of a conversation (chars/4 plus per-message overhead)
+pub fn estimate_tokens(messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .map(|message| {
+            message.content.chars().count().div_ceil(CHARS_PER_TOKEN) + MESSAGE_OVERHEAD_TOKENS
+        })
+        .sum()
+}
+
+/// Drop the oldest user/assistant turn pairs until the conversation fits in
+/// `budget` tokens. System messages and the final user message are always kept,
+/// so the result may still exceed the budget when they alone are too large.
+pub fn fit_to_budget(messages: &mut Vec<Message>, budget: usize) -> TokenUsage {
+    let mut dropped = 0;
+    let mut estimated = estimate_tokens(messages);
+
+    while estimated > budget {
+        let Some(first_turn) = messages.iter().position(|m| m.role != Role::System) else {
+            break;
+        };
+
+        // Keep the last message (the query being asked) and whole turn pairs,
+        // so roles still alternate after trimming
+        if messages.len() - first_turn < 3 {
+            break;
+        }
+
+        messages.drain(first_turn..first_turn + 2);
+        dropped += 2;
+        estimated = estimate_tokens(messages);
+    }
+
+    TokenUsage {
+        estimated,
+        budget,
+        dropped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversation(history_turns: usize, turn_len: usize) -> Vec<Message> {
+        let mut messages = vec![Message::system("s".repeat(40))];
+        for i in 0..history_turns {
+            messages.push(Message::user(format!("{}{}", i, "q".repeat(turn_len))));
+            messages.push(Message::assistant("a".repeat(turn_len)));
+        }
+        messages.push(Message::user("latest question"));
+        messages
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        let messages = vec![Message::system("abcd"), Message::user("abcde")];
+        assert_eq!(estimate_tokens(&messages), (1 + 4) + (2 + 4));
+    }
+
+    #[test]
+    fn test_fit_drops_oldest_turns_first() {
+        let mut messages = conversation(3, 400);
+        let full = estimate_tokens(&messages);
+
+        let usage = fit_to_budget(&mut messages, full - 1);
+        assert_eq!(usage.dropped, 2);
+        assert!(usage.estimated <= usage.budget);
+        assert_eq!(messages.len(), 6);
+        assert_eq!(messages[0].role, Role::System);
+        assert!(messages[1].content.starts_with('1'));
+        assert_eq!(messages.last().unwrap().content, "latest question");
+        Message::validate_sequence(&messages).unwrap();
+    }
+
+    #[test]
+    fn test_fit_keeps_system_and_query_when_over_budget() {
+        let mut messages = conversation(2, 400);
+
+        let usage = fit_to_budget(&mut messages, 1);
+        assert_eq!(usage.dropped, 4);
+        assert!(usage.estimated > usage.budget);
+        assert_eq!(messages.len(), 2);
+        Message::validate_sequence(&messages).unwrap();
+    }
+
+    #[test]
+    fn test_fit_leaves_small_conversations_alone() {
+        let mut messages = conversation(2, 10);
+        let usage = fit_to_budget(&mut messages, 10_000);
+        assert_eq!(usage.dropped, 0);
+        assert_eq!(messages.len(), 6);
+    }
+}
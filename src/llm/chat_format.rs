@@ -0,0 +1,86 @@
+use super::{Message, Role};
+use serde::Serialize;
+
+/// A message in the OpenAI chat format used by Groq and Perplexity: `content` is a
+/// plain string, or an array of text and image parts when images are attached
+#[derive(Debug, Serialize)]
+pub(crate) struct ChatMessage<'a> {
+    role: Role,
+    content: ChatContent<'a>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum ChatContent<'a> {
+    Text(&'a str),
+    Parts(Vec<ContentPart<'a>>),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentPart<'a> {
+    Text { text: &'a str },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Serialize)]
+struct ImageUrl {
+    url: String,
+}
+
+impl<'a> From<&'a Message> for ChatMessage<'a> {
+    fn from(message: &'a Message) -> Self {
+        let content = if message.images.is_empty() {
+            ChatContent::Text(&message.content)
+        } else {
+            let mut parts = vec![ContentPart::Text {
+                text: &message.content,
+            }];
+            parts.extend(message.images.iter().map(|image| ContentPart::ImageUrl {
+                image_url: ImageUrl {
+                    url: image.data_url(),
+                },
+            }));
+            ChatContent::Parts(parts)
+        };
+
+        Self {
+            role: message.role,
+            content,
+        }
+    }
+}
+
+/// Convert a conversation to the OpenAI chat format
+pub(crate) fn chat_messages(messages: &[Message]) -> Vec<ChatMessage<'_>> {
+    messages.iter().map(ChatMessage::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::Image;
+    use serde_json::json;
+
+    #[test]
+    fn test_text_and_image_parts() {
+        let image = Image::from_bytes(b"\xff\xd8\xff\xe0").unwrap();
+        let messages = vec![
+            Message::system("be brief"),
+            Message::user("what is this config?").with_images(vec![image.clone()]),
+        ];
+
+        let value = serde_json::to_value(chat_messages(&messages)).unwrap();
+        assert_eq!(value[0], json!({"role": "system", "content": "be brief"}));
+        assert_eq!(
+            value[1],
+            json!({
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "what is this config?"},
+                    {"type": "image_url", "image_url": {"url": image.data_url()}}
+                ]
+            })
+        );
+    }
+}
@@ -1,22 +1,52 @@
-use super::{provider::LLMProvider, Message};
+use super::async_provider::{read_event_stream, AsyncLLMProvider, BlockingBridge};
+use super::chat_format::{chat_messages, ChatMessage};
+use super::max_tokens::{clamp_max_tokens, reduced_max_tokens};
+use super::{is_auth_failure, provider::LLMProvider, ChunkHandler, Message};
+use crate::error::CyxError;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Context window of llama-3.3-70b-versatile, in tokens
+const GROQ_CONTEXT_WINDOW: usize = 131_072;
+
+/// Response length requested from the API (`max_tokens`)
+const GROQ_MAX_TOKENS: u32 = 8000;
+
+const GROQ_MODEL: &str = "llama-3.3-70b-versatile";
+
+/// Used instead of `GROQ_MODEL` when a message carries images
+const GROQ_VISION_MODEL: &str = "meta-llama/llama-4-scout-17b-16e-instruct";
 
 const GROQ_API_URL: &str = "https://api.groq.com/openai/v1/chat/completions";
 
+/// Lists models; a free way to validate the API key
+const GROQ_MODELS_URL: &str = "https://api.groq.com/openai/v1/models";
+
 pub struct GroqProvider {
     api_key: String,
-    client: reqwest::blocking::Client,
+    client: reqwest::Client,
+    bridge: BlockingBridge,
 }
 
 #[derive(Debug, Serialize)]
-struct GroqRequest {
+struct GroqRequest<'a> {
     model: String,
-    messages: Vec<Message>,
+    messages: Vec<ChatMessage<'a>>,
     temperature: f32,
     max_tokens: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
     stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
+}
+
+/// `{"type": "json_object"}` asks for a response that is one JSON object
+#[derive(Debug, Serialize)]
+struct ResponseFormat {
+    #[serde(rename = "type")]
+    kind: &'static str,
 }
 
 #[derive(Debug, Deserialize)]
@@ -40,120 +70,206 @@ struct Delta {
 
 impl GroqProvider {
     pub fn new(api_key: String) -> Result<Self> {
-        let client = reqwest::blocking::Client::builder()
-            .timeout(std::time::Duration::from_secs(120))
-            .build()
-            .context("Failed to create HTTP client")?;
+        Self::with_timeout(api_key, Duration::from_secs(120))
+    }
 
-        Ok(Self { api_key, client })
+    /// Client whose requests (including reading a streamed body) give up after `timeout`
+    pub fn with_timeout(api_key: String, timeout: Duration) -> Result<Self> {
+        let client = crate::http::build_async_client(Some(timeout), None)?;
+
+        Ok(Self {
+            api_key,
+            client,
+            bridge: BlockingBridge::default(),
+        })
     }
-}
 
-impl LLMProvider for GroqProvider {
-    fn send_message(&self, messages: &[Message]) -> Result<String> {
-        let request = GroqRequest {
-            model: "llama-3.3-70b-versatile".to_string(),
-            messages: messages.to_vec(),
+    /// POST a chat completion, in JSON mode if `json`. A 400 rejecting `max_tokens`
+    /// as too large is retried once with the limit from the error message.
+    async fn post(
+        &self,
+        stream: bool,
+        json: bool,
+        messages: &[Message],
+    ) -> Result<reqwest::Response> {
+        let model = if messages.iter().any(|m| !m.images.is_empty()) {
+            GROQ_VISION_MODEL
+        } else {
+            GROQ_MODEL
+        };
+        let mut request = GroqRequest {
+            model: model.to_string(),
+            messages: chat_messages(messages),
             temperature: 0.7,
-            max_tokens: 8000,
-            stream: None,
+            max_tokens: clamp_max_tokens(model, GROQ_MAX_TOKENS),
+            stream: stream.then_some(true),
+            response_format: json.then_some(ResponseFormat {
+                kind: "json_object",
+            }),
         };
 
-        let response = self
-            .client
-            .post(GROQ_API_URL)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .context("Failed to send request to Groq API")?;
+        let context = if stream {
+            "Failed to send streaming request to Groq API"
+        } else {
+            "Failed to send request to Groq API"
+        };
+        let mut retried = false;
+        loop {
+            let response = self
+                .client
+                .post(GROQ_API_URL)
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .header("Content-Type", "application/json")
+                .json(&request)
+                .send()
+                .await
+                .context(context)?;
 
-        if !response.status().is_success() {
             let status = response.status();
+            if status.is_success() {
+                return Ok(response);
+            }
+
             let error_text = response
                 .text()
+                .await
                 .unwrap_or_else(|_| "Unknown error".to_string());
-            anyhow::bail!("Groq API error ({}): {}", status, error_text);
+            if status == reqwest::StatusCode::BAD_REQUEST && !retried {
+                if let Some(reduced) = reduced_max_tokens(&error_text, request.max_tokens) {
+                    request.max_tokens = reduced;
+                    retried = true;
+                    continue;
+                }
+            }
+            let message = format!("Groq API error ({}): {}", status, error_text);
+            if is_auth_failure(status) {
+                return Err(CyxError::Auth(message).into());
+            }
+            anyhow::bail!(message);
         }
-
-        let groq_response: GroqResponse =
-            response.json().context("Failed to parse Groq response")?;
-
-        let content = groq_response
-            .choices
-            .first()
-            .and_then(|c| c.message.as_ref())
-            .map(|m| m.content.clone())
-            .ok_or_else(|| anyhow::anyhow!("No response from Groq"))?;
-
-        Ok(content)
     }
 
-    fn send_message_stream(
-        &self,
-        messages: &[Message],
-        mut on_chunk: Box<dyn FnMut(&str)>,
-    ) -> Result<String> {
-        use std::io::{BufRead, BufReader};
-
-        let request = GroqRequest {
-            model: "llama-3.3-70b-versatile".to_string(),
-            messages: messages.to_vec(),
-            temperature: 0.7,
-            max_tokens: 8000,
-            stream: Some(true),
-        };
-
+    /// GET the models endpoint. `Ok(false)` if the endpoint is unavailable, so the
+    /// caller can fall back to a completion.
+    async fn check_models_endpoint(&self) -> Result<bool> {
         let response = self
             .client
-            .post(GROQ_API_URL)
+            .get(GROQ_MODELS_URL)
             .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
             .send()
-            .context("Failed to send streaming request to Groq API")?;
+            .await
+            .context("Failed to connect to Groq API")?;
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            anyhow::bail!("Groq API error ({}): {}", status, error_text);
+        let status = response.status();
+        if status.is_success() {
+            return Ok(true);
+        }
+        if matches!(
+            status,
+            reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::METHOD_NOT_ALLOWED
+        ) {
+            return Ok(false);
         }
 
-        let mut full_response = String::new();
-        let reader = BufReader::new(response);
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        anyhow::bail!("Groq API error ({}): {}", status, error_text);
+    }
 
-        for line in reader.lines() {
-            let line = line.context("Failed to read stream line")?;
+    /// A complete (non-streamed) response, in JSON mode if `json`
+    async fn complete(&self, json: bool, messages: &[Message]) -> Result<String> {
+        let response = self.post(false, json, messages).await?;
+        let body = response
+            .text()
+            .await
+            .context("Failed to read Groq response")?;
+        Self::parse_completion(&body)
+    }
 
-            // Skip empty lines and non-data lines
-            if line.is_empty() || !line.starts_with("data: ") {
-                continue;
-            }
+    /// Text of a complete (non-streamed) chat completion body
+    fn parse_completion(body: &str) -> Result<String> {
+        let groq_response: GroqResponse = serde_json::from_str(body).with_context(|| {
+            format!(
+                "Failed to parse Groq response: {}",
+                body.chars().take(200).collect::<String>()
+            )
+        })?;
 
-            // Extract the JSON part
-            let data = &line[6..]; // Skip "data: " prefix
+        groq_response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|c| c.message)
+            .map(|m| m.content)
+            .ok_or_else(|| anyhow::anyhow!("No response from Groq"))
+    }
 
-            // Check for end of stream
-            if data == "[DONE]" {
-                break;
-            }
+    /// Handle one SSE line, returning false at the end of the stream
+    fn handle_stream_line(line: &str, chunks: &UnboundedSender<String>, full: &mut String) -> bool {
+        // Skip empty lines and non-data lines
+        let Some(data) = line.strip_prefix("data: ") else {
+            return true;
+        };
 
-            // Parse the SSE data
-            if let Ok(chunk_response) = serde_json::from_str::<GroqResponse>(data) {
-                if let Some(choice) = chunk_response.choices.first() {
-                    if let Some(delta) = &choice.delta {
-                        if let Some(content) = &delta.content {
-                            on_chunk(content);
-                            full_response.push_str(content);
-                        }
+        // Check for end of stream
+        if data == "[DONE]" {
+            return false;
+        }
+
+        // Parse the SSE data
+        if let Ok(chunk_response) = serde_json::from_str::<GroqResponse>(data) {
+            if let Some(choice) = chunk_response.choices.first() {
+                if let Some(delta) = &choice.delta {
+                    if let Some(content) = &delta.content {
+                        let _ = chunks.send(content.clone());
+                        full.push_str(content);
                     }
                 }
             }
         }
 
-        Ok(full_response)
+        true
+    }
+}
+
+impl AsyncLLMProvider for GroqProvider {
+    async fn send_message(&self, messages: &[Message]) -> Result<String> {
+        self.complete(false, messages).await
+    }
+
+    async fn send_message_stream(
+        &self,
+        messages: &[Message],
+        chunks: UnboundedSender<String>,
+    ) -> Result<String> {
+        let response = self.post(true, false, messages).await?;
+        read_event_stream(
+            response,
+            &chunks,
+            |line, full| Self::handle_stream_line(line, &chunks, full),
+            Self::parse_completion,
+        )
+        .await
+    }
+}
+
+impl LLMProvider for GroqProvider {
+    fn send_message(&self, messages: &[Message]) -> Result<String> {
+        self.bridge
+            .block_on(AsyncLLMProvider::send_message(self, messages))
+    }
+
+    fn send_message_stream(&self, messages: &[Message], on_chunk: ChunkHandler) -> Result<String> {
+        self.bridge.stream(
+            |chunks| AsyncLLMProvider::send_message_stream(self, messages, chunks),
+            on_chunk,
+        )
+    }
+
+    fn send_message_json(&self, messages: &[Message]) -> Result<String> {
+        self.bridge.block_on(self.complete(true, messages))
     }
 
     fn name(&self) -> &str {
@@ -161,10 +277,25 @@ impl LLMProvider for GroqProvider {
     }
 
     fn model(&self) -> &str {
-        "llama-3.3-70b-versatile"
+        GROQ_MODEL
     }
 
     fn searches_web(&self) -> bool {
         false // Groq uses knowledge base only
     }
+
+    fn prompt_budget(&self) -> usize {
+        GROQ_CONTEXT_WINDOW - GROQ_MAX_TOKENS as usize
+    }
+
+    fn supports_images(&self) -> bool {
+        true // Routed to GROQ_VISION_MODEL
+    }
+
+    fn test_connection_cheap(&self) -> Result<()> {
+        if self.bridge.block_on(self.check_models_endpoint())? {
+            return Ok(());
+        }
+        LLMProvider::send_message(self, &[Message::user("test")]).map(|_| ())
+    }
 }
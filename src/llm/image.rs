@@ -0,0 +1,109 @@
+use crate::util::base64_encode;
+use anyhow::{Context, Result};
+use serde::{Serialize, Serializer};
+use std::path::Path;
+
+/// Largest image accepted by `--image`, before encoding
+pub const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+/// Image attached to a user message, held base64-encoded. Serializes as the bare
+/// base64 string, which is what Ollama's `images` field expects.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Image {
+    pub media_type: &'static str,
+    pub data: String,
+}
+
+impl Image {
+    /// Read and encode a PNG, JPEG, GIF or WebP file
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let bytes =
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+        if bytes.len() > MAX_IMAGE_BYTES {
+            anyhow::bail!(
+                "{} is {} MB; images must be at most {} MB",
+                path.display(),
+                bytes.len() / (1024 * 1024),
+                MAX_IMAGE_BYTES / (1024 * 1024)
+            );
+        }
+
+        Self::from_bytes(&bytes)
+            .map_err(|e| anyhow::anyhow!("Cannot attach {}: {}", path.display(), e))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let media_type = detect_media_type(bytes).ok_or_else(|| {
+            anyhow::anyhow!("Unsupported image format (expected PNG, JPEG, GIF or WebP)")
+        })?;
+
+        Ok(Self {
+            media_type,
+            data: base64_encode(bytes),
+        })
+    }
+
+    /// `data:` URL for APIs that take images as URLs (OpenAI-compatible)
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+
+    /// Decoded size in bytes
+    pub fn size(&self) -> usize {
+        let padding = self.data.bytes().rev().take_while(|&b| b == b'=').count();
+        self.data.len() / 4 * 3 - padding
+    }
+}
+
+impl std::fmt::Debug for Image {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Image")
+            .field("media_type", &self.media_type)
+            .field("size", &self.size())
+            .finish()
+    }
+}
+
+impl Serialize for Image {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.data)
+    }
+}
+
+/// Media type from the file's magic bytes
+fn detect_media_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_format_and_encodes() {
+        let png = Image::from_bytes(b"\x89PNG\r\n\x1a\nrest").unwrap();
+        assert_eq!(png.media_type, "image/png");
+        assert_eq!(png.size(), 12);
+        assert!(png
+            .data_url()
+            .starts_with("data:image/png;base64,iVBORw0KGg"));
+        assert_eq!(serde_json::to_value(&png).unwrap(), png.data.as_str());
+
+        let webp = Image::from_bytes(b"RIFF\0\0\0\0WEBPVP8 ").unwrap();
+        assert_eq!(webp.media_type, "image/webp");
+
+        let err = Image::from_bytes(b"%PDF-1.7").unwrap_err();
+        assert!(err.to_string().contains("Unsupported image format"));
+    }
+}
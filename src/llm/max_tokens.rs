@@ -0,0 +1,68 @@
+//! `max_tokens` limits of the hosted models, so a request never asks for a longer
+//! completion than the model allows (which the API rejects with a 400)
+
+/// Largest completion each known model accepts, in tokens
+const MODEL_MAX_TOKENS: &[(&str, u32)] = &[
+    ("llama-3.3-70b-versatile", 32_768),
+    ("llama-3.1-8b-instant", 8_192),
+    ("meta-llama/llama-4-scout-17b-16e-instruct", 8_192),
+    ("sonar", 8_000),
+    ("sonar-pro", 8_000),
+];
+
+/// `requested`, lowered to the model's limit when it has a known one
+pub(crate) fn clamp_max_tokens(model: &str, requested: u32) -> u32 {
+    MODEL_MAX_TOKENS
+        .iter()
+        .find(|(name, _)| *name == model)
+        .map_or(requested, |&(_, limit)| requested.min(limit))
+}
+
+/// If an API error rejects `requested` as too large a `max_tokens`, a value to retry
+/// with: the largest smaller number in the message (usually the limit it states),
+/// or half the request when the message gives none
+pub(crate) fn reduced_max_tokens(error_text: &str, requested: u32) -> Option<u32> {
+    let lower = error_text.to_lowercase();
+    if !["max_tokens", "max tokens", "completion tokens"]
+        .iter()
+        .any(|phrase| lower.contains(phrase))
+    {
+        return None;
+    }
+
+    let stated_limit = error_text
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|number| number.parse::<u32>().ok())
+        .filter(|&number| number > 0 && number < requested)
+        .max();
+
+    let reduced = stated_limit.unwrap_or(requested / 2);
+    (reduced > 0).then_some(reduced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp_to_known_model_limit() {
+        assert_eq!(
+            clamp_max_tokens("meta-llama/llama-4-scout-17b-16e-instruct", 16_000),
+            8_192
+        );
+        assert_eq!(clamp_max_tokens("llama-3.3-70b-versatile", 8_000), 8_000);
+        assert_eq!(clamp_max_tokens("unknown-model", 100_000), 100_000);
+    }
+
+    #[test]
+    fn test_reduced_max_tokens_from_error() {
+        let groq = r#"{"error":{"message":"`max_tokens` must be less than or equal to `8192`, the maximum value for `max_tokens` is less than the `context_window` for this model","type":"invalid_request_error","param":"max_tokens"}}"#;
+        assert_eq!(reduced_max_tokens(groq, 16_000), Some(8_192));
+
+        let vague = "max_tokens is too large for this model";
+        assert_eq!(reduced_max_tokens(vague, 8_000), Some(4_000));
+
+        let unrelated = r#"{"error":{"message":"Invalid API key"}}"#;
+        assert_eq!(reduced_max_tokens(unrelated, 8_000), None);
+    }
+}
@@ -0,0 +1,128 @@
+use super::{ChunkHandler, LLMProvider, Message};
+use anyhow::Result;
+use std::time::Duration;
+
+/// Answer shown by `--demo`, in the format the system prompt asks for
+const DEMO_RESPONSE: &str = r#"```bash
+nmap -sS -T4 -p- --min-rate 1000 <target>
+```
+
+Flags:
+  -sS            SYN ("half-open") scan, never completes the TCP handshake
+  -T4            Aggressive timing for reliable networks
+  -p-            All 65535 ports
+  --min-rate     Send at least 1000 packets per second
+
+Needs root for raw packets. Follow up with -sV -sC on the open ports.
+
+[SOURCES]
+- Nmap port scanning techniques: https://nmap.org/book/man-port-scanning-techniques.html
+- Nmap timing and performance: https://nmap.org/book/man-performance.html"#;
+
+/// Offline provider that answers every request with a canned response, streamed
+/// in small chunks like a real API. Backs `--demo` and session tests.
+pub struct MockProvider {
+    response: String,
+    chunk_chars: usize,
+    chunk_delay: Duration,
+}
+
+impl MockProvider {
+    pub fn new(response: impl Into<String>) -> Self {
+        Self {
+            response: response.into(),
+            chunk_chars: 16,
+            chunk_delay: Duration::ZERO,
+        }
+    }
+
+    /// The `--demo` answer, streamed at a readable pace
+    pub fn demo() -> Self {
+        Self::new(DEMO_RESPONSE).with_chunk_delay(Duration::from_millis(20))
+    }
+
+    /// Stream `chars` characters per chunk (at least one)
+    pub fn with_chunk_chars(mut self, chars: usize) -> Self {
+        self.chunk_chars = chars.max(1);
+        self
+    }
+
+    /// Pause before each streamed chunk
+    pub fn with_chunk_delay(mut self, delay: Duration) -> Self {
+        self.chunk_delay = delay;
+        self
+    }
+}
+
+impl LLMProvider for MockProvider {
+    fn send_message(&self, _messages: &[Message]) -> Result<String> {
+        Ok(self.response.clone())
+    }
+
+    fn send_message_stream(
+        &self,
+        _messages: &[Message],
+        mut on_chunk: ChunkHandler,
+    ) -> Result<String> {
+        let chars: Vec<char> = self.response.chars().collect();
+        let mut streamed = String::new();
+
+        for chunk in chars.chunks(self.chunk_chars) {
+            std::thread::sleep(self.chunk_delay);
+            let chunk: String = chunk.iter().collect();
+            streamed.push_str(&chunk);
+            if on_chunk(&chunk).is_break() {
+                break;
+            }
+        }
+
+        Ok(streamed)
+    }
+
+    fn name(&self) -> &str {
+        "Demo"
+    }
+
+    fn model(&self) -> &str {
+        "canned"
+    }
+
+    fn searches_web(&self) -> bool {
+        false
+    }
+
+    fn prompt_budget(&self) -> usize {
+        8192
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::ControlFlow;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_stream_stops_on_break() {
+        let provider = MockProvider::new("abcdefgh").with_chunk_chars(3);
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let seen = chunks.clone();
+
+        let streamed = provider
+            .send_message_stream(
+                &[],
+                Box::new(move |chunk| {
+                    seen.lock().unwrap().push(chunk.to_string());
+                    if chunk == "def" {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                }),
+            )
+            .unwrap();
+
+        assert_eq!(streamed, "abcdef");
+        assert_eq!(*chunks.lock().unwrap(), vec!["abc", "def"]);
+    }
+}
@@ -1,40 +1,275 @@
+pub mod async_provider;
+pub mod budget;
+pub(crate) mod chat_format;
 pub mod groq;
+pub mod image;
+pub(crate) mod max_tokens;
+pub mod mock;
 pub mod ollama;
+pub mod ollama_profiles;
 pub mod perplexity;
 pub mod provider;
+pub mod rate_limit;
+pub mod thinking;
+pub mod timeout;
 
+pub use async_provider::AsyncLLMProvider;
+pub use budget::{estimate_tokens, fit_to_budget, TokenUsage};
 pub use groq::GroqProvider;
+pub use image::Image;
+pub use mock::MockProvider;
 pub use ollama::OllamaProvider;
+pub use ollama_profiles::{model_profiles, ModelProfile};
 pub use perplexity::PerplexityProvider;
-pub use provider::LLMProvider;
+pub use provider::{ChunkHandler, LLMProvider};
+pub use rate_limit::{RateLimited, RateLimiter, MIN_RPS};
+pub use thinking::{strip_thinking, StripThinking, ThinkingFilter};
+pub use timeout::TimeLimited;
 
+use crate::config::Config;
+use crate::error::CyxError;
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Create the client for provider `kind` from the config, failing if it has no API key.
+/// Requests are paced to `network.rps` when it is set, and thinking blocks are
+/// dropped from responses unless `response.strip_thinking` is off. A `timeout`
+/// replaces the provider's HTTP timeout and caps how long each request may stream.
+pub fn create_provider(
+    config: &Config,
+    kind: &crate::config::LLMProvider,
+    allow_invalid_certs: bool,
+    timeout: Option<Duration>,
+) -> Result<Box<dyn LLMProvider>> {
+    use crate::config::LLMProvider as Kind;
+
+    let provider: Box<dyn LLMProvider> = match kind {
+        Kind::Groq => {
+            let api_key = config
+                .api_keys
+                .groq
+                .clone()
+                .ok_or_else(|| CyxError::Auth("Groq API key not configured".to_string()))?;
+            match timeout {
+                Some(timeout) => Box::new(GroqProvider::with_timeout(api_key, timeout)?),
+                None => Box::new(GroqProvider::new(api_key)?),
+            }
+        }
+        Kind::Perplexity => {
+            let api_key =
+                config.api_keys.perplexity.clone().ok_or_else(|| {
+                    CyxError::Auth("Perplexity API key not configured".to_string())
+                })?;
+            match timeout {
+                Some(timeout) => Box::new(PerplexityProvider::with_timeout(api_key, timeout)?),
+                None => Box::new(PerplexityProvider::new(api_key)?),
+            }
+        }
+        Kind::Ollama => {
+            let mut ollama = config.ollama.clone();
+            if let Some(timeout) = timeout {
+                ollama.timeout_seconds = timeout.as_secs();
+            }
+            Box::new(OllamaProvider::new(ollama, allow_invalid_certs)?)
+        }
+    };
+
+    let provider: Box<dyn LLMProvider> = match timeout {
+        Some(timeout) => Box::new(TimeLimited::new(provider, timeout)),
+        None => provider,
+    };
+
+    let provider: Box<dyn LLMProvider> = if config.network.rps > 0.0 {
+        let limiter = RateLimiter::new(config.network.rps, 1);
+        Box::new(RateLimited::new(provider, limiter))
+    } else {
+        provider
+    };
+
+    if config.response.strip_thinking {
+        return Ok(Box::new(StripThinking::new(provider)));
+    }
+
+    Ok(provider)
+}
+
+/// Whether an HTTP status means the provider rejected the API key
+pub(crate) fn is_auth_failure(status: reqwest::StatusCode) -> bool {
+    matches!(
+        status,
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN
+    )
+}
+
+/// Chat message role, serialized as the lowercase strings providers expect
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        };
+        write!(f, "{}", name)
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
-    pub role: String,
+    pub role: Role,
     pub content: String,
+    /// Images attached to a user turn. Providers without vision support reject them.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<Image>,
 }
 
 impl Message {
+    /// Create a message, rejecting empty content
+    pub fn new(role: Role, content: impl Into<String>) -> Result<Self> {
+        let content = content.into();
+        if content.trim().is_empty() {
+            anyhow::bail!("{} message content cannot be empty", role);
+        }
+
+        Ok(Self {
+            role,
+            content,
+            images: Vec::new(),
+        })
+    }
+
     pub fn system(content: impl Into<String>) -> Self {
         Self {
-            role: "system".to_string(),
+            role: Role::System,
             content: content.into(),
+            images: Vec::new(),
         }
     }
 
     pub fn user(content: impl Into<String>) -> Self {
         Self {
-            role: "user".to_string(),
+            role: Role::User,
             content: content.into(),
+            images: Vec::new(),
         }
     }
 
     pub fn assistant(content: impl Into<String>) -> Self {
         Self {
-            role: "assistant".to_string(),
+            role: Role::Assistant,
             content: content.into(),
+            images: Vec::new(),
         }
     }
+
+    /// Attach images to this message
+    pub fn with_images(mut self, images: Vec<Image>) -> Self {
+        self.images = images;
+        self
+    }
+
+    /// Check a conversation before sending it: system messages only at the start,
+    /// then strictly alternating user/assistant turns ending with a user turn,
+    /// and no empty content.
+    pub fn validate_sequence(messages: &[Message]) -> Result<()> {
+        if messages.is_empty() {
+            anyhow::bail!("Conversation has no messages");
+        }
+
+        if let Some(index) = messages.iter().position(|m| m.content.trim().is_empty()) {
+            anyhow::bail!(
+                "Message {} ({}) has empty content",
+                index,
+                messages[index].role
+            );
+        }
+
+        let turns_start = messages
+            .iter()
+            .position(|m| m.role != Role::System)
+            .ok_or_else(|| anyhow::anyhow!("Conversation has no user message"))?;
+
+        let mut expected = Role::User;
+        for (index, message) in messages.iter().enumerate().skip(turns_start) {
+            if message.role != expected {
+                anyhow::bail!(
+                    "Message {} has role {}, expected {}",
+                    index,
+                    message.role,
+                    expected
+                );
+            }
+            expected = match expected {
+                Role::User => Role::Assistant,
+                _ => Role::User,
+            };
+        }
+
+        if expected != Role::Assistant {
+            anyhow::bail!("Conversation must end with a user message");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_serializes_lowercase() {
+        let json = serde_json::to_string(&Message::user("hi")).unwrap();
+        assert_eq!(json, r#"{"role":"user","content":"hi"}"#);
+
+        let message: Message =
+            serde_json::from_str(r#"{"role":"assistant","content":"ok"}"#).unwrap();
+        assert_eq!(message.role, Role::Assistant);
+    }
+
+    #[test]
+    fn test_new_rejects_empty_content() {
+        assert!(Message::new(Role::User, "  ").is_err());
+        assert!(Message::new(Role::User, "nmap scan").is_ok());
+    }
+
+    #[test]
+    fn test_validate_sequence_accepts_alternating_turns() {
+        let messages = vec![
+            Message::system("prompt"),
+            Message::user("q1"),
+            Message::assistant("a1"),
+            Message::user("q2"),
+        ];
+        assert!(Message::validate_sequence(&messages).is_ok());
+    }
+
+    #[test]
+    fn test_validate_sequence_rejects_bad_order() {
+        let consecutive_users = vec![Message::user("q1"), Message::user("q2")];
+        assert!(Message::validate_sequence(&consecutive_users).is_err());
+
+        let late_system = vec![Message::user("q1"), Message::system("prompt")];
+        assert!(Message::validate_sequence(&late_system).is_err());
+
+        let ends_with_assistant = vec![Message::user("q1"), Message::assistant("a1")];
+        assert!(Message::validate_sequence(&ends_with_assistant).is_err());
+
+        let only_system = vec![Message::system("prompt")];
+        assert!(Message::validate_sequence(&only_system).is_err());
+    }
+
+    #[test]
+    fn test_validate_sequence_rejects_empty_content() {
+        let messages = vec![Message::system("prompt"), Message::user("")];
+        assert!(Message::validate_sequence(&messages).is_err());
+    }
 }
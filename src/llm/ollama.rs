@@ -1,14 +1,18 @@
-use super::provider::LLMProvider;
+use super::async_provider::{AsyncLLMProvider, BlockingBridge, LineBuffer};
+use super::provider::{ChunkHandler, LLMProvider};
 use super::Message;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::io::{BufRead, BufReader};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
 
 pub struct OllamaProvider {
     base_url: String,
     model: String,
-    client: reqwest::blocking::Client,
+    client: reqwest::Client,
     config: crate::config::OllamaConfig,
+    bridge: BlockingBridge,
 }
 
 #[derive(Debug, Serialize)]
@@ -16,6 +20,9 @@ struct OllamaRequest {
     model: String,
     messages: Vec<Message>,
     stream: bool,
+    /// `"json"` constrains the response to valid JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'static str>,
     options: OllamaOptions,
 }
 
@@ -31,6 +38,35 @@ struct OllamaResponse {
     done: bool,
 }
 
+/// A chat response body or streamed line: Ollama reports failures that happen
+/// after the response started (e.g. the model runner crashing) as `{"error": ...}`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OllamaChatLine {
+    Error { error: String },
+    Message(OllamaResponse),
+}
+
+/// A progress update streamed by Ollama while pulling a model
+#[derive(Debug, Deserialize)]
+pub struct PullProgress {
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub total: Option<u64>,
+    #[serde(default)]
+    pub completed: Option<u64>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaShowResponse {
+    /// Not reported by Ollama versions older than 0.6
+    #[serde(default)]
+    capabilities: Option<Vec<String>>,
+}
+
 #[derive(Debug, Deserialize)]
 struct OllamaTagsResponse {
     models: Vec<OllamaModel>,
@@ -42,42 +78,93 @@ struct OllamaModel {
 }
 
 impl OllamaProvider {
-    pub fn new(config: crate::config::OllamaConfig) -> Result<Self> {
-        let client = reqwest::blocking::Client::builder()
-            .timeout(std::time::Duration::from_secs(config.timeout_seconds))
-            .build()
-            .context("Failed to create HTTP client")?;
+    /// Create a provider and check that Ollama is reachable (blocking)
+    pub fn new(config: crate::config::OllamaConfig, allow_invalid_certs: bool) -> Result<Self> {
+        let provider = Self::unchecked(config, allow_invalid_certs)?;
+        provider.bridge.block_on(provider.check_connection())?;
+        Ok(provider)
+    }
+
+    /// Async counterpart of [`OllamaProvider::new`], for use inside a runtime
+    pub async fn connect(
+        config: crate::config::OllamaConfig,
+        allow_invalid_certs: bool,
+    ) -> Result<Self> {
+        let provider = Self::unchecked(config, allow_invalid_certs)?;
+        provider.check_connection().await?;
+        Ok(provider)
+    }
 
-        Self::check_connection(&client, &config.base_url)?;
+    fn unchecked(config: crate::config::OllamaConfig, allow_invalid_certs: bool) -> Result<Self> {
+        let client = crate::http::build_self_hosted_async_client(
+            Some(Duration::from_secs(config.timeout_seconds)),
+            allow_invalid_certs,
+        )?;
 
         Ok(Self {
             base_url: config.base_url.clone(),
             model: config.model.clone(),
             client,
             config,
+            bridge: BlockingBridge::default(),
         })
     }
 
-    fn check_connection(client: &reqwest::blocking::Client, base_url: &str) -> Result<()> {
-        let url = format!("{}/api/tags", base_url);
-        client
+    async fn check_connection(&self) -> Result<()> {
+        let url = format!("{}/api/tags", self.base_url);
+        self.client
             .get(&url)
             .send()
+            .await
             .context("Failed to connect to Ollama. Is Ollama running?")?;
         Ok(())
     }
 
-    pub fn list_models(&self) -> Result<Vec<String>> {
-        let url = format!("{}/api/tags", self.base_url);
-        let response: OllamaTagsResponse = self.client.get(&url).send()?.json()?;
+    /// Fail with a clear message when the model cannot read images. Servers that
+    /// don't report capabilities are given the benefit of the doubt.
+    async fn check_vision(&self) -> Result<()> {
+        let url = format!("{}/api/show", self.base_url);
+        let response = self
+            .client
+            .post(&url)
+            .json(&serde_json::json!({ "model": self.model }))
+            .send()
+            .await
+            .context("Failed to query Ollama model info")?;
+
+        if !response.status().is_success() {
+            return Ok(());
+        }
 
-        let models = response.models.iter().map(|m| m.name.clone()).collect();
+        let show: OllamaShowResponse = response.json().await?;
+        match show.capabilities {
+            Some(capabilities) if !capabilities.iter().any(|c| c == "vision") => anyhow::bail!(
+                "Ollama model '{}' does not support images. Pull a vision model \
+                 (e.g. `cyx ollama pull llava`) and set it with `cyx config set ollama_model llava`",
+                self.model
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn list_models(&self) -> Result<Vec<String>> {
+        self.bridge.block_on(async {
+            let url = format!("{}/api/tags", self.base_url);
+            let response: OllamaTagsResponse = self.client.get(&url).send().await?.json().await?;
 
-        Ok(models)
+            Ok(response.models.iter().map(|m| m.name.clone()).collect())
+        })
     }
 
-    pub fn pull_model(model: &str, base_url: &str) -> Result<()> {
-        let client = reqwest::blocking::Client::new();
+    /// Pull a model, reporting each streamed progress update to `on_progress`
+    pub fn pull_model(
+        model: &str,
+        base_url: &str,
+        allow_invalid_certs: bool,
+        mut on_progress: impl FnMut(&PullProgress),
+    ) -> Result<()> {
+        // Pulls can take far longer than the default request timeout
+        let client = crate::http::build_self_hosted_client(None, allow_invalid_certs)?;
         let url = format!("{}/api/pull", base_url);
 
         #[derive(Serialize)]
@@ -96,11 +183,28 @@ impl OllamaProvider {
             anyhow::bail!("Failed to pull model: {}", response.status());
         }
 
+        for line in BufReader::new(response).lines() {
+            let line = line.context("Failed to read pull progress")?;
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Ok(progress) = serde_json::from_str::<PullProgress>(&line) {
+                if let Some(error) = progress.error {
+                    anyhow::bail!("Failed to pull model: {}", error);
+                }
+                on_progress(&progress);
+            }
+        }
+
         Ok(())
     }
 
-    pub fn remove_model(model: &str, base_url: &str) -> Result<()> {
-        let client = reqwest::blocking::Client::new();
+    pub fn remove_model(model: &str, base_url: &str, allow_invalid_certs: bool) -> Result<()> {
+        let client = crate::http::build_self_hosted_client(
+            Some(Duration::from_secs(30)),
+            allow_invalid_certs,
+        )?;
         let url = format!("{}/api/delete", base_url);
 
         #[derive(Serialize)]
@@ -123,85 +227,142 @@ impl OllamaProvider {
     }
 }
 
-impl LLMProvider for OllamaProvider {
-    fn send_message(&self, messages: &[Message]) -> Result<String> {
+impl OllamaProvider {
+    /// A complete (non-streamed) response, in JSON mode if `json`
+    async fn complete(&self, json: bool, messages: &[Message]) -> Result<String> {
+        let response = self.post_chat(false, json, messages).await?;
+        match response.json().await? {
+            OllamaChatLine::Message(response) => Ok(response.message.content),
+            OllamaChatLine::Error { error } => anyhow::bail!("Ollama error: {}", error),
+        }
+    }
+
+    /// POST to the chat endpoint, in JSON mode if `json`
+    async fn post_chat(
+        &self,
+        stream: bool,
+        json: bool,
+        messages: &[Message],
+    ) -> Result<reqwest::Response> {
+        if messages.iter().any(|m| !m.images.is_empty()) {
+            self.check_vision().await?;
+        }
+
         let request = OllamaRequest {
             model: self.model.clone(),
             messages: messages.to_vec(),
-            stream: false,
+            stream,
+            format: json.then_some("json"),
             options: OllamaOptions {
                 temperature: 0.7,
                 num_ctx: self.config.context_window,
             },
         };
 
+        let context = if stream {
+            "Failed to send streaming request to Ollama"
+        } else {
+            "Failed to send request to Ollama"
+        };
         let url = format!("{}/api/chat", self.base_url);
         let response = self
             .client
             .post(&url)
             .json(&request)
             .send()
-            .context("Failed to send request to Ollama")?;
+            .await
+            .context(context)?;
 
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response
                 .text()
+                .await
                 .unwrap_or_else(|_| "Unknown error".to_string());
-            anyhow::bail!("Ollama API error ({}): {}", status, error_text);
+            anyhow::bail!(chat_error(status, &error_text, &self.model));
         }
 
-        let ollama_response: OllamaResponse = response.json()?;
-        Ok(ollama_response.message.content)
+        Ok(response)
     }
 
-    fn send_message_stream(
-        &self,
-        messages: &[Message],
-        mut on_chunk: Box<dyn FnMut(&str)>,
-    ) -> Result<String> {
-        let request = OllamaRequest {
-            model: self.model.clone(),
-            messages: messages.to_vec(),
-            stream: true,
-            options: OllamaOptions {
-                temperature: 0.7,
-                num_ctx: self.config.context_window,
-            },
-        };
-
-        let url = format!("{}/api/chat", self.base_url);
-        let response = self
-            .client
-            .post(&url)
-            .json(&request)
-            .send()
-            .context("Failed to send streaming request to Ollama")?;
-
-        let mut full_response = String::new();
-        let reader = BufReader::new(response);
-
-        for line in reader.lines() {
-            let line = line.context("Failed to read stream line")?;
-            if line.is_empty() {
-                continue;
-            }
+    /// Handle one NDJSON line, returning false once Ollama reports `done`. An
+    /// error line fails the request; lines that aren't JSON are skipped.
+    fn handle_stream_line(
+        line: &str,
+        chunks: &UnboundedSender<String>,
+        full: &mut String,
+    ) -> Result<bool> {
+        if line.is_empty() {
+            return Ok(true);
+        }
 
-            if let Ok(chunk_response) = serde_json::from_str::<OllamaResponse>(&line) {
+        match serde_json::from_str::<OllamaChatLine>(line) {
+            Ok(OllamaChatLine::Message(chunk_response)) => {
                 let content = &chunk_response.message.content;
                 if !content.is_empty() {
-                    on_chunk(content);
-                    full_response.push_str(content);
+                    let _ = chunks.send(content.clone());
+                    full.push_str(content);
                 }
+                Ok(!chunk_response.done)
+            }
+            Ok(OllamaChatLine::Error { error }) => {
+                anyhow::bail!("Ollama error mid-stream: {}", error)
+            }
+            Err(_) => Ok(true),
+        }
+    }
+}
 
-                if chunk_response.done {
-                    break;
+impl AsyncLLMProvider for OllamaProvider {
+    async fn send_message(&self, messages: &[Message]) -> Result<String> {
+        self.complete(false, messages).await
+    }
+
+    async fn send_message_stream(
+        &self,
+        messages: &[Message],
+        chunks: UnboundedSender<String>,
+    ) -> Result<String> {
+        let mut response = self.post_chat(true, false, messages).await?;
+        let mut full_response = String::new();
+        let mut lines = LineBuffer::default();
+
+        while let Some(bytes) = response
+            .chunk()
+            .await
+            .context("Failed to read stream chunk")?
+        {
+            for line in lines.push(&bytes) {
+                if !Self::handle_stream_line(&line, &chunks, &mut full_response)? {
+                    return Ok(full_response);
                 }
             }
         }
 
+        if let Some(line) = lines.finish() {
+            Self::handle_stream_line(&line, &chunks, &mut full_response)?;
+        }
+
         Ok(full_response)
     }
+}
+
+impl LLMProvider for OllamaProvider {
+    fn send_message(&self, messages: &[Message]) -> Result<String> {
+        self.bridge
+            .block_on(AsyncLLMProvider::send_message(self, messages))
+    }
+
+    fn send_message_stream(&self, messages: &[Message], on_chunk: ChunkHandler) -> Result<String> {
+        self.bridge.stream(
+            |chunks| AsyncLLMProvider::send_message_stream(self, messages, chunks),
+            on_chunk,
+        )
+    }
+
+    fn send_message_json(&self, messages: &[Message]) -> Result<String> {
+        self.bridge.block_on(self.complete(true, messages))
+    }
 
     fn name(&self) -> &str {
         "Ollama"
@@ -214,4 +375,96 @@ impl LLMProvider for OllamaProvider {
     fn searches_web(&self) -> bool {
         false
     }
+
+    fn prompt_budget(&self) -> usize {
+        // Ollama truncates from the front past num_ctx; keep a quarter for the response
+        self.config.context_window - self.config.context_window / 4
+    }
+
+    fn supports_images(&self) -> bool {
+        true // Vision models only; checked against the model before sending
+    }
+
+    fn test_connection_cheap(&self) -> Result<()> {
+        // /api/tags answers without loading a model, and tells us whether ours is pulled
+        let models = self.list_models()?;
+        if !models.iter().any(|m| is_same_model(m, &self.model)) {
+            anyhow::bail!(
+                "Ollama model '{}' is not installed. Download it with `cyx ollama pull {}`",
+                self.model,
+                self.model
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Describe a failed /api/chat request. Ollama answers 404 with
+/// `{"error": "model \"x\" not found, try pulling it first"}` when the model
+/// isn't pulled, which deserves a pointer to `cyx ollama pull`
+fn chat_error(status: reqwest::StatusCode, body: &str, model: &str) -> String {
+    let message = match serde_json::from_str::<OllamaChatLine>(body) {
+        Ok(OllamaChatLine::Error { error }) => error,
+        _ => body.trim().to_string(),
+    };
+
+    if status == reqwest::StatusCode::NOT_FOUND {
+        format!(
+            "Ollama model '{}' is not installed ({}). Download it with `cyx ollama pull {}`",
+            model, message, model
+        )
+    } else {
+        format!("Ollama API error ({}): {}", status, message)
+    }
+}
+
+/// Whether an installed model name refers to `model`; Ollama lists untagged
+/// models with an explicit `:latest`
+pub(crate) fn is_same_model(installed: &str, model: &str) -> bool {
+    installed == model || (!model.contains(':') && installed == format!("{}:latest", model))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_error_line_surfaces() {
+        let (chunks, mut received) = tokio::sync::mpsc::unbounded_channel();
+        let mut full = String::new();
+
+        let line = r#"{"message":{"role":"assistant","content":"nmap -sS"},"done":false}"#;
+        assert!(OllamaProvider::handle_stream_line(line, &chunks, &mut full).unwrap());
+        assert!(OllamaProvider::handle_stream_line("not json", &chunks, &mut full).unwrap());
+
+        let line = r#"{"error":"model runner has unexpectedly stopped"}"#;
+        let error = OllamaProvider::handle_stream_line(line, &chunks, &mut full).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("model runner has unexpectedly stopped"));
+
+        assert_eq!(full, "nmap -sS");
+        assert_eq!(received.try_recv().unwrap(), "nmap -sS");
+
+        let line = r#"{"message":{"role":"assistant","content":""},"done":true}"#;
+        assert!(!OllamaProvider::handle_stream_line(line, &chunks, &mut full).unwrap());
+    }
+
+    #[test]
+    fn test_chat_error_suggests_pull_for_missing_model() {
+        let body = r#"{"error":"model \"llama3.2\" not found, try pulling it first"}"#;
+        let message = chat_error(reqwest::StatusCode::NOT_FOUND, body, "llama3.2");
+        assert!(message.contains("cyx ollama pull llama3.2"));
+        assert!(message.contains("not found, try pulling it first"));
+
+        let message = chat_error(
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+            "boom\n",
+            "llama3.2",
+        );
+        assert_eq!(
+            message,
+            "Ollama API error (500 Internal Server Error): boom"
+        );
+    }
 }
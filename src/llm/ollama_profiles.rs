@@ -0,0 +1,71 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+// Curated models offered by the setup wizard and `cyx ollama recommend`
+const PROFILES_JSON: &str = include_str!("../../data/ollama_profiles.json");
+
+/// A recommended Ollama model
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ModelProfile {
+    pub name: String,
+    /// Approximate download size, for display
+    pub size: String,
+    pub description: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfilesData {
+    profiles: Vec<ModelProfile>,
+}
+
+/// The recommended models, in display order
+pub fn model_profiles() -> Result<Vec<ModelProfile>> {
+    let data: ProfilesData =
+        serde_json::from_str(PROFILES_JSON).context("Failed to parse Ollama profiles JSON")?;
+    Ok(data.profiles)
+}
+
+impl ModelProfile {
+    /// Whether `installed` (a name from `ollama list`) is this model
+    pub fn matches(&self, installed: &str) -> bool {
+        super::ollama::is_same_model(installed, &self.name)
+    }
+}
+
+impl std::fmt::Display for ModelProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}) - {}", self.name, self.size, self.description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_profiles_parse() {
+        let profiles = model_profiles().unwrap();
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "llama3.2:3b",
+                "mistral:7b-instruct",
+                "mixtral:8x7b",
+                "codellama:7b-instruct"
+            ]
+        );
+        assert!(profiles.iter().all(|p| !p.description.is_empty()));
+    }
+
+    #[test]
+    fn test_matches_installed_name() {
+        let profile = ModelProfile {
+            name: "mistral".to_string(),
+            size: "4.1 GB".to_string(),
+            description: "test".to_string(),
+        };
+        assert!(profile.matches("mistral:latest"));
+        assert!(!profile.matches("mistral:7b-instruct"));
+    }
+}
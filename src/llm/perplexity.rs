@@ -1,18 +1,36 @@
-use super::{provider::LLMProvider, Message};
+use super::async_provider::{read_event_stream, AsyncLLMProvider, BlockingBridge};
+use super::chat_format::{chat_messages, ChatMessage};
+use super::max_tokens::{clamp_max_tokens, reduced_max_tokens};
+use super::{is_auth_failure, provider::LLMProvider, ChunkHandler, Message};
+use crate::error::CyxError;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Context window of sonar-pro, in tokens
+const PERPLEXITY_CONTEXT_WINDOW: usize = 200_000;
+
+/// Response length requested from the API (`max_tokens`)
+const PERPLEXITY_MAX_TOKENS: u32 = 8000;
+
+const PERPLEXITY_MODEL: &str = "sonar-pro";
 
 const PERPLEXITY_API_URL: &str = "https://api.perplexity.ai/chat/completions";
 
 pub struct PerplexityProvider {
     api_key: String,
-    client: reqwest::blocking::Client,
+    client: reqwest::Client,
+    bridge: BlockingBridge,
+    /// Citations returned with the last response
+    citations: Mutex<Vec<String>>,
 }
 
 #[derive(Debug, Serialize)]
-struct PerplexityRequest {
+struct PerplexityRequest<'a> {
     model: String,
-    messages: Vec<Message>,
+    messages: Vec<ChatMessage<'a>>,
     temperature: f32,
     max_tokens: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -22,6 +40,9 @@ struct PerplexityRequest {
 #[derive(Debug, Deserialize)]
 struct PerplexityResponse {
     choices: Vec<Choice>,
+    /// URLs of the search results the answer is based on
+    #[serde(default)]
+    citations: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -40,45 +61,88 @@ struct Delta {
 
 impl PerplexityProvider {
     pub fn new(api_key: String) -> Result<Self> {
-        let client = reqwest::blocking::Client::builder()
-            .timeout(std::time::Duration::from_secs(120))
-            .build()
-            .context("Failed to create HTTP client")?;
+        Self::with_timeout(api_key, Duration::from_secs(120))
+    }
+
+    /// Client whose requests (including reading a streamed body) give up after `timeout`
+    pub fn with_timeout(api_key: String, timeout: Duration) -> Result<Self> {
+        let client = crate::http::build_async_client(Some(timeout), None)?;
 
-        Ok(Self { api_key, client })
+        Ok(Self {
+            api_key,
+            client,
+            bridge: BlockingBridge::default(),
+            citations: Mutex::new(Vec::new()),
+        })
     }
-}
 
-impl LLMProvider for PerplexityProvider {
-    fn send_message(&self, messages: &[Message]) -> Result<String> {
-        let request = PerplexityRequest {
-            model: "sonar-pro".to_string(),
-            messages: messages.to_vec(),
+    fn set_citations(&self, citations: Vec<String>) {
+        *self.citations.lock().unwrap_or_else(|e| e.into_inner()) = citations;
+    }
+
+    /// POST a chat completion. A 400 rejecting `max_tokens` as too large is retried
+    /// once with the limit from the error message.
+    async fn post(&self, stream: bool, messages: &[Message]) -> Result<reqwest::Response> {
+        self.set_citations(Vec::new());
+
+        let mut request = PerplexityRequest {
+            model: PERPLEXITY_MODEL.to_string(),
+            messages: chat_messages(messages),
             temperature: 0.7,
-            max_tokens: 8000,
-            stream: None,
+            max_tokens: clamp_max_tokens(PERPLEXITY_MODEL, PERPLEXITY_MAX_TOKENS),
+            stream: stream.then_some(true),
         };
 
-        let response = self
-            .client
-            .post(PERPLEXITY_API_URL)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .context("Failed to send request to Perplexity API")?;
+        let context = if stream {
+            "Failed to send streaming request to Perplexity API"
+        } else {
+            "Failed to send request to Perplexity API"
+        };
+        let mut retried = false;
+        loop {
+            let response = self
+                .client
+                .post(PERPLEXITY_API_URL)
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .header("Content-Type", "application/json")
+                .json(&request)
+                .send()
+                .await
+                .context(context)?;
 
-        if !response.status().is_success() {
             let status = response.status();
+            if status.is_success() {
+                return Ok(response);
+            }
+
             let error_text = response
                 .text()
+                .await
                 .unwrap_or_else(|_| "Unknown error".to_string());
-            anyhow::bail!("Perplexity API error ({}): {}", status, error_text);
+            if status == reqwest::StatusCode::BAD_REQUEST && !retried {
+                if let Some(reduced) = reduced_max_tokens(&error_text, request.max_tokens) {
+                    request.max_tokens = reduced;
+                    retried = true;
+                    continue;
+                }
+            }
+            let message = format!("Perplexity API error ({}): {}", status, error_text);
+            if is_auth_failure(status) {
+                return Err(CyxError::Auth(message).into());
+            }
+            anyhow::bail!(message);
         }
+    }
 
-        let perplexity_response: PerplexityResponse = response
-            .json()
-            .context("Failed to parse Perplexity response")?;
+    /// Text of a complete (non-streamed) chat completion body, recording its citations
+    fn parse_completion(&self, body: &str) -> Result<String> {
+        let perplexity_response: PerplexityResponse =
+            serde_json::from_str(body).with_context(|| {
+                format!(
+                    "Failed to parse Perplexity response: {}",
+                    body.chars().take(200).collect::<String>()
+                )
+            })?;
 
         let content = perplexity_response
             .choices
@@ -86,75 +150,85 @@ impl LLMProvider for PerplexityProvider {
             .and_then(|c| c.message.as_ref())
             .map(|m| m.content.clone())
             .ok_or_else(|| anyhow::anyhow!("No response from Perplexity"))?;
+        self.set_citations(perplexity_response.citations);
 
         Ok(content)
     }
 
-    fn send_message_stream(
+    /// Handle one SSE line, returning false at the end of the stream
+    fn handle_stream_line(
         &self,
-        messages: &[Message],
-        mut on_chunk: Box<dyn FnMut(&str)>,
-    ) -> Result<String> {
-        use std::io::{BufRead, BufReader};
-
-        let request = PerplexityRequest {
-            model: "sonar-pro".to_string(),
-            messages: messages.to_vec(),
-            temperature: 0.7,
-            max_tokens: 8000,
-            stream: Some(true),
+        line: &str,
+        chunks: &UnboundedSender<String>,
+        full: &mut String,
+    ) -> bool {
+        // Skip empty lines and non-data lines
+        let Some(data) = line.strip_prefix("data: ") else {
+            return true;
         };
 
-        let response = self
-            .client
-            .post(PERPLEXITY_API_URL)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .context("Failed to send streaming request to Perplexity API")?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            anyhow::bail!("Perplexity API error ({}): {}", status, error_text);
+        // Check for end of stream
+        if data == "[DONE]" {
+            return false;
         }
 
-        let mut full_response = String::new();
-        let reader = BufReader::new(response);
-
-        for line in reader.lines() {
-            let line = line.context("Failed to read stream line")?;
-
-            // Skip empty lines and non-data lines
-            if line.is_empty() || !line.starts_with("data: ") {
-                continue;
+        // Parse the SSE data
+        if let Ok(chunk_response) = serde_json::from_str::<PerplexityResponse>(data) {
+            // Every chunk repeats the citations; keep the latest
+            if !chunk_response.citations.is_empty() {
+                self.set_citations(chunk_response.citations);
             }
-
-            // Extract the JSON part
-            let data = &line[6..]; // Skip "data: " prefix
-
-            // Check for end of stream
-            if data == "[DONE]" {
-                break;
-            }
-
-            // Parse the SSE data
-            if let Ok(chunk_response) = serde_json::from_str::<PerplexityResponse>(data) {
-                if let Some(choice) = chunk_response.choices.first() {
-                    if let Some(delta) = &choice.delta {
-                        if let Some(content) = &delta.content {
-                            on_chunk(content);
-                            full_response.push_str(content);
-                        }
+            if let Some(choice) = chunk_response.choices.first() {
+                if let Some(delta) = &choice.delta {
+                    if let Some(content) = &delta.content {
+                        let _ = chunks.send(content.clone());
+                        full.push_str(content);
                     }
                 }
             }
         }
 
-        Ok(full_response)
+        true
+    }
+}
+
+impl AsyncLLMProvider for PerplexityProvider {
+    async fn send_message(&self, messages: &[Message]) -> Result<String> {
+        let response = self.post(false, messages).await?;
+        let body = response
+            .text()
+            .await
+            .context("Failed to read Perplexity response")?;
+        self.parse_completion(&body)
+    }
+
+    async fn send_message_stream(
+        &self,
+        messages: &[Message],
+        chunks: UnboundedSender<String>,
+    ) -> Result<String> {
+        let response = self.post(true, messages).await?;
+        read_event_stream(
+            response,
+            &chunks,
+            |line, full| self.handle_stream_line(line, &chunks, full),
+            |body| self.parse_completion(body),
+        )
+        .await
+    }
+}
+
+impl LLMProvider for PerplexityProvider {
+    fn send_message(&self, messages: &[Message]) -> Result<String> {
+        self.bridge
+            .block_on(AsyncLLMProvider::send_message(self, messages))
+    }
+
+    fn send_message_stream(&self, messages: &[Message], on_chunk: ChunkHandler) -> Result<String> {
+        self.bridge.stream(
+            |chunks| AsyncLLMProvider::send_message_stream(self, messages, chunks),
+            on_chunk,
+        )
     }
 
     fn name(&self) -> &str {
@@ -162,10 +236,48 @@ impl LLMProvider for PerplexityProvider {
     }
 
     fn model(&self) -> &str {
-        "sonar-pro"
+        PERPLEXITY_MODEL
     }
 
     fn searches_web(&self) -> bool {
         true // Perplexity sonar-pro has built-in web search
     }
+
+    fn prompt_budget(&self) -> usize {
+        PERPLEXITY_CONTEXT_WINDOW - PERPLEXITY_MAX_TOKENS as usize
+    }
+
+    fn supports_images(&self) -> bool {
+        true
+    }
+
+    fn citations(&self) -> Vec<String> {
+        self.citations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_chunks_record_citations() {
+        let provider = PerplexityProvider::new("key".to_string()).unwrap();
+        let (chunks, mut received) = tokio::sync::mpsc::unbounded_channel();
+        let mut full = String::new();
+
+        let line = r#"data: {"choices":[{"delta":{"content":"nmap -sS"}}],"citations":["https://nmap.org/book/"]}"#;
+        assert!(provider.handle_stream_line(line, &chunks, &mut full));
+        assert!(!provider.handle_stream_line("data: [DONE]", &chunks, &mut full));
+
+        assert_eq!(full, "nmap -sS");
+        assert_eq!(received.try_recv().unwrap(), "nmap -sS");
+        assert_eq!(
+            LLMProvider::citations(&provider),
+            vec!["https://nmap.org/book/"]
+        );
+    }
 }
@@ -1,18 +1,30 @@
 use super::Message;
+use crate::error::CyxError;
 use anyhow::Result;
+use std::ops::ControlFlow;
+
+/// Called with each streamed text chunk; `ControlFlow::Break` stops the stream
+pub type ChunkHandler = Box<dyn FnMut(&str) -> ControlFlow<()>>;
 
 pub trait LLMProvider: Send + Sync {
     /// Send a message to the LLM and get a response
     fn send_message(&self, messages: &[Message]) -> Result<String>;
 
     /// Send a message with streaming support
-    /// The callback is called with each text chunk as it arrives
-    /// Returns the complete response
-    fn send_message_stream(
-        &self,
-        messages: &[Message],
-        on_chunk: Box<dyn FnMut(&str)>,
-    ) -> Result<String>;
+    /// The callback is called with each text chunk as it arrives; returning
+    /// `ControlFlow::Break` stops reading and drops the connection.
+    /// Returns the complete response, or the text received before stopping
+    fn send_message_stream(&self, messages: &[Message], on_chunk: ChunkHandler) -> Result<String>;
+
+    /// Send a message in the API's JSON mode, so the response is a single JSON
+    /// object (`--json-response`). Providers without one fail.
+    fn send_message_json(&self, _messages: &[Message]) -> Result<String> {
+        Err(CyxError::Config(format!(
+            "{} has no JSON response mode; --json-response works with Groq and Ollama",
+            self.name()
+        ))
+        .into())
+    }
 
     /// Get the provider name
     fn name(&self) -> &str;
@@ -22,4 +34,25 @@ pub trait LLMProvider: Send + Sync {
 
     /// Check if this provider performs web searches
     fn searches_web(&self) -> bool;
+
+    /// Tokens available for the prompt: the model's context window minus room
+    /// reserved for the response
+    fn prompt_budget(&self) -> usize;
+
+    /// Whether messages may carry images (`--image`)
+    fn supports_images(&self) -> bool {
+        false
+    }
+
+    /// Source URLs the API returned alongside the last response, for providers
+    /// that report them (Perplexity). Preferred over a model-written `[SOURCES]` list.
+    fn citations(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Check the connection and credentials as cheaply as possible. Providers
+    /// with a models endpoint query it; the default sends a one-word completion.
+    fn test_connection_cheap(&self) -> Result<()> {
+        self.send_message(&[Message::user("test")]).map(|_| ())
+    }
 }
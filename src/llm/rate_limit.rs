@@ -0,0 +1,167 @@
+use super::{ChunkHandler, LLMProvider, Message};
+use anyhow::Result;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Lowest sustained rate: one request a day. Slower rates are clamped to it,
+/// since their interval would overflow a `Duration`.
+pub const MIN_RPS: f64 = 1.0 / 86_400.0;
+
+/// Token bucket that paces requests to a sustained rate, sleeping (never
+/// failing) when the bucket is empty
+pub struct RateLimiter {
+    /// Time to refill one token
+    interval: Duration,
+    capacity: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    /// Allow `rps` requests per second on average (at least `MIN_RPS`), with
+    /// bursts of up to `burst`
+    pub fn new(rps: f64, burst: u32) -> Self {
+        let capacity = f64::from(burst.max(1));
+        Self {
+            interval: Duration::from_secs_f64(1.0 / rps.max(MIN_RPS)),
+            capacity,
+            bucket: Mutex::new(Bucket {
+                tokens: capacity,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take a token, sleeping until one is available
+    pub fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Take a token at `now` and return how long the caller must wait before using it.
+    /// The token may be borrowed ahead, so concurrent callers queue up in order.
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() / self.interval.as_secs_f64())
+            .min(self.capacity);
+        bucket.refilled_at = now;
+
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            self.interval.mul_f64(-bucket.tokens)
+        }
+    }
+}
+
+/// Wraps a provider so every request first takes a token from a `RateLimiter`
+pub struct RateLimited {
+    inner: Box<dyn LLMProvider>,
+    limiter: RateLimiter,
+}
+
+impl RateLimited {
+    pub fn new(inner: Box<dyn LLMProvider>, limiter: RateLimiter) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+impl LLMProvider for RateLimited {
+    fn send_message(&self, messages: &[Message]) -> Result<String> {
+        self.limiter.acquire();
+        self.inner.send_message(messages)
+    }
+
+    fn send_message_stream(&self, messages: &[Message], on_chunk: ChunkHandler) -> Result<String> {
+        self.limiter.acquire();
+        self.inner.send_message_stream(messages, on_chunk)
+    }
+
+    fn send_message_json(&self, messages: &[Message]) -> Result<String> {
+        self.limiter.acquire();
+        self.inner.send_message_json(messages)
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn searches_web(&self) -> bool {
+        self.inner.searches_web()
+    }
+
+    fn prompt_budget(&self) -> usize {
+        self.inner.prompt_budget()
+    }
+
+    fn supports_images(&self) -> bool {
+        self.inner.supports_images()
+    }
+
+    fn citations(&self) -> Vec<String> {
+        self.inner.citations()
+    }
+
+    fn test_connection_cheap(&self) -> Result<()> {
+        self.limiter.acquire();
+        self.inner.test_connection_cheap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_respect_minimum_spacing() {
+        let limiter = RateLimiter::new(20.0, 1);
+        let mut now = Instant::now();
+        assert_eq!(limiter.reserve(now), Duration::ZERO);
+
+        // Each request sent as soon as allowed waits out the full spacing
+        for _ in 0..4 {
+            let wait = limiter.reserve(now);
+            assert_eq!(wait, Duration::from_millis(50));
+            now += wait;
+        }
+    }
+
+    #[test]
+    fn test_tiny_rates_are_clamped() {
+        let limiter = RateLimiter::new(1e-20, 1);
+        let now = Instant::now();
+        assert_eq!(limiter.reserve(now), Duration::ZERO);
+        assert_eq!(limiter.reserve(now), Duration::from_secs(86_400));
+    }
+
+    #[test]
+    fn test_burst_then_steady_rate() {
+        let limiter = RateLimiter::new(2.0, 3);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(limiter.reserve(now), Duration::ZERO);
+        }
+        assert_eq!(limiter.reserve(now), Duration::from_millis(500));
+        assert_eq!(limiter.reserve(now), Duration::from_millis(1000));
+
+        // Two seconds later four tokens have refilled, paying back the two borrowed
+        let later = now + Duration::from_secs(2);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert_eq!(limiter.reserve(later), Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,248 @@
+//! Removal of the `<think>…</think>` blocks reasoning models write before their
+//! answer, both from complete responses and from a stream of chunks
+
+use super::{ChunkHandler, LLMProvider, Message};
+use anyhow::Result;
+use std::cell::{Cell, RefCell};
+use std::ops::ControlFlow;
+use std::rc::Rc;
+
+/// Opening and closing tags of the blocks that are dropped
+const THINKING_TAGS: [(&str, &str); 3] = [
+    ("<think>", "</think>"),
+    ("<thinking>", "</thinking>"),
+    ("<reasoning>", "</reasoning>"),
+];
+
+/// Drops thinking blocks from streamed text. A tag may be split across chunks, so
+/// text that could still turn out to be part of one is held back until the next
+/// chunk (or `finish`) resolves it.
+#[derive(Debug, Default)]
+pub struct ThinkingFilter {
+    /// Text received but not yet emitted or dropped
+    pending: String,
+    /// Closing tag of the block being dropped
+    closing: Option<&'static str>,
+    /// Drop the whitespace that follows a closed block
+    trim_start: bool,
+}
+
+impl ThinkingFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a chunk and get back the text that is now known to be outside any block
+    pub fn push(&mut self, chunk: &str) -> String {
+        self.pending.push_str(chunk);
+        let mut visible = String::new();
+
+        loop {
+            if let Some(closing) = self.closing {
+                match self.pending.find(closing) {
+                    Some(at) => {
+                        self.pending.drain(..at + closing.len());
+                        self.closing = None;
+                        self.trim_start = true;
+                    }
+                    None => {
+                        let keep = partial_tag_start(&self.pending, &[closing]);
+                        self.pending.drain(..keep);
+                        break;
+                    }
+                }
+                continue;
+            }
+
+            if self.trim_start {
+                let trimmed = self.pending.len() - self.pending.trim_start().len();
+                self.pending.drain(..trimmed);
+                if self.pending.is_empty() {
+                    break;
+                }
+                self.trim_start = false;
+            }
+
+            let opening = THINKING_TAGS
+                .iter()
+                .filter_map(|&(open, close)| Some((self.pending.find(open)?, open, close)))
+                .min_by_key(|&(at, _, _)| at);
+
+            match opening {
+                Some((at, open, close)) => {
+                    visible.push_str(&self.pending[..at]);
+                    self.pending.drain(..at + open.len());
+                    self.closing = Some(close);
+                }
+                None => {
+                    let opens: Vec<&str> = THINKING_TAGS.iter().map(|&(open, _)| open).collect();
+                    let keep = partial_tag_start(&self.pending, &opens);
+                    visible.push_str(&self.pending[..keep]);
+                    self.pending.drain(..keep);
+                    break;
+                }
+            }
+        }
+
+        visible
+    }
+
+    /// Text still held back at the end of the stream. A block that was never closed
+    /// is dropped along with everything after its opening tag.
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        if self.closing.take().is_some() {
+            return String::new();
+        }
+        if std::mem::take(&mut self.trim_start) {
+            return rest.trim_start().to_string();
+        }
+        rest
+    }
+}
+
+/// Where a suffix of `text` that is the start of one of `tags` begins, or
+/// `text.len()` when no tag could begin there
+fn partial_tag_start(text: &str, tags: &[&str]) -> usize {
+    match text.rfind('<') {
+        Some(at) if tags.iter().any(|tag| tag.starts_with(&text[at..])) => at,
+        _ => text.len(),
+    }
+}
+
+/// `text` with its thinking blocks removed
+pub fn strip_thinking(text: &str) -> String {
+    let mut filter = ThinkingFilter::new();
+    let mut cleaned = filter.push(text);
+    cleaned.push_str(&filter.finish());
+    cleaned
+}
+
+/// Wraps a provider so responses, streamed or not, arrive without thinking blocks
+pub struct StripThinking {
+    inner: Box<dyn LLMProvider>,
+}
+
+impl StripThinking {
+    pub fn new(inner: Box<dyn LLMProvider>) -> Self {
+        Self { inner }
+    }
+}
+
+impl LLMProvider for StripThinking {
+    fn send_message(&self, messages: &[Message]) -> Result<String> {
+        Ok(strip_thinking(&self.inner.send_message(messages)?))
+    }
+
+    fn send_message_stream(&self, messages: &[Message], on_chunk: ChunkHandler) -> Result<String> {
+        let on_chunk = Rc::new(RefCell::new(on_chunk));
+        let filter = Rc::new(RefCell::new(ThinkingFilter::new()));
+        let stopped = Rc::new(Cell::new(false));
+
+        let filtered: ChunkHandler = {
+            let (on_chunk, filter, stopped) = (on_chunk.clone(), filter.clone(), stopped.clone());
+            Box::new(move |chunk| {
+                let visible = filter.borrow_mut().push(chunk);
+                if visible.is_empty() {
+                    return ControlFlow::Continue(());
+                }
+                let flow = (on_chunk.borrow_mut())(&visible);
+                stopped.set(flow.is_break());
+                flow
+            })
+        };
+        let response = self.inner.send_message_stream(messages, filtered)?;
+
+        let tail = filter.borrow_mut().finish();
+        if !tail.is_empty() && !stopped.get() {
+            let _ = (on_chunk.borrow_mut())(&tail);
+        }
+        Ok(strip_thinking(&response))
+    }
+
+    fn send_message_json(&self, messages: &[Message]) -> Result<String> {
+        Ok(strip_thinking(&self.inner.send_message_json(messages)?))
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn searches_web(&self) -> bool {
+        self.inner.searches_web()
+    }
+
+    fn prompt_budget(&self) -> usize {
+        self.inner.prompt_budget()
+    }
+
+    fn supports_images(&self) -> bool {
+        self.inner.supports_images()
+    }
+
+    fn citations(&self) -> Vec<String> {
+        self.inner.citations()
+    }
+
+    fn test_connection_cheap(&self) -> Result<()> {
+        self.inner.test_connection_cheap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter_chunks(chunks: &[&str]) -> String {
+        let mut filter = ThinkingFilter::new();
+        let mut out: String = chunks.iter().map(|chunk| filter.push(chunk)).collect();
+        out.push_str(&filter.finish());
+        out
+    }
+
+    #[test]
+    fn test_strip_thinking_block() {
+        assert_eq!(
+            strip_thinking("<think>\nUser wants a SYN scan.\n</think>\n\nnmap -sS target"),
+            "nmap -sS target"
+        );
+        assert_eq!(
+            strip_thinking("<reasoning>hmm</reasoning>answer <b>bold</b>"),
+            "answer <b>bold</b>"
+        );
+        assert_eq!(strip_thinking("no tags, a < b"), "no tags, a < b");
+    }
+
+    #[test]
+    fn test_tags_split_across_chunks() {
+        assert_eq!(
+            filter_chunks(&[
+                "<th",
+                "ink>plan the",
+                " scan</th",
+                "ink>",
+                "\n",
+                "nmap -sS <",
+                "target>"
+            ]),
+            "nmap -sS <target>"
+        );
+        assert_eq!(
+            filter_chunks(&["use ", "<", "thinking>x</", "thinking> nmap"]),
+            "use nmap"
+        );
+    }
+
+    #[test]
+    fn test_partial_tag_is_held_back() {
+        let mut filter = ThinkingFilter::new();
+        assert_eq!(filter.push("nmap <thi"), "nmap ");
+        assert_eq!(filter.push("s>"), "<this>");
+        assert_eq!(filter.push("<think>never closed"), "");
+        assert_eq!(filter.finish(), "");
+    }
+}
@@ -0,0 +1,174 @@
+use super::{ChunkHandler, LLMProvider, Message};
+use crate::error::CyxError;
+use anyhow::Result;
+use std::cell::Cell;
+use std::ops::ControlFlow;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Wraps a provider so a request that takes longer than `limit` fails with
+/// `CyxError::Timeout`. A stream is stopped at the first chunk past the limit; a
+/// stalled connection is left to the HTTP client, which should use the same limit.
+pub struct TimeLimited {
+    inner: Box<dyn LLMProvider>,
+    limit: Duration,
+}
+
+impl TimeLimited {
+    pub fn new(inner: Box<dyn LLMProvider>, limit: Duration) -> Self {
+        Self { inner, limit }
+    }
+
+    /// `error`, replaced by `CyxError::Timeout` when the HTTP client timed out
+    fn classify(&self, error: anyhow::Error) -> anyhow::Error {
+        let timed_out = error
+            .chain()
+            .filter_map(|cause| cause.downcast_ref::<reqwest::Error>())
+            .any(reqwest::Error::is_timeout);
+        if timed_out {
+            CyxError::Timeout(self.limit).into()
+        } else {
+            error
+        }
+    }
+}
+
+impl LLMProvider for TimeLimited {
+    fn send_message(&self, messages: &[Message]) -> Result<String> {
+        self.inner
+            .send_message(messages)
+            .map_err(|e| self.classify(e))
+    }
+
+    fn send_message_stream(
+        &self,
+        messages: &[Message],
+        mut on_chunk: ChunkHandler,
+    ) -> Result<String> {
+        let started = Instant::now();
+        let limit = self.limit;
+        let expired = Rc::new(Cell::new(false));
+
+        let limited: ChunkHandler = {
+            let expired = expired.clone();
+            Box::new(move |chunk| {
+                if started.elapsed() > limit {
+                    expired.set(true);
+                    return ControlFlow::Break(());
+                }
+                on_chunk(chunk)
+            })
+        };
+
+        let response = self
+            .inner
+            .send_message_stream(messages, limited)
+            .map_err(|e| self.classify(e))?;
+        if expired.get() {
+            return Err(CyxError::Timeout(limit).into());
+        }
+        Ok(response)
+    }
+
+    fn send_message_json(&self, messages: &[Message]) -> Result<String> {
+        self.inner
+            .send_message_json(messages)
+            .map_err(|e| self.classify(e))
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn searches_web(&self) -> bool {
+        self.inner.searches_web()
+    }
+
+    fn prompt_budget(&self) -> usize {
+        self.inner.prompt_budget()
+    }
+
+    fn supports_images(&self) -> bool {
+        self.inner.supports_images()
+    }
+
+    fn citations(&self) -> Vec<String> {
+        self.inner.citations()
+    }
+
+    fn test_connection_cheap(&self) -> Result<()> {
+        self.inner
+            .test_connection_cheap()
+            .map_err(|e| self.classify(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{exit_code, CyxError};
+
+    /// Streams a chunk every 20ms
+    struct SlowProvider;
+
+    impl LLMProvider for SlowProvider {
+        fn send_message(&self, _messages: &[Message]) -> Result<String> {
+            Ok("done".to_string())
+        }
+
+        fn send_message_stream(
+            &self,
+            _messages: &[Message],
+            mut on_chunk: ChunkHandler,
+        ) -> Result<String> {
+            let mut received = String::new();
+            for _ in 0..50 {
+                std::thread::sleep(Duration::from_millis(20));
+                if on_chunk("x").is_break() {
+                    break;
+                }
+                received.push('x');
+            }
+            Ok(received)
+        }
+
+        fn name(&self) -> &str {
+            "Slow"
+        }
+
+        fn model(&self) -> &str {
+            "slow"
+        }
+
+        fn searches_web(&self) -> bool {
+            false
+        }
+
+        fn prompt_budget(&self) -> usize {
+            1_000
+        }
+    }
+
+    #[test]
+    fn test_stream_stops_at_limit() {
+        let provider = TimeLimited::new(Box::new(SlowProvider), Duration::from_millis(100));
+        let error = provider
+            .send_message_stream(&[], Box::new(|_| ControlFlow::Continue(())))
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CyxError>(),
+            Some(CyxError::Timeout(_))
+        ));
+        assert_eq!(exit_code(&error), exit_code::NETWORK);
+
+        let provider = TimeLimited::new(Box::new(SlowProvider), Duration::from_secs(30));
+        let response = provider
+            .send_message_stream(&[], Box::new(|_| ControlFlow::Continue(())))
+            .unwrap();
+        assert_eq!(response.len(), 50);
+    }
+}
@@ -1,22 +1,37 @@
 use clap::Parser;
-use cyx::cli::{Cli, CliContext, CommandHandler};
+use cyx::cli::{Cli, CliContext, CommandHandler, Commands, OutputFormat};
 use cyx::ui::Display;
 
 fn main() {
     // Parse command line arguments
     let cli = Cli::parse();
 
+    // Leave the terminal clean if a query is interrupted or panics mid-render
+    cyx::ui::terminal::install_handlers();
+
     // Create CLI context from flags
-    let context = CliContext::new(cli.quiet, cli.verbose, cli.no_tty, cli.learn);
+    let context = CliContext::from(&cli);
 
     // Auto-check for updates (once per day, non-blocking)
-    if cyx::update::auto_check_update().is_err() {
+    let skip_update_check = cli.no_update_check
+        || cli.output != OutputFormat::Text
+        || cli.json_response
+        || cli.demo
+        || matches!(cli.command, Some(Commands::Version { .. }));
+    if cyx::update::auto_check_update(skip_update_check).is_err() {
         // Silently ignore auto-check errors
     }
 
-    // Handle commands
-    if let Err(e) = CommandHandler::handle(cli.query, cli.command, context) {
+    // Handle commands (with the opt-in diagnostic log running first)
+    let result = cyx::util::logging::init(cli.log_file.as_deref())
+        .and_then(|_| {
+            log::info!("cyx {} started", env!("CARGO_PKG_VERSION"));
+            cli.query_text()
+        })
+        .and_then(|query| CommandHandler::handle(query, cli.command, context));
+    if let Err(e) = result {
+        log::error!("{:#}", e);
         Display::error(&format!("Error: {}", e));
-        std::process::exit(1);
+        std::process::exit(cyx::error::exit_code(&e));
     }
 }
@@ -0,0 +1,170 @@
+use crate::cache::{CacheStorage, CachedQuery, SimilarityMetric};
+use anyhow::Result;
+use colored::Colorize;
+
+/// Nearest entries listed by `--explain-cache`
+const EXPLAIN_NEAREST: usize = 5;
+
+/// What `one_shot` does with a query's cache lookup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheDecision {
+    ExactHit,
+    /// Closest entry passed the threshold
+    SimilarHit(String),
+    Miss,
+}
+
+/// Diagnostics for `--explain-cache`: the values the cache lookup works with,
+/// gathered without touching access counts or hit/miss stats
+pub struct CacheExplanation {
+    pub normalized: String,
+    pub hash: String,
+    pub language: Option<String>,
+    pub exact: Option<CachedQuery>,
+    /// Closest entries with their scores, best first
+    pub nearest: Vec<(CachedQuery, f32)>,
+    pub threshold: f32,
+    pub metric: SimilarityMetric,
+}
+
+impl CacheExplanation {
+    pub fn gather(
+        storage: &CacheStorage,
+        normalized: &str,
+        hash: &str,
+        language: Option<&str>,
+        threshold: f32,
+    ) -> Result<Self> {
+        Ok(Self {
+            normalized: normalized.to_string(),
+            hash: hash.to_string(),
+            language: language.map(str::to_string),
+            exact: storage.peek_by_hash(hash)?,
+            nearest: storage.nearest(normalized, EXPLAIN_NEAREST)?,
+            threshold,
+            metric: storage.similarity_metric(),
+        })
+    }
+
+    pub fn decision(&self) -> CacheDecision {
+        if self.exact.is_some() {
+            return CacheDecision::ExactHit;
+        }
+
+        match self.nearest.first() {
+            Some((cached, score)) if self.metric.passes(*score, self.threshold) => {
+                CacheDecision::SimilarHit(cached.query_original.clone())
+            }
+            _ => CacheDecision::Miss,
+        }
+    }
+
+    /// Write the diagnostics to stderr, leaving stdout to the response
+    pub fn print(&self, query: &str) {
+        let label = |name: &str| format!("{:<11}", name).dimmed();
+
+        eprintln!("{}", "Cache lookup".bold().cyan());
+        eprintln!("  {} \"{}\"", label("Query"), query);
+        eprintln!("  {} \"{}\"", label("Normalized"), self.normalized);
+        eprintln!(
+            "  {} {} ({})",
+            label("Hash"),
+            self.hash,
+            self.language.as_deref().unwrap_or("English")
+        );
+        eprintln!(
+            "  {} {}",
+            label("Exact"),
+            match &self.exact {
+                Some(cached) => format!("yes, cached for \"{}\"", cached.query_original),
+                None => "no".to_string(),
+            }
+        );
+
+        let bound = if self.metric.is_distance() {
+            "<="
+        } else {
+            ">="
+        };
+        eprintln!(
+            "  {} {:?}, hit if {} {:.2}",
+            label("Metric"),
+            self.metric,
+            bound,
+            self.threshold
+        );
+
+        if self.nearest.is_empty() {
+            eprintln!(
+                "  {} none (cache is empty for this language)",
+                label("Nearest")
+            );
+        } else {
+            eprintln!("  {}", "Nearest".dimmed());
+            for (i, (cached, score)) in self.nearest.iter().enumerate() {
+                let mark = if self.metric.passes(*score, self.threshold) {
+                    "✓".green()
+                } else {
+                    "✗".dimmed()
+                };
+                eprintln!(
+                    "    {}. {} {:.4} \"{}\" {}",
+                    i + 1,
+                    mark,
+                    score,
+                    cached.query_original,
+                    cached.query_hash.dimmed()
+                );
+            }
+        }
+
+        let decision = match self.decision() {
+            CacheDecision::ExactHit => "exact hit, serving the cached answer".to_string(),
+            CacheDecision::SimilarHit(query) => {
+                format!("similar hit, serving the answer cached for \"{}\"", query)
+            }
+            CacheDecision::Miss => "miss, calling the API".to_string(),
+        };
+        eprintln!("  {} {}", label("Decision"), decision.bold());
+        eprintln!();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_decision_follows_threshold() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = CacheStorage::new(temp_dir.path()).unwrap();
+        storage
+            .store("nmap scan", "nmap stealth scan", "h1", "r1", "p", "m")
+            .unwrap();
+
+        let explain = |hash: &str, normalized: &str, threshold: f32| {
+            CacheExplanation::gather(&storage, normalized, hash, None, threshold)
+                .unwrap()
+                .decision()
+        };
+
+        assert_eq!(
+            explain("h1", "nmap stealth scan", 0.9),
+            CacheDecision::ExactHit
+        );
+        assert_eq!(
+            explain("other", "nmap stealth scan", 0.9),
+            CacheDecision::SimilarHit("nmap scan".to_string())
+        );
+        assert_eq!(
+            explain("other", "sqlmap dump database", 0.9),
+            CacheDecision::Miss
+        );
+
+        // Gathering diagnostics is read-only
+        let stats = storage.stats().unwrap();
+        assert_eq!((stats.hit_count, stats.miss_count), (0, 0));
+        assert_eq!(storage.peek_by_hash("h1").unwrap().unwrap().access_count, 1);
+    }
+}
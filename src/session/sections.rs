@@ -0,0 +1,150 @@
+use super::sources::strip_sources;
+
+/// Headers of the learn mode breakdown, in the order the learn prompt asks for them
+pub const LEARN_SECTIONS: [&str; 8] = [
+    "Tool",
+    "Flags",
+    "How it works",
+    "Advantages",
+    "Disadvantages",
+    "When to use",
+    "Alternatives",
+    "Example usage",
+];
+
+/// Resolve a user-supplied section name (case-insensitive, `-`/`_` for spaces)
+/// to its learn mode header
+pub fn learn_section(name: &str) -> Option<&'static str> {
+    let wanted = name.trim().replace(['-', '_'], " ");
+    LEARN_SECTIONS
+        .into_iter()
+        .find(|header| header.eq_ignore_ascii_case(&wanted))
+}
+
+/// Body of the `section` header in a learn mode response, up to the next header
+/// or the `[SOURCES]` list. Headers may carry markdown emphasis (`**Flags:**`,
+/// `## Flags:`); lines inside code blocks are never treated as headers.
+pub fn extract_section(response: &str, section: &str) -> Option<String> {
+    let mut body: Option<Vec<&str>> = None;
+    let mut in_code = false;
+
+    for line in strip_sources(response).lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+        }
+
+        if !in_code {
+            if let Some((header, rest)) = parse_header(line) {
+                if body.is_some() {
+                    break;
+                }
+                if header.eq_ignore_ascii_case(section) {
+                    body = Some(if rest.is_empty() {
+                        Vec::new()
+                    } else {
+                        vec![rest]
+                    });
+                }
+                continue;
+            }
+        }
+
+        if let Some(lines) = body.as_mut() {
+            lines.push(line);
+        }
+    }
+
+    let text = body?.join("\n");
+    let text = text.trim_matches('\n').trim_end();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Split an unindented `Header: rest` line whose header is a learn mode section
+fn parse_header(line: &str) -> Option<(&'static str, &str)> {
+    if line.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let line = line.trim_start_matches(['#', '*', '_']).trim_start();
+    LEARN_SECTIONS.into_iter().find_map(|header| {
+        let prefix = line.get(..header.len())?;
+        if !prefix.eq_ignore_ascii_case(header) {
+            return None;
+        }
+        let rest = line[header.len()..]
+            .trim_start_matches(['*', '_'])
+            .strip_prefix(':')?;
+        Some((header, rest.trim_start_matches(['*', '_']).trim()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::SystemPrompt;
+
+    const RESPONSE: &str = "```bash
+nmap -sS <target>
+```
+TCP SYN scan.
+
+Tool: nmap (Network Mapper)
+  Network scanner
+
+**Flags:**
+  -sS    TCP SYN Scan
+         - Requires root
+
+## How it works:
+  1. Sends TCP SYN packet
+```text
+Flags: not a header inside a code block
+```
+
+Example usage:
+  nmap -sS 192.168.1.100
+
+[SOURCES]
+- nmap docs: https://nmap.org/book/";
+
+    #[test]
+    fn test_extract_section_stops_at_next_header() {
+        assert_eq!(
+            extract_section(RESPONSE, "Flags").unwrap(),
+            "  -sS    TCP SYN Scan\n         - Requires root"
+        );
+        assert_eq!(
+            extract_section(RESPONSE, "Tool").unwrap(),
+            "nmap (Network Mapper)\n  Network scanner"
+        );
+        assert_eq!(
+            extract_section(RESPONSE, "How it works").unwrap(),
+            "  1. Sends TCP SYN packet\n```text\nFlags: not a header inside a code block\n```"
+        );
+        assert_eq!(
+            extract_section(RESPONSE, "Example usage").unwrap(),
+            "  nmap -sS 192.168.1.100"
+        );
+        assert_eq!(extract_section(RESPONSE, "Alternatives"), None);
+    }
+
+    #[test]
+    fn test_learn_section_names() {
+        assert_eq!(learn_section("flags"), Some("Flags"));
+        assert_eq!(learn_section("how-it-works"), Some("How it works"));
+        assert_eq!(learn_section("EXAMPLE_USAGE"), Some("Example usage"));
+        assert_eq!(learn_section("summary"), None);
+    }
+
+    #[test]
+    fn test_learn_prompt_uses_every_header() {
+        let prompt = SystemPrompt::default().learn(true).build();
+        for header in LEARN_SECTIONS {
+            assert!(
+                prompt.contains(&format!("\n{}:", header)),
+                "learn prompt has no '{}:' header",
+                header
+            );
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use url::Url;
+
+/// Marker the system prompts ask models to place before their source list
+pub const SOURCES_MARKER: &str = "[SOURCES]";
+
+/// Headings models put above a numbered reference list
+const REFERENCE_HEADINGS: &[&str] = &["references", "sources", "citations"];
+
+/// Extract validated `(description, url)` pairs from the `[SOURCES]` section of a response.
+/// Lines without a valid http(s) URL are skipped and duplicate URLs are dropped.
+pub fn extract_sources(response: &str) -> Vec<(String, String)> {
+    let Some(sources_pos) = response.find(SOURCES_MARKER) else {
+        return Vec::new();
+    };
+
+    let mut seen = HashSet::new();
+    response[sources_pos + SOURCES_MARKER.len()..]
+        .lines()
+        .filter_map(parse_source_line)
+        .filter(|(_, url)| seen.insert(url.clone()))
+        .collect()
+}
+
+/// Sources for a response: the provider's citations when it returned any, otherwise
+/// the `[SOURCES]` section. Citations reuse the description the model gave the same
+/// URL, falling back to the host name.
+pub fn response_sources(response: &str, citations: &[String]) -> Vec<(String, String)> {
+    let listed = extract_sources(response);
+    if citations.is_empty() {
+        return listed;
+    }
+
+    let mut seen = HashSet::new();
+    citations
+        .iter()
+        .filter_map(|citation| parse_source_line(citation))
+        .filter(|(_, url)| seen.insert(url.clone()))
+        .map(|(host, url)| {
+            let description = listed
+                .iter()
+                .find(|(_, listed_url)| *listed_url == url)
+                .map_or(host, |(description, _)| description.clone());
+            (description, url)
+        })
+        .collect()
+}
+
+/// Return the response body without the trailing `[SOURCES]` section
+pub fn strip_sources(response: &str) -> &str {
+    match response.find(SOURCES_MARKER) {
+        Some(sources_pos) => response[..sources_pos].trim_end(),
+        None => response.trim_end(),
+    }
+}
+
+/// Clean up the numbered citations the prompts forbid but models still emit: inline
+/// markers such as `[1]` or `[2, 3]` are removed outside code, and a trailing
+/// `[n] description: url` reference list is moved into the `[SOURCES]` section.
+/// Responses without numbered citations are returned unchanged.
+pub fn enforce_citation_format(response: &str) -> Cow<'_, str> {
+    let (body, listed) = match response.find(SOURCES_MARKER) {
+        Some(sources_pos) => (
+            &response[..sources_pos],
+            response[sources_pos + SOURCES_MARKER.len()..].trim(),
+        ),
+        None => (response, ""),
+    };
+
+    let mut lines: Vec<&str> = body.trim_end().lines().collect();
+    let mut references = Vec::new();
+    let mut list_start = lines.len();
+    for (i, line) in lines.iter().enumerate().rev() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match reference_line(line) {
+            Some(source) => {
+                references.push(source);
+                list_start = i;
+            }
+            None => break,
+        }
+    }
+    references.reverse();
+    lines.truncate(list_start);
+    if !references.is_empty() {
+        while lines.last().is_some_and(|line| line.trim().is_empty()) {
+            lines.pop();
+        }
+        if lines.last().is_some_and(|line| is_reference_heading(line)) {
+            lines.pop();
+        }
+    }
+
+    let text = lines.join("\n");
+    let cleaned = strip_inline_citations(&text);
+    if references.is_empty() && matches!(cleaned, Cow::Borrowed(_)) {
+        return Cow::Borrowed(response);
+    }
+
+    let mut sources: Vec<String> = listed.lines().map(String::from).collect();
+    sources.extend(
+        references
+            .into_iter()
+            .map(|(description, url)| format!("- {}: {}", description, url)),
+    );
+
+    let mut result = cleaned.trim_end().to_string();
+    if !sources.is_empty() {
+        result.push_str("\n\n");
+        result.push_str(SOURCES_MARKER);
+        result.push('\n');
+        result.push_str(&sources.join("\n"));
+    }
+    Cow::Owned(result)
+}
+
+/// Parse a reference list entry such as `[1] nmap docs: https://nmap.org/book/`
+fn reference_line(line: &str) -> Option<(String, String)> {
+    let (number, rest) = line.trim().strip_prefix('[')?.split_once(']')?;
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    parse_source_line(rest)
+}
+
+fn is_reference_heading(line: &str) -> bool {
+    let heading = line
+        .trim_matches(|c: char| c.is_whitespace() || "#*_:".contains(c))
+        .to_lowercase();
+    REFERENCE_HEADINGS.contains(&heading.as_str())
+}
+
+/// Remove inline `[n]` markers outside fenced code blocks and inline code
+fn strip_inline_citations(text: &str) -> Cow<'_, str> {
+    let mut result = String::with_capacity(text.len());
+    let mut in_fence = false;
+    let mut changed = false;
+
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            result.push('\n');
+        }
+        let trimmed = line.trim_start();
+        let is_fence = trimmed.starts_with("```") || trimmed.starts_with("~~~");
+        if is_fence {
+            in_fence = !in_fence;
+        }
+        if is_fence || in_fence {
+            result.push_str(line);
+            continue;
+        }
+        changed |= strip_line_citations(line, &mut result);
+    }
+
+    if changed {
+        Cow::Owned(result)
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Append `line` to `out` without its citation markers, returning whether any were
+/// removed. A marker must follow whitespace, punctuation or another marker, so
+/// indexing such as `argv[1]` is left alone.
+fn strip_line_citations(line: &str, out: &mut String) -> bool {
+    let mut changed = false;
+    let mut in_code = false;
+    let mut prev = None;
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if c == '`' {
+            in_code = !in_code;
+        }
+        let follows_text_end =
+            prev.is_some_and(|p: char| p.is_whitespace() || ".,;:!?)]\"'*".contains(p));
+        if c == '[' && !in_code && follows_text_end {
+            if let Some(len) = citation_len(&line[i..]) {
+                if prev.is_some_and(char::is_whitespace) {
+                    let trimmed = out.trim_end_matches([' ', '\t']).len();
+                    out.truncate(trimmed);
+                }
+                while chars.peek().is_some_and(|&(j, _)| j < i + len) {
+                    chars.next();
+                }
+                prev = Some(']');
+                changed = true;
+                continue;
+            }
+        }
+        out.push(c);
+        prev = Some(c);
+    }
+
+    changed
+}
+
+/// Length of a `[1]` or `[1, 2]` marker at the start of `text`; markdown links
+/// such as `[1](url)` don't count
+fn citation_len(text: &str) -> Option<usize> {
+    let end = text.find(']')?;
+    let inner = &text[1..end];
+    let numbers_only = !inner.is_empty()
+        && inner.split(',').all(|n| {
+            (1..=3).contains(&n.trim().len()) && n.trim().chars().all(|c| c.is_ascii_digit())
+        });
+    let is_link = text[end + 1..].starts_with('(');
+    (numbers_only && !is_link).then_some(end + 1)
+}
+
+/// Parse a single source line such as `- nmap docs: https://nmap.org/book/`.
+/// Also accepts markdown links and bare URLs. Returns `None` if the line has no valid URL.
+pub fn parse_source_line(line: &str) -> Option<(String, String)> {
+    let line = line.trim().trim_start_matches(['-', '*', '•']).trim_start();
+
+    let url_start = line.find("https://").or_else(|| line.find("http://"))?;
+    let candidate = line[url_start..]
+        .split_whitespace()
+        .next()
+        .unwrap_or_default();
+    let candidate = trim_url_suffix(candidate);
+
+    let url = Url::parse(candidate).ok()?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return None;
+    }
+
+    let description = line[..url_start]
+        .trim_matches(|c: char| c.is_whitespace() || "[]():-–—".contains(c))
+        .to_string();
+    let description = if description.is_empty() {
+        url.host_str().unwrap_or_default().to_string()
+    } else {
+        description
+    };
+
+    Some((description, candidate.to_string()))
+}
+
+/// Strip trailing punctuation that models commonly glue onto URLs
+fn trim_url_suffix(candidate: &str) -> &str {
+    let mut url = candidate.trim_end_matches(['.', ',', ';', '>', '"', '\'', ']']);
+    // Keep a closing paren only if the URL itself opened one (e.g. Wikipedia links)
+    while url.ends_with(')') && url.matches('(').count() < url.matches(')').count() {
+        url = url[..url.len() - 1].trim_end_matches(['.', ',', ';', ']']);
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_description_and_url() {
+        let parsed = parse_source_line("- nmap documentation: https://nmap.org/book/");
+        assert_eq!(
+            parsed,
+            Some((
+                "nmap documentation".to_string(),
+                "https://nmap.org/book/".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_markdown_link() {
+        let parsed = parse_source_line("- [HackTricks](https://book.hacktricks.xyz/).");
+        assert_eq!(
+            parsed,
+            Some((
+                "HackTricks".to_string(),
+                "https://book.hacktricks.xyz/".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_bare_url_uses_host() {
+        let parsed = parse_source_line("https://owasp.org/www-project-top-ten/");
+        assert_eq!(parsed.unwrap().0, "owasp.org");
+    }
+
+    #[test]
+    fn test_parse_rejects_non_urls() {
+        assert_eq!(parse_source_line("- nmap man page"), None);
+        assert_eq!(parse_source_line("- broken: https://"), None);
+    }
+
+    #[test]
+    fn test_extract_dedupes_and_skips_invalid() {
+        let response = "```bash\nnmap -sS <target>\n```\n\n[SOURCES]\n\
+                        - nmap docs: https://nmap.org/book/\n\
+                        - Not a link\n\
+                        - nmap book (again): https://nmap.org/book/\n\
+                        - RFC 793: https://www.ietf.org/rfc/rfc793.txt\n";
+
+        let sources = extract_sources(response);
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].1, "https://nmap.org/book/");
+        assert_eq!(sources[1].0, "RFC 793");
+    }
+
+    #[test]
+    fn test_citations_preferred_over_listed_sources() {
+        let response = "body\n\n[SOURCES]\n- nmap docs: https://nmap.org/book/\n\
+                        - made up: https://example.invalid/page\n";
+        let citations = vec![
+            "https://nmap.org/book/".to_string(),
+            "https://www.kali.org/tools/nmap/".to_string(),
+            "not a url".to_string(),
+        ];
+
+        assert_eq!(
+            response_sources(response, &citations),
+            vec![
+                (
+                    "nmap docs".to_string(),
+                    "https://nmap.org/book/".to_string()
+                ),
+                (
+                    "www.kali.org".to_string(),
+                    "https://www.kali.org/tools/nmap/".to_string()
+                ),
+            ]
+        );
+        assert_eq!(response_sources(response, &[]), extract_sources(response));
+    }
+
+    #[test]
+    fn test_strip_sources() {
+        let response = "body text\n\n[SOURCES]\n- a: https://a.example/";
+        assert_eq!(strip_sources(response), "body text");
+        assert_eq!(strip_sources("no sources\n"), "no sources");
+    }
+
+    #[test]
+    fn test_enforce_citation_format() {
+        let response = "Use a SYN scan [1]. It is stealthier than a connect scan.[2][3]\n\n\
+                        ```bash\n\
+                        nmap -sS $TARGETS[1] <target>\n\
+                        ```\n\
+                        Pass `arr[0]` or argv[1] as needed.\n\n\
+                        References:\n\
+                        [1] nmap docs: https://nmap.org/book/\n\
+                        [2]: https://www.ietf.org/rfc/rfc793.txt\n";
+
+        assert_eq!(
+            enforce_citation_format(response),
+            "Use a SYN scan. It is stealthier than a connect scan.\n\n\
+             ```bash\n\
+             nmap -sS $TARGETS[1] <target>\n\
+             ```\n\
+             Pass `arr[0]` or argv[1] as needed.\n\n\
+             [SOURCES]\n\
+             - nmap docs: https://nmap.org/book/\n\
+             - www.ietf.org: https://www.ietf.org/rfc/rfc793.txt"
+        );
+
+        let clean =
+            "Use a [link](https://nmap.org) and `[1]`.\n\n[SOURCES]\n- a: https://a.example/";
+        assert!(matches!(enforce_citation_format(clean), Cow::Borrowed(_)));
+    }
+}
@@ -1,10 +1,123 @@
+use super::terminal::terminal_width;
+use crate::config::UiConfig;
 use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::RwLock;
 use std::time::Duration;
 
+/// Widest box drawn when `ui.box_width` is unset and it follows the terminal
+pub const MAX_BOX_WIDTH: usize = 100;
+
+/// Box width when `ui.box_width` is unset and the terminal width is unknown
+pub const FALLBACK_BOX_WIDTH: usize = 80;
+
+/// Narrowest box that still fits a section title
+pub const MIN_BOX_WIDTH: usize = 20;
+
+/// How answer boxes are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoxStyle {
+    /// Width of the top and bottom borders
+    pub width: usize,
+    /// `+`, `|` and `-` instead of Unicode box-drawing characters
+    pub ascii: bool,
+    /// No box at all, just the text (`--no-tty`)
+    pub plain: bool,
+}
+
+impl BoxStyle {
+    pub const DEFAULT: Self = Self {
+        width: FALLBACK_BOX_WIDTH,
+        ascii: false,
+        plain: false,
+    };
+
+    /// Style for `ui.*` settings. `--no-tty` forces ASCII without a box. The box
+    /// spans the terminal (up to `MAX_BOX_WIDTH`) unless `ui.box_width` is set, and
+    /// is never wider than the terminal.
+    pub fn new(ui: &UiConfig, no_tty: bool) -> Self {
+        Self {
+            width: box_width(ui.box_width, terminal_width()),
+            ascii: ui.ascii || no_tty,
+            plain: no_tty,
+        }
+    }
+
+    /// Top-left corner, vertical edge, bottom-left corner and horizontal edge
+    fn chars(&self) -> (&'static str, &'static str, &'static str, &'static str) {
+        if self.ascii {
+            ("+", "|", "+", "-")
+        } else {
+            ("╭", "│", "╰", "─")
+        }
+    }
+
+    fn header(&self, title: &str) -> String {
+        let (top_left, _, _, horizontal) = self.chars();
+        format!(
+            "{}{} {} {}",
+            top_left,
+            horizontal.repeat(3),
+            title,
+            horizontal.repeat(self.width.saturating_sub(title.chars().count() + 6))
+        )
+    }
+
+    fn footer(&self) -> String {
+        let (_, _, bottom_left, horizontal) = self.chars();
+        format!("{}{}", bottom_left, horizontal.repeat(self.width))
+    }
+
+    /// Left edge of a line inside the box, with the space after it
+    fn prefix(&self) -> String {
+        if self.plain {
+            String::new()
+        } else {
+            format!("{} ", self.chars().1.cyan())
+        }
+    }
+}
+
+/// Box width for a configured width and the terminal's columns, each if known
+fn box_width(configured: Option<usize>, columns: Option<usize>) -> usize {
+    // One column spare, so a full-width border doesn't wrap
+    let available = columns.map(|columns| columns - 1);
+    let width = match (configured, available) {
+        (Some(width), Some(available)) => width.min(available),
+        (Some(width), None) => width,
+        (None, Some(available)) => available.min(MAX_BOX_WIDTH),
+        (None, None) => FALLBACK_BOX_WIDTH,
+    };
+    width.max(MIN_BOX_WIDTH)
+}
+
+/// Set once per run from the config by `Display::set_box_style`
+static BOX_STYLE: RwLock<BoxStyle> = RwLock::new(BoxStyle::DEFAULT);
+
 pub struct Display;
 
 impl Display {
+    /// Use `style` for every box drawn from now on
+    pub fn set_box_style(style: BoxStyle) {
+        if let Ok(mut current) = BOX_STYLE.write() {
+            *current = style;
+        }
+    }
+
+    fn box_style() -> BoxStyle {
+        BOX_STYLE.read().map_or(BoxStyle::DEFAULT, |style| *style)
+    }
+
+    /// Left edge of a line inside the answer box, for callers printing lines themselves
+    pub fn box_prefix() -> String {
+        Self::box_style().prefix()
+    }
+
+    /// A horizontal rule of `width` characters in the current box style
+    fn rule(width: usize) -> String {
+        Self::box_style().chars().3.repeat(width)
+    }
+
     /// Display a success message
     pub fn success(message: &str) {
         println!("{} {}", "[+]".green().bold(), message.green());
@@ -25,6 +138,17 @@ impl Display {
         println!("{} {}", "[!]".yellow().bold(), message.yellow());
     }
 
+    /// Display a warning on stderr, keeping it out of a command's own output
+    /// (answers, `--output json`/`ndjson`)
+    pub fn warning_stderr(message: &str) {
+        eprintln!("{} {}", "[!]".yellow().bold(), message.yellow());
+    }
+
+    /// Display an info message on stderr, like `warning_stderr`
+    pub fn info_stderr(message: &str) {
+        eprintln!("{} {}", "[*]".cyan(), message);
+    }
+
     /// Display source information with extracted links
     pub fn sources_with_links(
         provider_name: &str,
@@ -33,7 +157,7 @@ impl Display {
         links: &[String],
     ) {
         println!("\n{}", "[*] SOURCES".cyan().bold());
-        println!("{}", "───────────────────────────────────────".cyan());
+        println!("{}", Self::rule(39).cyan());
         println!(
             "{} {} ({})",
             "Provider:".dimmed(),
@@ -70,48 +194,62 @@ impl Display {
         );
         pb.set_message(message.to_string());
         pb.enable_steady_tick(Duration::from_millis(80));
+        super::terminal::track(&pb);
+        pb
+    }
+
+    /// Create a byte progress bar for downloads
+    pub fn create_download_bar(message: &str) -> ProgressBar {
+        let pb = ProgressBar::new(0);
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template(
+                    "{msg:.dimmed} [{bar:30.cyan/blue}] {bytes}/{total_bytes} {bytes_per_sec} {eta}",
+                )
+                .unwrap()
+                .progress_chars("=> "),
+        );
+        pb.set_message(message.to_string());
+        super::terminal::track(&pb);
         pb
     }
 
     /// Display content in a simple box (for streaming)
     pub fn stream_box_section(title: &str, content: &str) {
-        let width = 58;
-        println!();
-        println!(
-            "{}",
-            format!("╭─── {} {}", title, "─".repeat(width - title.len() - 6)).cyan()
-        );
+        Self::stream_box_header(title);
 
         // Detect code blocks and color them yellow
+        let prefix = Self::box_prefix();
         let mut in_code_block = false;
         for line in content.lines() {
             if line.trim().starts_with("```") {
                 in_code_block = !in_code_block;
-                println!("{} {}", "│".cyan(), line.dimmed());
+                println!("{}{}", prefix, line.dimmed());
             } else if in_code_block {
-                println!("{} {}", "│".cyan(), line.yellow());
+                println!("{}{}", prefix, line.yellow());
             } else {
-                println!("{} {}", "│".cyan(), line);
+                println!("{}{}", prefix, line);
             }
         }
 
-        println!("{}", format!("╰{}", "─".repeat(width)).cyan());
+        Self::stream_box_footer();
     }
 
     /// Print the box header for live streaming
     pub fn stream_box_header(title: &str) {
-        let width = 58;
+        let style = Self::box_style();
         println!();
-        println!(
-            "{}",
-            format!("╭─── {} {}", title, "─".repeat(width - title.len() - 6)).cyan()
-        );
+        if !style.plain {
+            println!("{}", style.header(title).cyan());
+        }
     }
 
     /// Print the box footer for live streaming
     pub fn stream_box_footer() {
-        let width = 58;
-        println!("{}", format!("╰{}", "─".repeat(width)).cyan());
+        let style = Self::box_style();
+        if !style.plain {
+            println!("{}", style.footer().cyan());
+        }
     }
 
     /// Print a line with smooth character-by-character animation
@@ -151,7 +289,7 @@ impl Display {
         thread::sleep(Duration::from_millis(50));
         println!();
 
-        print!("{}", "───────────────────────────────────────".cyan());
+        print!("{}", Self::rule(39).cyan());
         io::stdout().flush().unwrap();
         thread::sleep(Duration::from_millis(30));
         println!();
@@ -213,3 +351,51 @@ impl Display {
         println!();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_box() {
+        let style = BoxStyle {
+            width: 20,
+            ascii: true,
+            plain: false,
+        };
+        assert_eq!(style.header("RESPONSE"), "+--- RESPONSE ------");
+        assert_eq!(style.footer(), format!("+{}", "-".repeat(20)));
+
+        let unicode = BoxStyle {
+            ascii: false,
+            ..style
+        };
+        assert_eq!(unicode.header("RESPONSE").chars().count(), 20);
+        // A title longer than the box doesn't underflow the padding
+        assert!(unicode
+            .header("A VERY LONG SECTION TITLE")
+            .ends_with("TITLE "));
+    }
+
+    #[test]
+    fn test_no_tty_forces_plain_ascii() {
+        let ui = UiConfig {
+            box_width: Some(5),
+            ascii: false,
+        };
+        let style = BoxStyle::new(&ui, true);
+        assert!(style.ascii && style.plain);
+        assert_eq!(style.width, MIN_BOX_WIDTH);
+        assert_eq!(style.prefix(), "");
+    }
+
+    #[test]
+    fn test_box_width_follows_terminal() {
+        assert_eq!(box_width(None, Some(60)), 59);
+        assert_eq!(box_width(None, Some(200)), MAX_BOX_WIDTH);
+        assert_eq!(box_width(None, None), FALLBACK_BOX_WIDTH);
+        assert_eq!(box_width(Some(120), Some(100)), 99);
+        assert_eq!(box_width(Some(120), None), 120);
+        assert_eq!(box_width(None, Some(10)), MIN_BOX_WIDTH);
+    }
+}
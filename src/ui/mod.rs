@@ -1,3 +1,10 @@
 pub mod display;
+pub mod ndjson;
+pub mod table;
+pub mod terminal;
+pub mod tui;
 
-pub use display::Display;
+pub use display::{BoxStyle, Display};
+pub use table::TableFormatter;
+pub use terminal::{ProgressGuard, SlowResponseWarning};
+pub use tui::{BrowserExit, CacheBrowser};
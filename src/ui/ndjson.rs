@@ -0,0 +1,217 @@
+use crate::llm::{Message, Role};
+use crate::session::SOURCES_MARKER;
+use serde::Serialize;
+use std::io::Write;
+
+/// One line of `--output ndjson`
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Event<'a> {
+    /// Response text as it streams in (the `[SOURCES]` section is never included)
+    Chunk { text: &'a str },
+    /// Final event, after the last chunk
+    Done {
+        sources: Vec<Source<'a>>,
+        provider: &'a str,
+        model: &'a str,
+        cached: bool,
+        /// Estimated token usage; `null` for cached answers
+        usage: Option<Usage>,
+    },
+    /// The request `--dry-run` would send, instead of any chunks
+    #[serde(rename = "dry_run")]
+    DryRun {
+        provider: &'a str,
+        model: &'a str,
+        messages: Vec<PromptMessage<'a>>,
+    },
+}
+
+/// A message as `--dry-run` reports it: images are summarized, not base64-encoded
+#[derive(Debug, Serialize)]
+pub struct PromptMessage<'a> {
+    pub role: Role,
+    pub content: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImageSummary {
+    pub media_type: &'static str,
+    pub size_bytes: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Source<'a> {
+    pub description: &'a str,
+    pub url: &'a str,
+}
+
+/// Token counts estimated with the chars/4 heuristic
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Usage {
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+}
+
+impl<'a> Event<'a> {
+    pub fn done(
+        sources: &'a [(String, String)],
+        provider: &'a str,
+        model: &'a str,
+        usage: Option<Usage>,
+    ) -> Self {
+        Event::Done {
+            sources: sources
+                .iter()
+                .map(|(description, url)| Source { description, url })
+                .collect(),
+            provider,
+            model,
+            cached: usage.is_none(),
+            usage,
+        }
+    }
+
+    pub fn dry_run(provider: &'a str, model: &'a str, messages: &'a [Message]) -> Self {
+        Event::DryRun {
+            provider,
+            model,
+            messages: messages
+                .iter()
+                .map(|message| PromptMessage {
+                    role: message.role,
+                    content: &message.content,
+                    images: message
+                        .images
+                        .iter()
+                        .map(|image| ImageSummary {
+                            media_type: image.media_type,
+                            size_bytes: image.size(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Write an event as a single JSON line and flush it immediately
+pub fn emit(event: &Event) {
+    let mut stdout = std::io::stdout().lock();
+    if serde_json::to_writer(&mut stdout, event).is_ok() {
+        let _ = writeln!(stdout);
+    }
+    let _ = stdout.flush();
+}
+
+/// Emit a whole cached response as one chunk followed by `done`
+pub fn emit_cached(response: &str, sources: &[(String, String)], provider: &str, model: &str) {
+    emit(&Event::Chunk { text: response });
+    emit(&Event::done(sources, provider, model, None));
+}
+
+/// Holds back streamed text that could be the start of the `[SOURCES]` marker,
+/// so chunk events carry only the response body
+#[derive(Debug, Default)]
+pub struct ChunkFilter {
+    full: String,
+    emitted: usize,
+}
+
+impl ChunkFilter {
+    /// Add a streamed chunk and return the text that is now safe to emit
+    pub fn push(&mut self, chunk: &str) -> Option<String> {
+        self.full.push_str(chunk);
+
+        let end = match self.full.find(SOURCES_MARKER) {
+            Some(marker) => marker,
+            None => {
+                let mut end = self.full.len().saturating_sub(SOURCES_MARKER.len() - 1);
+                while !self.full.is_char_boundary(end) {
+                    end -= 1;
+                }
+                end
+            }
+        };
+        self.take_until(end)
+    }
+
+    /// Return whatever was held back once the stream has ended
+    pub fn finish(&mut self) -> Option<String> {
+        let end = self.full.find(SOURCES_MARKER).unwrap_or(self.full.len());
+        self.take_until(end)
+    }
+
+    fn take_until(&mut self, end: usize) -> Option<String> {
+        if end <= self.emitted {
+            return None;
+        }
+        let text = self.full[self.emitted..end].to_string();
+        self.emitted = end;
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_json_shape() {
+        let chunk = serde_json::to_string(&Event::Chunk { text: "nmap" }).unwrap();
+        assert_eq!(chunk, r#"{"type":"chunk","text":"nmap"}"#);
+
+        let sources = vec![("Nmap".to_string(), "https://nmap.org".to_string())];
+        let usage = Usage {
+            prompt_tokens: 10,
+            completion_tokens: 2,
+        };
+        let done =
+            serde_json::to_value(Event::done(&sources, "Groq", "llama", Some(usage))).unwrap();
+        assert_eq!(done["type"], "done");
+        assert_eq!(done["sources"][0]["url"], "https://nmap.org");
+        assert_eq!(done["cached"], false);
+        assert_eq!(done["usage"]["prompt_tokens"], 10);
+
+        let cached = serde_json::to_value(Event::done(&sources, "Groq", "llama", None)).unwrap();
+        assert_eq!(cached["cached"], true);
+        assert!(cached["usage"].is_null());
+
+        let messages = [Message::system("Be brief"), Message::user("nmap syn scan")];
+        let dry_run = serde_json::to_value(Event::dry_run("Groq", "llama", &messages)).unwrap();
+        assert_eq!(dry_run["type"], "dry_run");
+        assert_eq!(dry_run["messages"][1]["role"], "user");
+        assert_eq!(dry_run["messages"][1]["content"], "nmap syn scan");
+        assert!(dry_run["messages"][1].get("images").is_none());
+    }
+
+    #[test]
+    fn test_chunk_filter_drops_sources_section() {
+        let mut filter = ChunkFilter::default();
+        let mut emitted = String::new();
+        for chunk in [
+            "nmap -sS tar",
+            "get\n\n[SOU",
+            "RCES]\n- Nmap: https://nmap.org",
+        ] {
+            if let Some(text) = filter.push(chunk) {
+                emitted.push_str(&text);
+            }
+        }
+        if let Some(text) = filter.finish() {
+            emitted.push_str(&text);
+        }
+        assert_eq!(emitted, "nmap -sS target\n\n");
+    }
+
+    #[test]
+    fn test_chunk_filter_flushes_tail_without_marker() {
+        let mut filter = ChunkFilter::default();
+        assert_eq!(filter.push("ab").as_deref(), None);
+        assert_eq!(filter.push("cdefghijk").as_deref(), Some("abc"));
+        assert_eq!(filter.finish().as_deref(), Some("defghijk"));
+        assert_eq!(filter.finish(), None);
+    }
+}
@@ -0,0 +1,31 @@
+use comfy_table::{modifiers, presets, ContentArrangement, Table};
+
+/// Thin wrapper around comfy-table with the Cyx table style
+pub struct TableFormatter {
+    table: Table,
+}
+
+impl TableFormatter {
+    /// Create a table with the given column headers
+    pub fn new(headers: &[&str]) -> Self {
+        let mut table = Table::new();
+        table
+            .load_preset(presets::UTF8_FULL_CONDENSED)
+            .apply_modifier(modifiers::UTF8_ROUND_CORNERS)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(headers.to_vec());
+
+        Self { table }
+    }
+
+    /// Append a row of cells
+    pub fn add_row(&mut self, cells: Vec<String>) -> &mut Self {
+        self.table.add_row(cells);
+        self
+    }
+
+    /// Print the table to stdout
+    pub fn print(&self) {
+        println!("{}", self.table);
+    }
+}
@@ -0,0 +1,157 @@
+use indicatif::{ProgressBar, WeakProgressBar};
+use std::io::{IsTerminal, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Spinner message once a response is slow to start
+pub const SLOW_RESPONSE_MESSAGE: &str = "Still working... (model may be loading)";
+
+/// Progress bars currently on screen, so they can be cleared on Ctrl-C or panic
+static ACTIVE_BARS: Mutex<Vec<WeakProgressBar>> = Mutex::new(Vec::new());
+
+/// Register a progress bar to be cleared if the process is interrupted
+pub(crate) fn track(pb: &ProgressBar) {
+    if let Ok(mut bars) = ACTIVE_BARS.lock() {
+        bars.retain(|bar| bar.upgrade().is_some());
+        bars.push(pb.downgrade());
+    }
+}
+
+/// Columns of the terminal stdout is attached to; `None` when output is piped
+/// or the terminal reports no size
+pub fn terminal_width() -> Option<usize> {
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    match ratatui::crossterm::terminal::size() {
+        // Some pseudo-terminals report a size of 0
+        Ok((columns @ 1.., _)) => Some(usize::from(columns)),
+        _ => None,
+    }
+}
+
+/// Install a panic hook and a Ctrl-C handler that restore the terminal
+/// (clear spinners, end the partial line) before the process exits
+pub fn install_handlers() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore();
+        default_hook(info);
+    }));
+
+    // tokio is already a dependency, so wait for the signal on a small runtime thread
+    std::thread::spawn(|| {
+        let Ok(runtime) = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        else {
+            return;
+        };
+
+        if runtime.block_on(tokio::signal::ctrl_c()).is_ok() {
+            restore();
+            std::process::exit(130);
+        }
+    });
+}
+
+/// Clear any active progress bars, show the cursor and finish the current line
+fn restore() {
+    if let Ok(bars) = ACTIVE_BARS.lock() {
+        for bar in bars.iter().filter_map(WeakProgressBar::upgrade) {
+            if !bar.is_finished() {
+                bar.finish_and_clear();
+            }
+        }
+    }
+
+    // Only a terminal needs cleaning up: a newline piped into `--output json`
+    // or `ndjson` would corrupt the stream
+    let mut stdout = std::io::stdout();
+    if stdout.is_terminal() {
+        let _ = writeln!(stdout, "\x1b[?25h");
+        let _ = stdout.flush();
+    }
+}
+
+/// Clears its progress bar when dropped, so early returns and errors don't
+/// leave a spinner drawn on screen
+pub struct ProgressGuard(ProgressBar);
+
+impl ProgressGuard {
+    pub fn new(pb: ProgressBar) -> Self {
+        Self(pb)
+    }
+}
+
+impl Drop for ProgressGuard {
+    fn drop(&mut self) {
+        if !self.0.is_finished() {
+            self.0.finish_and_clear();
+        }
+    }
+}
+
+/// Switches a spinner to `SLOW_RESPONSE_MESSAGE` if it is still spinning after a
+/// delay, so a cold model load doesn't look like a hang. Drop it as soon as the
+/// response starts to cancel the warning.
+pub struct SlowResponseWarning {
+    /// Dropping it ends the wait early
+    _cancel: mpsc::Sender<()>,
+}
+
+impl SlowResponseWarning {
+    pub fn new(pb: ProgressBar, after: Duration) -> Self {
+        let (cancel, cancelled) = mpsc::channel();
+        std::thread::spawn(move || {
+            if cancelled.recv_timeout(after) == Err(RecvTimeoutError::Timeout) && !pb.is_finished()
+            {
+                pb.set_message(SLOW_RESPONSE_MESSAGE);
+            }
+        });
+        Self { _cancel: cancel }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_guard_finishes_bar() {
+        let pb = ProgressBar::hidden();
+        {
+            let _guard = ProgressGuard::new(pb.clone());
+            assert!(!pb.is_finished());
+        }
+        assert!(pb.is_finished());
+    }
+
+    #[test]
+    fn test_slow_response_warning() {
+        let pb = ProgressBar::hidden();
+        pb.set_message("Getting response...");
+        let _warning = SlowResponseWarning::new(pb.clone(), Duration::from_millis(10));
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(pb.message(), SLOW_RESPONSE_MESSAGE);
+
+        let pb = ProgressBar::hidden();
+        pb.set_message("Getting response...");
+        drop(SlowResponseWarning::new(
+            pb.clone(),
+            Duration::from_millis(10),
+        ));
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(pb.message(), "Getting response...");
+    }
+
+    #[test]
+    fn test_restore_clears_tracked_bars() {
+        let pb = ProgressBar::hidden();
+        track(&pb);
+
+        restore();
+        assert!(pb.is_finished());
+    }
+}
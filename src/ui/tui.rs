@@ -0,0 +1,292 @@
+use crate::cache::{CacheStorage, CachedQuery};
+use crate::session::strip_sources;
+use crate::util::base64_encode;
+use anyhow::Result;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    DefaultTerminal, Frame,
+};
+use std::io::Write;
+
+/// What the user asked for when leaving the browser
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BrowserExit {
+    Quit,
+    /// Re-run this query against the provider, bypassing the cache
+    Rerun(String),
+}
+
+/// Full-screen browser over cached answers: searchable query list on the left,
+/// the selected response on the right
+pub struct CacheBrowser<'a> {
+    storage: &'a CacheStorage,
+    entries: Vec<CachedQuery>,
+    filter: String,
+    searching: bool,
+    list_state: ListState,
+    scroll: u16,
+    status: Option<String>,
+}
+
+impl<'a> CacheBrowser<'a> {
+    pub fn new(storage: &'a CacheStorage) -> Result<Self> {
+        let entries = storage.list_all(None)?;
+        let mut list_state = ListState::default();
+        if !entries.is_empty() {
+            list_state.select(Some(0));
+        }
+
+        Ok(Self {
+            storage,
+            entries,
+            filter: String::new(),
+            searching: false,
+            list_state,
+            scroll: 0,
+            status: None,
+        })
+    }
+
+    /// Take over the terminal until the user quits or picks an entry to re-run
+    pub fn run(mut self) -> Result<BrowserExit> {
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<BrowserExit> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if let Some(exit) = self.handle_key(key)? {
+                    return Ok(exit);
+                }
+            }
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Result<Option<BrowserExit>> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Ok(Some(BrowserExit::Quit));
+        }
+
+        if self.searching {
+            match key.code {
+                KeyCode::Esc | KeyCode::Enter => self.searching = false,
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                    self.reset_selection();
+                }
+                KeyCode::Char(c) => {
+                    self.filter.push(c);
+                    self.reset_selection();
+                }
+                _ => {}
+            }
+            return Ok(None);
+        }
+
+        self.status = None;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(Some(BrowserExit::Quit)),
+            KeyCode::Char('/') => self.searching = true,
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::Char('d') => self.delete_selected()?,
+            KeyCode::Char('c') => self.copy_selected()?,
+            KeyCode::Char('r') => {
+                if let Some(entry) = self.selected() {
+                    return Ok(Some(BrowserExit::Rerun(entry.query_original.clone())));
+                }
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    /// Indices into `entries` whose query matches the current filter
+    fn visible(&self) -> Vec<usize> {
+        let needle = self.filter.to_lowercase();
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.query_original.to_lowercase().contains(&needle))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    fn selected(&self) -> Option<&CachedQuery> {
+        let visible = self.visible();
+        self.list_state
+            .selected()
+            .and_then(|position| visible.get(position))
+            .map(|&index| &self.entries[index])
+    }
+
+    fn reset_selection(&mut self) {
+        let has_entries = !self.visible().is_empty();
+        self.list_state.select(has_entries.then_some(0));
+        self.scroll = 0;
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let count = self.visible().len();
+        if count == 0 {
+            return;
+        }
+
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, count as isize - 1) as usize;
+        self.list_state.select(Some(next));
+        self.scroll = 0;
+    }
+
+    fn delete_selected(&mut self) -> Result<()> {
+        let Some(entry) = self.selected() else {
+            return Ok(());
+        };
+        let hash = entry.query_hash.clone();
+        let query = entry.query_original.clone();
+
+        if self.storage.remove_by_hash(&hash)? {
+            self.entries.retain(|entry| entry.query_hash != hash);
+            self.status = Some(format!("Deleted \"{}\"", query));
+        }
+
+        let count = self.visible().len();
+        let position = self.list_state.selected().unwrap_or(0);
+        self.list_state
+            .select((count > 0).then(|| position.min(count - 1)));
+        self.scroll = 0;
+        Ok(())
+    }
+
+    fn copy_selected(&mut self) -> Result<()> {
+        let Some(entry) = self.selected() else {
+            return Ok(());
+        };
+
+        match extract_command(&entry.response) {
+            Some(command) => {
+                // OSC 52 asks the terminal itself to set the clipboard, which
+                // also works over SSH and needs no display-server bindings
+                let mut stdout = std::io::stdout();
+                write!(
+                    stdout,
+                    "\x1b]52;c;{}\x07",
+                    base64_encode(command.as_bytes())
+                )?;
+                stdout.flush()?;
+                self.status = Some(format!("Copied: {}", command));
+            }
+            None => self.status = Some("No command found in this response".to_string()),
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(main);
+
+        let visible = self.visible();
+        let items: Vec<ListItem> = visible
+            .iter()
+            .map(|&index| ListItem::new(self.entries[index].query_original.as_str()))
+            .collect();
+
+        let title = if self.filter.is_empty() && !self.searching {
+            format!(" Cached queries ({}) ", visible.len())
+        } else {
+            format!(" /{} ({}) ", self.filter, visible.len())
+        };
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD | Modifier::REVERSED),
+            );
+        frame.render_stateful_widget(list, left, &mut self.list_state);
+
+        let (response_title, response) = match self.selected() {
+            Some(entry) => (
+                format!(" {} • {} ", entry.provider, entry.model),
+                strip_sources(&entry.response).to_string(),
+            ),
+            None => (
+                " Response ".to_string(),
+                "No cached queries match.".to_string(),
+            ),
+        };
+        let paragraph = Paragraph::new(response)
+            .block(Block::default().borders(Borders::ALL).title(response_title))
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0));
+        frame.render_widget(paragraph, right);
+
+        let help = match (&self.status, self.searching) {
+            (_, true) => "Type to filter • Enter/Esc: done".to_string(),
+            (Some(status), false) => status.clone(),
+            (None, false) => {
+                "↑/↓ select • / search • c copy command • r re-run • d delete • PgUp/PgDn scroll • q quit"
+                    .to_string()
+            }
+        };
+        frame.render_widget(
+            Paragraph::new(Line::from(help)).style(Style::default().fg(Color::DarkGray)),
+            footer,
+        );
+    }
+}
+
+/// The first line of the first fenced code block, falling back to the first non-empty line
+fn extract_command(response: &str) -> Option<String> {
+    let mut lines = response.lines().map(str::trim);
+
+    if lines.any(|line| line.starts_with("```")) {
+        if let Some(line) = lines.find(|line| !line.is_empty()) {
+            if !line.starts_with("```") {
+                return Some(line.to_string());
+            }
+        }
+    }
+
+    response
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_command_prefers_code_block() {
+        let response = "Use nmap for this:\n\n```bash\nnmap -sV 10.0.0.1\n```\n";
+        assert_eq!(
+            extract_command(response).as_deref(),
+            Some("nmap -sV 10.0.0.1")
+        );
+        assert_eq!(
+            extract_command("\nJust prose here\n").as_deref(),
+            Some("Just prose here")
+        );
+        assert_eq!(extract_command(""), None);
+    }
+}
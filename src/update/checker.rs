@@ -1,63 +1,121 @@
 use anyhow::Result;
 use chrono::{DateTime, Duration, Utc};
 use semver::Version;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+const CRATES_VERSIONS_URL: &str = "https://crates.io/api/v1/crates/cyx/versions";
+const GITHUB_LATEST_RELEASE_URL: &str = "https://api.github.com/repos/neur0map/cyx/releases/latest";
+
+/// Where to look for new versions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateSource {
+    /// crates.io (for `cargo install` users)
+    #[default]
+    Crates,
+    /// GitHub releases (for release binary users)
+    Github,
+}
+
+impl std::str::FromStr for UpdateSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "crates" => Ok(Self::Crates),
+            "github" => Ok(Self::Github),
+            _ => anyhow::bail!("Invalid update source. Options: crates, github"),
+        }
+    }
+}
 
 pub struct VersionChecker {
     current_version: Version,
+    source: UpdateSource,
     client: reqwest::blocking::Client,
 }
 
+#[derive(Debug, Deserialize)]
+struct VersionsResponse {
+    versions: Vec<CrateVersion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CrateVersion {
+    num: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct UpdateInfo {
     pub current_version: Version,
     pub latest_version: Version,
     pub needs_update: bool,
+    pub source: UpdateSource,
 }
 
 impl VersionChecker {
-    pub fn new() -> Result<Self> {
+    pub fn new(source: UpdateSource) -> Result<Self> {
         let current_version = Version::parse(env!("CARGO_PKG_VERSION"))?;
 
-        let client = reqwest::blocking::Client::builder()
-            .user_agent(format!("cyx/{}", current_version))
-            .timeout(std::time::Duration::from_secs(10))
-            .build()?;
+        let client = crate::http::build_client(Some(std::time::Duration::from_secs(10)), None)?;
 
         Ok(Self {
             current_version,
+            source,
             client,
         })
     }
 
-    /// Check for updates on crates.io
+    /// Check the configured source for a newer version
     pub fn check(&self) -> Result<UpdateInfo> {
-        #[derive(Deserialize)]
-        struct CratesResponse {
-            #[serde(rename = "crate")]
-            crate_info: CrateInfo,
-        }
+        let latest_version = match self.source {
+            UpdateSource::Crates => self.latest_from_crates()?,
+            UpdateSource::Github => self.latest_from_github()?,
+        };
 
-        #[derive(Deserialize)]
-        struct CrateInfo {
-            max_version: String,
-        }
+        Ok(UpdateInfo {
+            current_version: self.current_version.clone(),
+            latest_version: latest_version.clone(),
+            needs_update: latest_version > self.current_version,
+            source: self.source,
+        })
+    }
 
-        let url = "https://crates.io/api/v1/crates/cyx";
-        let response = self.client.get(url).send()?;
+    /// Latest crates.io version, ignoring yanked versions and (unless a
+    /// pre-release is installed) pre-releases
+    fn latest_from_crates(&self) -> Result<Version> {
+        let response = self.client.get(CRATES_VERSIONS_URL).send()?;
 
         if !response.status().is_success() {
             anyhow::bail!("Failed to check crates.io: HTTP {}", response.status());
         }
 
-        let crates_data: CratesResponse = response.json()?;
-        let latest_version = Version::parse(&crates_data.crate_info.max_version)?;
+        let versions: VersionsResponse = response.json()?;
+        let include_prerelease = !self.current_version.pre.is_empty();
+        Ok(latest_release(&versions.versions, include_prerelease)
+            .unwrap_or_else(|| self.current_version.clone()))
+    }
 
-        Ok(UpdateInfo {
-            current_version: self.current_version.clone(),
-            latest_version: latest_version.clone(),
-            needs_update: latest_version > self.current_version,
-        })
+    /// Latest published GitHub release (GitHub already excludes drafts and pre-releases)
+    fn latest_from_github(&self) -> Result<Version> {
+        let response = self.client.get(GITHUB_LATEST_RELEASE_URL).send()?;
+
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "Failed to check GitHub releases: HTTP {}",
+                response.status()
+            );
+        }
+
+        let release: GithubRelease = response.json()?;
+        parse_release_tag(&release.tag_name)
     }
 
     /// Check if enough time has passed since last check
@@ -73,6 +131,22 @@ impl VersionChecker {
     }
 }
 
+/// Highest published version that is not yanked (and not a pre-release unless allowed)
+fn latest_release(versions: &[CrateVersion], include_prerelease: bool) -> Option<Version> {
+    versions
+        .iter()
+        .filter(|v| !v.yanked)
+        .filter_map(|v| Version::parse(&v.num).ok())
+        .filter(|v| include_prerelease || v.pre.is_empty())
+        .max()
+}
+
+/// Parse a release tag such as `v0.3.1` as a semver version
+fn parse_release_tag(tag: &str) -> Result<Version> {
+    let version = tag.trim().trim_start_matches('v');
+    Version::parse(version).map_err(|e| anyhow::anyhow!("Invalid release tag {}: {}", tag, e))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -90,4 +164,46 @@ mod tests {
         let old = Utc::now() - Duration::hours(25);
         assert!(VersionChecker::should_check_now(Some(old)));
     }
+
+    #[test]
+    fn test_latest_release_skips_yanked_and_prerelease() {
+        let response: VersionsResponse = serde_json::from_str(
+            r#"{"versions": [
+                {"num": "0.5.0", "yanked": true},
+                {"num": "0.4.1-beta.1", "yanked": false},
+                {"num": "0.4.0", "yanked": false},
+                {"num": "0.3.1", "yanked": false}
+            ]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            latest_release(&response.versions, false),
+            Some(Version::parse("0.4.0").unwrap())
+        );
+        assert_eq!(
+            latest_release(&response.versions, true),
+            Some(Version::parse("0.4.1-beta.1").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_latest_release_all_yanked() {
+        let response: VersionsResponse =
+            serde_json::from_str(r#"{"versions": [{"num": "0.5.0", "yanked": true}]}"#).unwrap();
+        assert_eq!(latest_release(&response.versions, false), None);
+    }
+
+    #[test]
+    fn test_parse_release_tag() {
+        assert_eq!(
+            parse_release_tag("v0.3.2").unwrap(),
+            Version::parse("0.3.2").unwrap()
+        );
+        assert_eq!(
+            parse_release_tag("1.0.0").unwrap(),
+            Version::parse("1.0.0").unwrap()
+        );
+        assert!(parse_release_tag("latest").is_err());
+    }
 }
@@ -39,7 +39,7 @@ impl UpdateMetadata {
         }
 
         let content = serde_json::to_string_pretty(self)?;
-        std::fs::write(&metadata_path, content)?;
+        crate::util::write_atomic(&metadata_path, content, None)?;
         Ok(())
     }
 
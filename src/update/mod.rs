@@ -1,25 +1,68 @@
 pub mod checker;
 pub mod metadata;
 
-pub use checker::VersionChecker;
+pub use checker::{UpdateInfo, UpdateSource, VersionChecker};
 pub use metadata::UpdateMetadata;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use colored::Colorize;
+use std::path::{Path, PathBuf};
+
+const CARGO_INSTALL_COMMAND: &str = "cargo install cyx --force";
+const RELEASES_URL: &str = "https://github.com/neur0map/cyx/releases/latest";
+
+/// Environment variable that disables the startup update check when set (e.g. to 1)
+pub const NO_UPDATE_CHECK_ENV: &str = "CYX_NO_UPDATE_CHECK";
+
+/// How the running binary was installed, which decides how it can be updated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallMethod {
+    /// Installed with `cargo install` (binary lives in `$CARGO_HOME/bin`)
+    Cargo,
+    /// Release binary, package manager or local build
+    Other,
+}
+
+impl InstallMethod {
+    pub fn detect() -> Self {
+        match (std::env::current_exe(), cargo_home()) {
+            (Ok(exe), Some(cargo_home)) => Self::from_paths(&exe, &cargo_home),
+            _ => Self::Other,
+        }
+    }
+
+    fn from_paths(exe: &Path, cargo_home: &Path) -> Self {
+        let exe = exe.canonicalize().unwrap_or_else(|_| exe.to_path_buf());
+        let bin_dir = cargo_home.join("bin");
+        let bin_dir = bin_dir.canonicalize().unwrap_or(bin_dir);
+
+        if exe.parent() == Some(bin_dir.as_path()) {
+            Self::Cargo
+        } else {
+            Self::Other
+        }
+    }
+}
+
+fn cargo_home() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".cargo")))
+}
 
 pub struct UpdateManager {
     checker: VersionChecker,
 }
 
 impl UpdateManager {
-    pub fn new() -> Result<Self> {
-        let checker = VersionChecker::new()?;
+    pub fn new(source: UpdateSource) -> Result<Self> {
+        let checker = VersionChecker::new(source)?;
 
         Ok(Self { checker })
     }
 
     /// Check and display update availability
-    pub fn check_and_display(&self) -> Result<()> {
+    pub fn check_and_display(&self) -> Result<UpdateInfo> {
         println!();
         println!("{}", "Checking for updates...".cyan());
 
@@ -38,9 +81,18 @@ impl UpdateManager {
                 format!("v{}", update_info.latest_version).green()
             );
             println!();
-            println!("{}", "To update, run:".cyan());
-            println!();
-            println!("  {}", "cargo install cyx --force".green().bold());
+            match (update_info.source, InstallMethod::detect()) {
+                (UpdateSource::Crates, InstallMethod::Cargo) => {
+                    println!("{}", "To update, run:".cyan());
+                    println!();
+                    println!("  {}", CARGO_INSTALL_COMMAND.green().bold());
+                }
+                _ => {
+                    println!("{}", "To update, download the latest release:".cyan());
+                    println!();
+                    println!("  {}", RELEASES_URL.green().bold());
+                }
+            }
             println!();
         } else {
             println!(
@@ -54,12 +106,37 @@ impl UpdateManager {
             println!();
         }
 
+        Ok(update_info)
+    }
+
+    /// Reinstall the latest version with cargo, streaming its output
+    pub fn self_update(&self) -> Result<()> {
+        let status = std::process::Command::new("cargo")
+            .args(["install", "cyx", "--force"])
+            .status()
+            .context("Failed to run cargo. Is it on your PATH?")?;
+
+        if !status.success() {
+            anyhow::bail!("`{}` failed ({})", CARGO_INSTALL_COMMAND, status);
+        }
+
+        UpdateMetadata::load()?.update_last_update()?;
         Ok(())
     }
 }
 
-/// Auto-check for updates (called on startup)
-pub fn auto_check_update() -> Result<()> {
+/// Auto-check for updates (called on startup). `skip` comes from `--no-update-check`;
+/// the check is also skipped via `CYX_NO_UPDATE_CHECK` or `update.auto_check = false`.
+pub fn auto_check_update(skip: bool) -> Result<()> {
+    if skip || env_disables_check(std::env::var(NO_UPDATE_CHECK_ENV).ok().as_deref()) {
+        return Ok(());
+    }
+
+    let config = crate::config::ConfigManager::peek()?;
+    if !config.update.auto_check {
+        return Ok(());
+    }
+
     // Load metadata
     let mut metadata = UpdateMetadata::load()?;
 
@@ -69,7 +146,7 @@ pub fn auto_check_update() -> Result<()> {
     }
 
     // Perform background check
-    let checker = VersionChecker::new()?;
+    let checker = VersionChecker::new(config.update.source)?;
 
     // Update last check timestamp
     metadata.update_last_check()?;
@@ -96,3 +173,40 @@ pub fn auto_check_update() -> Result<()> {
 
     Ok(())
 }
+
+/// Whether the env var value disables the check: any value except empty, 0 or false
+fn env_disables_check(value: Option<&str>) -> bool {
+    match value.map(|v| v.trim().to_lowercase()) {
+        Some(v) => !(v.is_empty() || v == "0" || v == "false"),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_method_from_paths() {
+        let cargo_home = Path::new("/home/user/.cargo");
+
+        assert_eq!(
+            InstallMethod::from_paths(Path::new("/home/user/.cargo/bin/cyx"), cargo_home),
+            InstallMethod::Cargo
+        );
+        assert_eq!(
+            InstallMethod::from_paths(Path::new("/usr/local/bin/cyx"), cargo_home),
+            InstallMethod::Other
+        );
+    }
+
+    #[test]
+    fn test_env_disables_check() {
+        assert!(env_disables_check(Some("1")));
+        assert!(env_disables_check(Some("true")));
+        assert!(!env_disables_check(Some("0")));
+        assert!(!env_disables_check(Some("false")));
+        assert!(!env_disables_check(Some("")));
+        assert!(!env_disables_check(None));
+    }
+}
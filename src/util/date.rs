@@ -0,0 +1,90 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+
+/// Parse a date filter: `YYYY-MM-DD` in `now`'s time zone, or a relative age such as
+/// `12h`, `7d` or `2w` counted back from `now`. With `end_of_day`, a date means the
+/// last second of that day so it can be used as an inclusive upper bound.
+pub fn parse_date_bound<Tz: TimeZone>(
+    value: &str,
+    end_of_day: bool,
+    now: DateTime<Tz>,
+) -> Result<DateTime<Utc>> {
+    let value = value.trim();
+
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let start = date
+            .and_hms_opt(0, 0, 0)
+            .and_then(|midnight| now.timezone().from_local_datetime(&midnight).earliest())
+            .ok_or_else(|| anyhow::anyhow!("{} has no midnight in the local time zone", value))?
+            .with_timezone(&Utc);
+
+        return Ok(if end_of_day {
+            start + Duration::days(1) - Duration::seconds(1)
+        } else {
+            start
+        });
+    }
+
+    let split = value.len().saturating_sub(1);
+    let (amount, unit) = (&value[..split], &value[split..]);
+    let amount: i64 = amount.parse().map_err(|_| {
+        anyhow::anyhow!(
+            "'{}' is not a date (YYYY-MM-DD) or relative age (e.g. 12h, 7d, 2w)",
+            value
+        )
+    })?;
+
+    let age = match unit {
+        "h" => Duration::hours(amount),
+        "d" => Duration::days(amount),
+        "w" => Duration::weeks(amount),
+        _ => anyhow::bail!("Unknown unit in '{}' (use h, d or w)", value),
+    };
+
+    Ok(now.with_timezone(&Utc) - age)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 3, 10, 15, 30, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse_absolute_date() {
+        assert_eq!(
+            parse_date_bound("2025-03-01", false, now()).unwrap(),
+            Utc.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_date_bound("2025-03-01", true, now()).unwrap(),
+            Utc.with_ymd_and_hms(2025, 3, 1, 23, 59, 59).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_relative_age() {
+        assert_eq!(
+            parse_date_bound("7d", false, now()).unwrap(),
+            Utc.with_ymd_and_hms(2025, 3, 3, 15, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse_date_bound("12h", true, now()).unwrap(),
+            Utc.with_ymd_and_hms(2025, 3, 10, 3, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse_date_bound("2w", false, now()).unwrap(),
+            Utc.with_ymd_and_hms(2025, 2, 24, 15, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_garbage() {
+        assert!(parse_date_bound("yesterday", false, now()).is_err());
+        assert!(parse_date_bound("7y", false, now()).is_err());
+        assert!(parse_date_bound("2025-13-01", false, now()).is_err());
+        assert!(parse_date_bound("", false, now()).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::{Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// Write `contents` to `path` atomically: the data goes to a temp file in the same
+/// directory, is flushed to disk, then renamed over the target. A crash or full disk
+/// leaves either the old file or the new one, never a truncated mix.
+/// `mode` sets the Unix permissions of the new file (e.g. 0o600 for secrets).
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>, mode: Option<u32>) -> Result<()> {
+    let temp_path = temp_path_for(path)?;
+
+    let result = write_temp(&temp_path, contents.as_ref(), mode).and_then(|_| {
+        fs::rename(&temp_path, path)
+            .with_context(|| format!("Failed to replace {}", path.display()))
+    });
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+/// Read a UTF-8 text file, naming the file in the error when it is missing or binary
+pub fn read_text_file(path: &Path) -> Result<String> {
+    let bytes =
+        fs::read(path).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    String::from_utf8(bytes).map_err(|e| {
+        anyhow::anyhow!(
+            "{} is not valid UTF-8 text ({})",
+            path.display(),
+            e.utf8_error()
+        )
+    })
+}
+
+fn temp_path_for(path: &Path) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid file path: {}", path.display()))?;
+
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".tmp{}", std::process::id()));
+
+    Ok(path.with_file_name(temp_name))
+}
+
+fn write_temp(temp_path: &Path, contents: &[u8], mode: Option<u32>) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    if let Some(mode) = mode {
+        options.mode(mode);
+    }
+
+    let mut file = options
+        .open(temp_path)
+        .with_context(|| format!("Failed to create {}", temp_path.display()))?;
+
+    // The mode passed to open only applies to new files and is masked by the umask
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+    }
+
+    file.write_all(contents)
+        .with_context(|| format!("Failed to write {}", temp_path.display()))?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_atomic_replaces_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+
+        write_atomic(&path, "old", None).unwrap();
+        write_atomic(&path, "new", None).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        // No temp files left behind
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_write_atomic_sets_mode() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+
+        write_atomic(&path, "secret", Some(0o600)).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_write_atomic_keeps_original_on_failure() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        fs::write(&path, "original").unwrap();
+
+        // A directory where the temp file goes makes the write itself fail (even
+        // as root, which ignores read-only permissions)
+        let blocker = temp_path_for(&path).unwrap();
+        fs::create_dir(&blocker).unwrap();
+
+        assert!(write_atomic(&path, "new", None).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+
+        fs::remove_dir(&blocker).unwrap();
+        write_atomic(&path, "new", None).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_read_text_file_rejects_binary_and_missing() {
+        let temp_dir = TempDir::new().unwrap();
+        let text = temp_dir.path().join("query.txt");
+        fs::write(&text, "line one\nline two\n").unwrap();
+        assert_eq!(read_text_file(&text).unwrap(), "line one\nline two\n");
+
+        let binary = temp_dir.path().join("dump.bin");
+        fs::write(&binary, [0x66, 0x6f, 0xff, 0xfe]).unwrap();
+        let err = read_text_file(&binary).unwrap_err().to_string();
+        assert!(err.contains("dump.bin is not valid UTF-8"), "{}", err);
+
+        let missing = temp_dir.path().join("missing.txt");
+        let err = read_text_file(&missing).unwrap_err().to_string();
+        assert!(err.contains("Failed to read"), "{}", err);
+    }
+}
@@ -0,0 +1,8 @@
+pub mod date;
+pub mod encoding;
+pub mod fs;
+pub mod logging;
+
+pub use date::parse_date_bound;
+pub use encoding::base64_encode;
+pub use fs::{read_text_file, write_atomic};